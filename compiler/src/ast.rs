@@ -1,18 +1,50 @@
+use std::{cell::Cell, rc::Rc};
+
 use crate::scanner::Token;
 
+/// where the variable an [`Ident`] refers to lives, as worked out by
+/// the resolver
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Binding {
+    /// looked up by name at runtime
+    #[default]
+    Global,
+    /// `slot`th variable of the scope `depth` levels up
+    Local { depth: usize, slot: usize },
+}
+
 #[derive(Debug, Clone)]
 pub struct Ident {
     token: Token,
     name: String,
+    binding: Cell<Binding>,
+    /// the variable is declared as a decimal, as worked out by the
+    /// checker. ints stored through the identifier are converted
+    decimal: Cell<bool>,
 }
 
 impl Ident {
-    pub fn new(token: Token) -> anyhow::Result<Self, Token> {
+    pub fn new(token: Token) -> anyhow::Result<Self, Box<Token>> {
         if let Some(name) = token.token_type.as_identifier() {
             let name = name.clone();
-            Ok(Self { token, name })
+            Ok(Self {
+                token,
+                name,
+                binding: Cell::default(),
+                decimal: Cell::default(),
+            })
         } else {
-            Err(token)
+            Err(Box::new(token))
+        }
+    }
+
+    /// `this` or `super`, which are bound like variables
+    pub fn keyword(token: Token) -> Self {
+        Self {
+            name: token.lexeme.clone(),
+            token,
+            binding: Cell::default(),
+            decimal: Cell::default(),
         }
     }
 
@@ -20,30 +52,199 @@ impl Ident {
         &self.name
     }
 
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// the doc comment of the variable, function, class or method this
+    /// identifier declares
+    pub fn doc(&self) -> Option<&str> {
+        self.token.doc.as_deref()
+    }
+
     pub fn into_name(self) -> String {
-        self.token
-            .token_type
-            .try_into_identifier()
-            .expect("checked in constructor")
+        self.name
+    }
+
+    pub fn binding(&self) -> Binding {
+        self.binding.get()
+    }
+
+    pub fn set_binding(&self, binding: Binding) {
+        self.binding.set(binding);
+    }
+
+    pub fn is_decimal(&self) -> bool {
+        self.decimal.get()
+    }
+
+    pub fn set_decimal(&self, decimal: bool) {
+        self.decimal.set(decimal);
+    }
+}
+
+/// the type in an annotation such as `var x: int`
+#[derive(Debug, Clone)]
+pub struct TypeName(pub Token);
+
+impl TypeName {
+    pub fn name(&self) -> &str {
+        &self.0.lexeme
     }
 }
 
+/// statements between braces
+#[derive(Debug)]
+pub struct Block {
+    pub open: Token,
+    pub stmts: Vec<Statement>,
+    pub close: Token,
+    /// set on the block a `for` loop was lowered to. it opens with the
+    /// `for` keyword and closes with the brace of the loop's body
+    pub for_loop: Option<ForClauses>,
+}
+
+/// the clauses a `for (init; cond; incr) body` loop was written with.
+/// the parser lowers the loop to `{ init; while cond { body incr; } }`,
+/// with `true` for a missing condition, and the formatter writes it
+/// back as it was
+#[derive(Debug, Clone, Copy)]
+pub struct ForClauses {
+    pub init: bool,
+    pub cond: bool,
+    pub incr: bool,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: Ident,
+    pub params: Vec<Ident>,
+    /// the annotated type of each parameter
+    pub param_types: Vec<Option<TypeName>>,
+    pub returns: Option<TypeName>,
+    pub body: Block,
+}
+
+impl Function {
+    /// the parameters and return type as written: `(a: int, b): string`
+    pub fn signature(&self) -> String {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .zip(&self.param_types)
+            .map(|(param, ty)| annotated(param.name(), ty.as_ref()))
+            .collect();
+        annotated(&format!("({})", params.join(", ")), self.returns.as_ref())
+    }
+
+    /// whether the function is declared to return a decimal, so the
+    /// ints it returns are converted
+    pub fn returns_decimal(&self) -> bool {
+        self.returns
+            .as_ref()
+            .is_some_and(|ty| ty.name() == "decimal")
+    }
+}
+
+/// `name: type`, or just `name` without a type
+pub fn annotated(name: &str, ty: Option<&TypeName>) -> String {
+    match ty {
+        Some(ty) => format!("{name}: {}", ty.name()),
+        None => name.to_string(),
+    }
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: Ident,
+    pub superclass: Option<Ident>,
+    pub methods: Vec<Rc<Function>>,
+    /// brace closing the class body
+    pub close: Token,
+}
+
 #[derive(Debug)]
 pub enum Statement {
-    Block(Vec<Statement>),
+    Block(Block),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    Return(Token, Option<Expr>),
     If(Expr, Box<Statement>, Option<Box<Statement>>),
     While(Expr, Box<Statement>),
-    Var(Ident, Option<Expr>),
+    /// `for (var x in items) body`. holds the loop variable, the `in`
+    /// keyword, the collection and the body
+    ForIn(Ident, Token, Expr, Box<Statement>),
+    /// `var name: type = init;`
+    Var(Ident, Option<TypeName>, Option<Expr>),
     Print(Expr),
     Expr(Expr),
     Empty,
 }
 
+impl Statement {
+    /// the token the statement starts with, `None` for a lone `;`
+    pub fn first_token(&self) -> Option<&Token> {
+        let token = match self {
+            Statement::Block(block) => &block.open,
+            Statement::Function(fun) => fun.name.token(),
+            Statement::Class(class) => class.name.token(),
+            Statement::Return(keyword, _) => keyword,
+            Statement::If(cond, ..) | Statement::While(cond, _) => cond.first_token(),
+            Statement::ForIn(ident, ..) | Statement::Var(ident, ..) => ident.token(),
+            Statement::Print(e) | Statement::Expr(e) => e.first_token(),
+            Statement::Empty => return None,
+        };
+        Some(token)
+    }
+}
+
 #[derive(Debug)]
 pub enum Expr {
     Unary(Token, Box<Expr>),
     Binary(Box<Expr>, Token, Box<Expr>),
     Grouping(Box<Expr>),
     Literal(Token),
+    Variable(Ident),
     Assignment(Ident, Token, Box<Expr>),
+    /// callee, closing paren, arguments
+    Call(Box<Expr>, Token, Vec<Expr>),
+    /// object.property
+    Get(Box<Expr>, Ident),
+    /// object.property = value
+    Set(Box<Expr>, Ident, Box<Expr>),
+    This(Ident),
+    /// `super` keyword, method name
+    Super(Ident, Ident),
+    /// opening bracket, elements
+    List(Token, Vec<Expr>),
+    /// opening brace, key value pairs
+    Map(Token, Vec<(Expr, Expr)>),
+    /// collection[index], holds the closing bracket
+    Index(Box<Expr>, Token, Box<Expr>),
+    /// collection[index] = value
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+    /// `"a ${b} c"`. holds each piece of the string with the expression
+    /// following it, then the piece ending the string
+    Interpolation(Vec<(Token, Expr)>, Token),
+}
+
+impl Expr {
+    /// the leftmost token of the expression, used to point errors at it
+    pub fn first_token(&self) -> &Token {
+        match self {
+            Expr::Unary(tok, _) | Expr::Literal(tok) | Expr::List(tok, _) | Expr::Map(tok, _) => {
+                tok
+            }
+            Expr::Variable(ident) | Expr::This(ident) | Expr::Super(ident, _) => ident.token(),
+            Expr::Binary(l, _, _)
+            | Expr::Grouping(l)
+            | Expr::Call(l, _, _)
+            | Expr::Get(l, _)
+            | Expr::Set(l, _, _)
+            | Expr::Index(l, _, _)
+            | Expr::SetIndex(l, _, _, _) => l.first_token(),
+            Expr::Assignment(ident, _, _) => ident.token(),
+            Expr::Interpolation(parts, _) => &parts[0].0,
+        }
+    }
 }
@@ -0,0 +1,581 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    mem::discriminant,
+    rc::Rc,
+};
+
+use crate::{
+    ast::{self, Expr, Ident, Statement, TypeName},
+    diagnostic::Diagnostic,
+    scanner::{Location, Token, TokenType},
+    stack,
+};
+
+/// finds values that have the wrong type for how they are used before
+/// the script runs. types come from annotations and from the values
+/// variables are initialized with. anything else is only known at
+/// runtime and isn't checked
+pub fn check(stmts: &[Statement]) -> Result<(), Vec<Diagnostic>> {
+    // a variable without an annotation keeps the type of its initializer
+    // unless it's assigned something else. the assignment may come after
+    // uses of the variable, so the script is checked again until no more
+    // variables change
+    let mut widened = BTreeSet::new();
+    loop {
+        let mut checker = Checker {
+            scopes: vec![HashMap::new()],
+            returns: None,
+            widened,
+            changed: false,
+            errors: vec![],
+        };
+        checker.hoist(stmts);
+        for stmt in stmts {
+            stack::ensure(|| checker.statement(stmt));
+        }
+
+        if !checker.changed {
+            if !checker.errors.is_empty() {
+                return Err(checker.errors);
+            }
+            return Ok(());
+        }
+        widened = checker.widened;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    /// only known when the script runs
+    Any,
+    Nil,
+    Bool,
+    Int,
+    Decimal,
+    String,
+    List,
+    Map,
+    Instance,
+    /// a function, with its signature if it's known
+    Function(Option<Rc<Signature>>),
+    /// a class, with the signature of its initializer if it's known
+    Class(Option<Rc<Signature>>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Signature {
+    params: Vec<Type>,
+    returns: Type,
+}
+
+impl Type {
+    /// whether a value of type `found` can be used where one of this
+    /// type is expected. ints can be used as decimals, they are converted
+    /// when stored into a variable declared as one
+    fn accepts(&self, found: &Type) -> bool {
+        *self == Type::Any
+            || *found == Type::Any
+            || (*self == Type::Decimal && *found == Type::Int)
+            || discriminant(self) == discriminant(found)
+    }
+
+    fn is_number(&self) -> bool {
+        matches!(self, Type::Int | Type::Decimal)
+    }
+}
+
+/// named like [`RValue::type_name`](crate::value::RValue::type_name)
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Any => "any",
+            Type::Nil => "nil",
+            Type::Bool => "bool",
+            Type::Int => "int",
+            Type::Decimal => "decimal",
+            Type::String => "string",
+            Type::List => "list",
+            Type::Map => "map",
+            Type::Instance => "instance",
+            Type::Function(_) => "function",
+            Type::Class(_) => "class",
+        };
+        f.write_str(name)
+    }
+}
+
+struct Var {
+    ty: Type,
+    /// where the variable is declared, which identifies it between passes
+    declared: Location,
+    annotated: bool,
+    /// whether the variable is a global whose declaration hasn't been
+    /// checked yet, so only its annotation is known
+    hoisted: bool,
+}
+
+struct Checker {
+    /// variables of each scope, the globals first
+    scopes: Vec<HashMap<String, Var>>,
+    /// the type the function being checked returns, `None` at the top
+    /// level
+    returns: Option<Type>,
+    /// variables without annotation that are assigned a value of another
+    /// type than their initializer's. they can hold anything
+    widened: BTreeSet<Location>,
+    /// whether this pass widened a variable
+    changed: bool,
+    errors: Vec<Diagnostic>,
+}
+
+impl Checker {
+    /// declares the globals before anything is checked, so that the
+    /// functions using a global declared after them are checked against
+    /// its annotation
+    fn hoist(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            match stmt {
+                Statement::Var(ident, Some(ty), _) => {
+                    let ty = self.type_name(ty);
+                    self.declare(ident, ty, true);
+                }
+                Statement::Var(ident, None, _) => self.declare(ident, Type::Any, false),
+                Statement::Function(decl) => {
+                    let signature = self.signature(decl, Type::Any);
+                    self.declare(&decl.name, Type::Function(Some(signature)), false);
+                }
+                Statement::Class(decl) => self.declare(&decl.name, Type::Class(None), false),
+                _ => (),
+            }
+        }
+        for var in self.scopes[0].values_mut() {
+            var.hoisted = true;
+        }
+        // unknown types are reported once the declarations are checked
+        self.errors.clear();
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(block) => self.scoped(|this| {
+                for stmt in &block.stmts {
+                    this.statement(stmt);
+                }
+            }),
+            Statement::Function(decl) => {
+                let signature = self.signature(decl, Type::Any);
+                self.declare(&decl.name, Type::Function(Some(signature.clone())), false);
+                self.function(decl, &signature.params, signature.returns.clone());
+            }
+            Statement::Class(decl) => self.class_decl(decl),
+            Statement::Return(keyword, val) => {
+                let found = match val {
+                    Some(val) => self.expr(val),
+                    None => Type::Nil,
+                };
+                let Some(expected) = &self.returns else {
+                    return;
+                };
+                if !expected.accepts(&found) {
+                    let token = val.as_ref().map_or(keyword, Expr::first_token);
+                    let help = format!("the function is declared to return {expected}");
+                    self.errors
+                        .push(mismatched(token, expected, &found).with_help(help));
+                }
+            }
+            Statement::If(cond, when_true, when_false) => {
+                self.expr(cond);
+                self.statement(when_true);
+                if let Some(when_false) = when_false {
+                    self.statement(when_false);
+                }
+            }
+            Statement::While(cond, body) => {
+                self.expr(cond);
+                self.statement(body);
+            }
+            Statement::ForIn(ident, keyword, items, body) => {
+                let items = self.expr(items);
+                if !matches!(items, Type::Any | Type::List | Type::Map | Type::String) {
+                    self.errors.push(Diagnostic::at(
+                        keyword,
+                        format!("can't iterate over {items}"),
+                    ));
+                }
+                self.scoped(|this| {
+                    this.declare(ident, Type::Any, true);
+                    this.statement(body);
+                });
+            }
+            Statement::Var(ident, ty, init) => {
+                let found = match init {
+                    Some(init) => self.expr(init),
+                    None => Type::Nil,
+                };
+                let Some(ty) = ty else {
+                    self.declare(ident, found, false);
+                    return;
+                };
+                let expected = self.type_name(ty);
+                if !expected.accepts(&found) {
+                    let token = init.as_ref().map_or(ident.token(), Expr::first_token);
+                    let help = format!("'{}' is declared as {expected}", ident.name());
+                    self.errors
+                        .push(mismatched(token, &expected, &found).with_help(help));
+                }
+                self.declare(ident, expected, true);
+            }
+            Statement::Print(expr) | Statement::Expr(expr) => drop(self.expr(expr)),
+            Statement::Empty => (),
+        }
+    }
+
+    fn class_decl(&mut self, decl: &ast::Class) {
+        let inherited = match &decl.superclass {
+            None => Some(Rc::new(Signature {
+                params: vec![],
+                returns: Type::Instance,
+            })),
+            Some(superclass) => match self.variable(superclass) {
+                Type::Class(signature) => signature,
+                Type::Any => None,
+                ty => {
+                    self.errors.push(Diagnostic::at(
+                        superclass.token(),
+                        format!("superclass must be a class, not {ty}"),
+                    ));
+                    None
+                }
+            },
+        };
+
+        let methods: Vec<_> = decl
+            .methods
+            .iter()
+            .map(|method| {
+                let returns = match method.name.name().as_str() {
+                    "init" => Type::Instance,
+                    _ => Type::Any,
+                };
+                (method, self.signature(method, returns))
+            })
+            .collect();
+        let init = methods
+            .iter()
+            .find(|(method, _)| method.name.name() == "init")
+            .map(|(_, signature)| signature.clone())
+            .or(inherited);
+        self.declare(&decl.name, Type::Class(init), false);
+
+        for (method, signature) in methods {
+            // an initializer returns `this` whatever its `return` says
+            let returns = match method.name.name().as_str() {
+                "init" => Type::Any,
+                _ => signature.returns.clone(),
+            };
+            self.function(method, &signature.params, returns);
+        }
+    }
+
+    /// the types of a function's parameters and return value, `returns`
+    /// if the latter isn't annotated
+    fn signature(&mut self, decl: &ast::Function, returns: Type) -> Rc<Signature> {
+        let params = decl
+            .param_types
+            .iter()
+            .map(|ty| ty.as_ref().map_or(Type::Any, |ty| self.type_name(ty)))
+            .collect();
+        let returns = match &decl.returns {
+            Some(ty) => self.type_name(ty),
+            None => returns,
+        };
+        Rc::new(Signature { params, returns })
+    }
+
+    fn function(&mut self, decl: &ast::Function, params: &[Type], returns: Type) {
+        if !returns.accepts(&Type::Nil) && !always_returns(&decl.body.stmts) {
+            self.errors.push(
+                Diagnostic::at(
+                    &decl.body.close,
+                    format!("'{}' can end without returning a value", decl.name.name()),
+                )
+                .with_help(format!(
+                    "the function is declared to return {returns}, add a 'return' at the end"
+                )),
+            );
+        }
+        let enclosing = self.returns.replace(returns);
+        self.scoped(|this| {
+            for (param, ty) in decl.params.iter().zip(params) {
+                this.declare(param, ty.clone(), true);
+            }
+            for stmt in &decl.body.stmts {
+                this.statement(stmt);
+            }
+        });
+        self.returns = enclosing;
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        stack::ensure(|| match expr {
+            Expr::Literal(tok) => match tok.token_type {
+                TokenType::True | TokenType::False => Type::Bool,
+                TokenType::Nil => Type::Nil,
+                TokenType::String(_) => Type::String,
+                TokenType::Integer(_) => Type::Int,
+                TokenType::Decimal(_) => Type::Decimal,
+                _ => Type::Any,
+            },
+            Expr::Interpolation(parts, _) => {
+                for (_, expr) in parts {
+                    self.expr(expr);
+                }
+                Type::String
+            }
+            Expr::Grouping(expr) => self.expr(expr),
+            Expr::Unary(op, expr) => {
+                let ty = self.expr(expr);
+                match (&op.token_type, ty) {
+                    (TokenType::Bang, _) => Type::Bool,
+                    (_, ty @ (Type::Int | Type::Decimal | Type::Any)) => ty,
+                    (_, ty) => {
+                        self.errors
+                            .push(Diagnostic::at(op, format!("can't negate {ty}")));
+                        Type::Any
+                    }
+                }
+            }
+            Expr::Binary(l, op, r) => {
+                let lhs = self.expr(l);
+                let rhs = self.expr(r);
+                self.binary(op, lhs, rhs)
+            }
+            Expr::Variable(ident) => self.variable(ident),
+            Expr::Assignment(ident, _, val) => {
+                let ty = self.expr(val);
+                self.assign(ident, &ty, val);
+                ty
+            }
+            Expr::Call(callee, paren, args) => self.call(callee, paren, args),
+            Expr::Get(object, _) => {
+                self.expr(object);
+                Type::Any
+            }
+            Expr::Set(object, _, val) => {
+                self.expr(object);
+                self.expr(val)
+            }
+            Expr::This(_) => Type::Instance,
+            Expr::Super(..) => Type::Any,
+            Expr::List(_, elements) => {
+                for element in elements {
+                    self.expr(element);
+                }
+                Type::List
+            }
+            Expr::Map(_, entries) => {
+                for (key, val) in entries {
+                    self.expr(key);
+                    self.expr(val);
+                }
+                Type::Map
+            }
+            Expr::Index(object, _, index) => {
+                self.expr(object);
+                self.expr(index);
+                Type::Any
+            }
+            Expr::SetIndex(object, _, index, val) => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(val)
+            }
+        })
+    }
+
+    /// the type of a binary operation, following the operators on
+    /// [`RValue`](crate::value::RValue)
+    fn binary(&mut self, op: &Token, lhs: Type, rhs: Type) -> Type {
+        let operation = match op.token_type {
+            TokenType::Plus => "addition",
+            TokenType::Minus => "subtraction",
+            TokenType::Star => "multiplication",
+            TokenType::Slash => "division",
+            TokenType::Percent => "modulo",
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                let comparable = lhs == Type::Any
+                    || rhs == Type::Any
+                    || (lhs.is_number() && rhs.is_number())
+                    || (lhs == Type::String && rhs == Type::String);
+                if !comparable {
+                    self.errors.push(Diagnostic::at(
+                        op,
+                        format!("can't compare {lhs} with {rhs}"),
+                    ));
+                }
+                return Type::Bool;
+            }
+            _ => return Type::Bool,
+        };
+        match (&lhs, &rhs) {
+            (Type::Int, Type::Int) => Type::Int,
+            (l, r) if l.is_number() && r.is_number() => Type::Decimal,
+            (Type::String, Type::String) if op.token_type == TokenType::Plus => Type::String,
+            (Type::Any, _) | (_, Type::Any) => Type::Any,
+            _ => {
+                self.errors.push(Diagnostic::at(
+                    op,
+                    format!("invalid types for {operation}: {lhs} {} {rhs}", op.lexeme),
+                ));
+                Type::Any
+            }
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, paren: &Token, args: &[Expr]) -> Type {
+        let callee = self.expr(callee);
+        let found: Vec<_> = args.iter().map(|arg| self.expr(arg)).collect();
+        let signature = match callee {
+            Type::Function(Some(signature)) | Type::Class(Some(signature)) => signature,
+            Type::Function(None) | Type::Any => return Type::Any,
+            Type::Class(None) => return Type::Instance,
+            ty => {
+                self.errors.push(Diagnostic::at(
+                    paren,
+                    format!("can only call functions and classes, not {ty}"),
+                ));
+                return Type::Any;
+            }
+        };
+
+        if args.len() != signature.params.len() {
+            self.errors.push(Diagnostic::at(
+                paren,
+                format!(
+                    "expected {} arguments but got {}",
+                    signature.params.len(),
+                    args.len()
+                ),
+            ));
+        } else {
+            for ((expected, found), arg) in signature.params.iter().zip(&found).zip(args) {
+                if !expected.accepts(found) {
+                    self.errors
+                        .push(mismatched(arg.first_token(), expected, found));
+                }
+            }
+        }
+        signature.returns.clone()
+    }
+
+    fn variable(&self, ident: &Ident) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(ident.name()))
+            .map_or(Type::Any, |var| var.ty.clone())
+    }
+
+    /// checks a value of type `ty` can be stored in the variable, or
+    /// widens the variable if it has no annotation. a global assigned
+    /// before its declaration is checked is widened whatever it's
+    /// initialized with
+    fn assign(&mut self, ident: &Ident, ty: &Type, val: &Expr) {
+        let Some(var) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(ident.name()))
+        else {
+            return;
+        };
+        ident.set_decimal(var.annotated && var.ty == Type::Decimal);
+        if var.annotated {
+            if !var.ty.accepts(ty) {
+                let help = format!("'{}' is declared as {}", ident.name(), var.ty);
+                self.errors
+                    .push(mismatched(val.first_token(), &var.ty, ty).with_help(help));
+            }
+        } else if var.hoisted || (var.ty != *ty && var.ty != Type::Any) {
+            var.ty = Type::Any;
+            self.changed |= self.widened.insert(var.declared);
+        }
+    }
+
+    fn declare(&mut self, ident: &Ident, ty: Type, annotated: bool) {
+        ident.set_decimal(annotated && ty == Type::Decimal);
+        let declared = ident.token().location_start;
+        let ty = match !annotated && self.widened.contains(&declared) {
+            true => Type::Any,
+            false => ty,
+        };
+        let var = Var {
+            ty,
+            declared,
+            annotated,
+            hoisted: false,
+        };
+        self.scopes
+            .last_mut()
+            .expect("the globals are always in scope")
+            .insert(ident.name().clone(), var);
+    }
+
+    /// the type an annotation names
+    fn type_name(&mut self, ty: &TypeName) -> Type {
+        match ty.name() {
+            "any" => Type::Any,
+            "nil" => Type::Nil,
+            "bool" => Type::Bool,
+            "int" => Type::Int,
+            "decimal" => Type::Decimal,
+            "string" => Type::String,
+            "list" => Type::List,
+            "map" => Type::Map,
+            "instance" => Type::Instance,
+            "function" => Type::Function(None),
+            "class" => Type::Class(None),
+            name => {
+                self.errors.push(
+                    Diagnostic::at(&ty.0, format!("unknown type '{name}'")).with_help(
+                        "types are any, nil, bool, int, decimal, string, list, map, instance, function and class",
+                    ),
+                );
+                Type::Any
+            }
+        }
+    }
+
+    /// runs `f` in a new local scope
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+}
+
+/// whether running the statements always ends in a `return`. a loop
+/// whose condition is `true` can only be left by one
+fn always_returns(stmts: &[Statement]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Statement::Return(..) => true,
+        Statement::Block(block) => always_returns(&block.stmts),
+        Statement::If(_, when_true, Some(when_false)) => {
+            always_returns(std::slice::from_ref(when_true))
+                && always_returns(std::slice::from_ref(when_false))
+        }
+        Statement::While(Expr::Literal(tok), _) => tok.token_type == TokenType::True,
+        _ => false,
+    })
+}
+
+fn mismatched(token: &Token, expected: &Type, found: &Type) -> Diagnostic {
+    Diagnostic::at(
+        token,
+        format!("mismatched types: expected {expected}, found {found}"),
+    )
+}
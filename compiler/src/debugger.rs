@@ -0,0 +1,322 @@
+//! pausing scripts on the tree-walking interpreter to step through
+//! them and inspect their variables
+
+use std::{
+    cell::Cell,
+    collections::BTreeSet,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use rustyline::error::ReadlineError;
+
+use crate::{
+    ast::Statement,
+    interpreter::{Hook, Interpreter, RuntimeError},
+    parser,
+    repl::{Input, Terminal},
+    resolver,
+    scanner::{self, Token},
+    value::RValue,
+    RunError,
+};
+
+const PROMPT: &str = "(debug) ";
+
+const HELP: &str = "\
+step, s          run until the next statement
+next, n          run until the next statement in this function
+finish, f        run until the current function returns
+continue, c      run until a breakpoint
+break, b <line>  pause before running the statements on a line
+break, b         list the breakpoints
+delete, d <line> remove a breakpoint
+env, e           list the variables in scope, innermost first
+print, p <expr>  evaluate an expression in the current scope
+list, l          show the code around the current line
+help, h          show this message
+quit, q          stop the script
+
+an empty line repeats the previous command";
+
+/// where the script pauses next
+#[derive(Clone, Copy)]
+enum Mode {
+    /// at a breakpoint
+    Continue,
+    /// at any statement
+    Step,
+    /// at a statement no deeper in the call stack than the given depth
+    Next(usize),
+    /// at a statement less deep in the call stack than the given depth
+    Finish(usize),
+}
+
+/// pauses a script running on the tree-walking interpreter and reads
+/// commands, see [`HELP`]. starts paused before the first statement.
+/// what the commands print goes to one writer and errors to another
+pub struct Debugger {
+    file: String,
+    lines: Vec<String>,
+    input: Box<dyn Input>,
+    out: Box<dyn Write>,
+    err: Box<dyn Write>,
+    /// one based line numbers
+    breakpoints: BTreeSet<u64>,
+    mode: Mode,
+    /// line and call depth of the last statement, so that a breakpoint
+    /// pauses once on each visit to its line rather than on every
+    /// statement of it
+    last: Option<(u64, usize)>,
+    previous_command: String,
+    /// set when the user stops the script
+    quit: Rc<Cell<bool>>,
+}
+
+impl Debugger {
+    /// a debugger reading commands from the terminal, printing to stdout
+    /// and stderr
+    pub fn new(file: &str, source: &str, quit: Rc<Cell<bool>>) -> rustyline::Result<Self> {
+        Ok(Self::with_io(
+            file,
+            source,
+            quit,
+            Terminal::without_history()?,
+            io::stdout(),
+            io::stderr(),
+        ))
+    }
+
+    /// `quit` is set when the user stops the script, which then ends
+    /// with a runtime error
+    pub fn with_io(
+        file: &str,
+        source: &str,
+        quit: Rc<Cell<bool>>,
+        input: impl Input + 'static,
+        out: impl Write + 'static,
+        err: impl Write + 'static,
+    ) -> Self {
+        Self {
+            file: file.to_string(),
+            lines: source.lines().map(str::to_string).collect(),
+            input: Box::new(input),
+            out: Box::new(out),
+            err: Box::new(err),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last: None,
+            previous_command: String::new(),
+            quit,
+        }
+    }
+
+    /// shows where the script is paused and reads commands until one of
+    /// them resumes it
+    fn pause(&mut self, interpreter: &mut Interpreter, token: &Token) -> Result<(), RuntimeError> {
+        let quit = self
+            .commands(interpreter, token)
+            .map_err(|e| RuntimeError::new(token, format!("unable to print: {e}")))?;
+        if quit {
+            self.quit.set(true);
+            return Err(RuntimeError::new(token, "stopped in the debugger"));
+        }
+        Ok(())
+    }
+
+    /// runs commands until one of them resumes the script, returns
+    /// whether it was `quit`
+    fn commands(&mut self, interpreter: &mut Interpreter, token: &Token) -> io::Result<bool> {
+        let line = token.location_start.line() + 1;
+        writeln!(self.out, "{}:{line}", self.file)?;
+        self.show(line)?;
+
+        let depth = interpreter.call_depth();
+        loop {
+            let input = match self.input.readline(PROMPT) {
+                Ok(input) => input,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => "quit".to_string(),
+                Err(e) => {
+                    writeln!(self.err, "error: unable to read the command: {e}")?;
+                    "quit".to_string()
+                }
+            };
+            let input = match input.trim() {
+                "" => self.previous_command.clone(),
+                input => {
+                    let _ = self.input.add_history_entry(input);
+                    self.previous_command = input.to_string();
+                    input.to_string()
+                }
+            };
+            let (name, arg) = input
+                .split_once(char::is_whitespace)
+                .map_or((input.as_str(), ""), |(name, arg)| (name, arg.trim()));
+
+            match name {
+                "step" | "s" => self.mode = Mode::Step,
+                "next" | "n" => self.mode = Mode::Next(depth),
+                "finish" | "f" if depth == 0 => {
+                    writeln!(self.err, "error: not inside of a function")?;
+                    continue;
+                }
+                "finish" | "f" => self.mode = Mode::Finish(depth),
+                "continue" | "c" => self.mode = Mode::Continue,
+                "break" | "b" if arg.is_empty() => {
+                    for line in &self.breakpoints {
+                        writeln!(self.out, "{}:{line}", self.file)?;
+                    }
+                    continue;
+                }
+                "break" | "b" => {
+                    match self.line_number(arg) {
+                        Some(line) => drop(self.breakpoints.insert(line)),
+                        None => writeln!(self.err, "error: there is no line '{arg}'")?,
+                    }
+                    continue;
+                }
+                "delete" | "d" => {
+                    let removed = arg.parse().is_ok_and(|line| self.breakpoints.remove(&line));
+                    if !removed {
+                        writeln!(self.err, "error: there is no breakpoint on line '{arg}'")?;
+                    }
+                    continue;
+                }
+                "env" | "e" => {
+                    self.env(interpreter)?;
+                    continue;
+                }
+                "print" | "p" if arg.is_empty() => {
+                    writeln!(self.err, "error: usage is 'print <expr>'")?;
+                    continue;
+                }
+                "print" | "p" => {
+                    self.evaluate(interpreter, arg)?;
+                    continue;
+                }
+                "list" | "l" => {
+                    self.list(line)?;
+                    continue;
+                }
+                "help" | "h" => {
+                    writeln!(self.out, "{HELP}")?;
+                    continue;
+                }
+                "quit" | "q" => return Ok(true),
+                "" => continue,
+                _ => {
+                    writeln!(self.err, "error: unknown command '{name}'. see 'help'")?;
+                    continue;
+                }
+            }
+            return Ok(false);
+        }
+    }
+
+    fn line_number(&self, arg: &str) -> Option<u64> {
+        let line = arg.parse().ok()?;
+        (1..=self.lines.len() as u64)
+            .contains(&line)
+            .then_some(line)
+    }
+
+    /// prints a line of the script with its number
+    fn show(&mut self, line: u64) -> io::Result<()> {
+        let text = self.lines.get(line as usize - 1).map_or("", String::as_str);
+        let marker = match self.breakpoints.contains(&line) {
+            true => '*',
+            false => ' ',
+        };
+        writeln!(self.out, "{marker}{line:>4} | {text}")
+    }
+
+    /// prints the lines around `line`
+    fn list(&mut self, line: u64) -> io::Result<()> {
+        let first = line.saturating_sub(5).max(1);
+        let last = (line + 5).min(self.lines.len() as u64);
+        for n in first..=last {
+            self.show(n)?;
+        }
+        Ok(())
+    }
+
+    /// lists the local scopes, innermost first, then the globals except
+    /// for built-in functions
+    fn env(&mut self, interpreter: &Interpreter) -> io::Result<()> {
+        for (i, vars) in interpreter.locals().iter().enumerate() {
+            writeln!(self.out, "scope {i}:")?;
+            for (name, val) in vars {
+                writeln!(self.out, "  {name} = {val}")?;
+            }
+        }
+        let mut globals: Vec<_> = interpreter
+            .globals()
+            .filter(|(_, val)| !matches!(val, RValue::Native(_)))
+            .collect();
+        globals.sort_by_key(|(name, _)| *name);
+        writeln!(self.out, "globals:")?;
+        for (name, val) in globals {
+            writeln!(self.out, "  {name} = {val}")?;
+        }
+        Ok(())
+    }
+
+    /// evaluates an expression in the scope the script is paused in and
+    /// prints its value
+    fn evaluate(&mut self, interpreter: &mut Interpreter, source: &str) -> io::Result<()> {
+        let names: Vec<Vec<String>> = interpreter
+            .locals()
+            .into_iter()
+            .rev()
+            .map(|vars| vars.into_iter().map(|(name, _)| name).collect())
+            .collect();
+        let result = scanner::scan(source.to_string())
+            .map_err(RunError::Scan)
+            .and_then(|tokens| parser::parse_expression(tokens).map_err(RunError::Parse))
+            .and_then(|expr| {
+                resolver::resolve_in(&expr, &names).map_err(RunError::Resolve)?;
+                interpreter.evaluate_expr(&expr).map_err(RunError::Runtime)
+            });
+        match result {
+            Ok(val) => writeln!(self.out, "{val}"),
+            Err(e) => {
+                for diagnostic in e.diagnostics() {
+                    writeln!(self.err, "{}", diagnostic.render("<expr>", source))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Hook for Debugger {
+    fn statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        stmt: &Statement,
+    ) -> Result<(), RuntimeError> {
+        let Some(token) = stmt.first_token() else {
+            return Ok(());
+        };
+        let line = token.location_start.line() + 1;
+        let depth = interpreter.call_depth();
+        let revisit = self.last == Some((line, depth));
+        self.last = Some((line, depth));
+        // a block only runs the statements in it, which pause on their own
+        if matches!(stmt, Statement::Block(_)) {
+            return Ok(());
+        }
+
+        let pause = match self.mode {
+            Mode::Step => true,
+            Mode::Next(max) => depth <= max,
+            Mode::Finish(max) => depth < max,
+            Mode::Continue => false,
+        };
+        if !pause && (revisit || !self.breakpoints.contains(&line)) {
+            return Ok(());
+        }
+        self.pause(interpreter, token)
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt::Write;
+
+use crate::scanner::{Location, Token};
+
+/// an error report pointing at a span of the source. rendered in the
+/// style of rustc:
+///
+/// ```text
+/// error: variable 'x' does not exist
+///  --> script.lox:2:7
+///   |
+/// 2 | print x;
+///   |       ^
+///   = help: declare it with 'var x' first
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    message: String,
+    start: Location,
+    /// last character of the span, inclusive
+    end: Location,
+    help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, start: Location, end: Location) -> Self {
+        Self {
+            message: message.into(),
+            start,
+            end,
+            help: None,
+        }
+    }
+
+    /// an error spanning a token
+    pub fn at(token: &Token, message: impl Into<String>) -> Self {
+        Self::error(message, token.location_start, token.location_end)
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn start(&self) -> Location {
+        self.start
+    }
+
+    pub fn end(&self) -> Location {
+        self.end
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// renders the diagnostic against the source it was produced from
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let line_no = self.start.line() + 1;
+        let gutter = " ".repeat(line_no.to_string().len());
+        let line = source.lines().nth(self.start.line() as usize).unwrap_or("");
+
+        let start = self.start.column() as usize;
+        let line_len = line.chars().count();
+        // spans running past the end of the line are cut at the line end
+        let end = if self.end.line() == self.start.line() {
+            self.end.column() as usize
+        } else {
+            line_len.saturating_sub(1)
+        };
+        let width = end.saturating_sub(start) + 1;
+        let padding: String = line
+            .chars()
+            .take(start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "error: {}", self.message);
+        let _ = writeln!(out, "{gutter}--> {file}:{}", self.start);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{line_no} | {line}");
+        let _ = write!(out, "{gutter} | {padding}{}", "^".repeat(width));
+        if let Some(help) = &self.help {
+            let _ = write!(out, "\n{gutter} = help: {help}");
+        }
+        out
+    }
+}
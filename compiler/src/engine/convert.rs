@@ -0,0 +1,196 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc};
+
+use crate::value::{Native, RValue};
+
+/// a rust type that can be read out of a script value
+pub trait FromValue: Sized {
+    fn from_value(val: &RValue) -> Result<Self, String>;
+}
+
+/// a rust type that can be handed to scripts
+pub trait IntoValue {
+    fn into_value(self) -> RValue;
+}
+
+/// what a registered rust function may return. an `Err` becomes a
+/// runtime error at the call
+pub trait NativeResult {
+    fn into_result(self) -> Result<RValue, String>;
+}
+
+/// a rust function that can be registered with
+/// [`Engine::register`](super::Engine::register). implemented for
+/// closures of up to six [`FromValue`] arguments
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> Native;
+}
+
+fn mismatch(expected: &str, val: &RValue) -> String {
+    format!("expected {expected}, not {}", val.type_name())
+}
+
+impl FromValue for RValue {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        Ok(val.clone())
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        match val {
+            RValue::Boolean(b) => Ok(*b),
+            val => Err(mismatch("bool", val)),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        match val {
+            RValue::Int(i) => Ok(*i),
+            val => Err(mismatch("int", val)),
+        }
+    }
+}
+
+/// ints are promoted, like they are in arithmetic
+impl FromValue for f64 {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        match val {
+            RValue::Int(i) => Ok(*i as f64),
+            RValue::Decimal(d) => Ok(*d),
+            val => Err(mismatch("decimal", val)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        match val {
+            RValue::String(s) => Ok(s.clone()),
+            val => Err(mismatch("string", val)),
+        }
+    }
+}
+
+/// `nil` is `None`
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        match val {
+            RValue::Null => Ok(None),
+            val => T::from_value(val).map(Some),
+        }
+    }
+}
+
+/// copies the elements of a list
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(val: &RValue) -> Result<Self, String> {
+        match val {
+            RValue::List(list) => list.borrow().iter().map(T::from_value).collect(),
+            val => Err(mismatch("list", val)),
+        }
+    }
+}
+
+impl IntoValue for RValue {
+    fn into_value(self) -> RValue {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> RValue {
+        RValue::Null
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> RValue {
+        RValue::Boolean(self)
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> RValue {
+        RValue::Int(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> RValue {
+        RValue::Decimal(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> RValue {
+        RValue::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> RValue {
+        RValue::String(self.to_string())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> RValue {
+        self.map_or(RValue::Null, T::into_value)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> RValue {
+        let list = self.into_iter().map(T::into_value).collect();
+        RValue::List(Rc::new(RefCell::new(list)))
+    }
+}
+
+impl<T: IntoValue> NativeResult for T {
+    fn into_result(self) -> Result<RValue, String> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: Display> NativeResult for Result<T, E> {
+    fn into_result(self) -> Result<RValue, String> {
+        self.map(T::into_value).map_err(|e| e.to_string())
+    }
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident $ty:ident),*) => {
+        impl<F, R, $($ty,)*> IntoNative<($($ty,)*)> for F
+        where
+            F: Fn($($ty),*) -> R + 'static,
+            R: NativeResult,
+            $($ty: FromValue,)*
+        {
+            // the variables go unused for functions without arguments
+            #[allow(unused_mut, unused_variables)]
+            fn into_native(self, name: &str) -> Native {
+                let arity = <[&str]>::len(&[$(stringify!($arg)),*]);
+                let fun = name.to_string();
+                Native::new(name, arity..=arity, move |args| {
+                    let mut args = args.iter().enumerate();
+                    $(
+                        let (i, $arg) = args.next().expect("arity is checked before the call");
+                        let $arg = $ty::from_value($arg)
+                            .map_err(|e| format!("argument {} of {fun}: {e}", i + 1))?;
+                    )*
+                    self($($arg),*).into_result()
+                })
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(a A);
+impl_into_native!(a A, b B);
+impl_into_native!(a A, b B, c C);
+impl_into_native!(a A, b B, c C, d D);
+impl_into_native!(a A, b B, c C, d D, e E);
+impl_into_native!(a A, b B, c C, d D, e E, f F2);
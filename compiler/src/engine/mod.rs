@@ -0,0 +1,280 @@
+mod convert;
+
+use std::{
+    io::{BufRead, Write},
+    process::ExitCode,
+    rc::Rc,
+};
+
+pub use convert::{FromValue, IntoNative, IntoValue, NativeResult};
+
+use crate::{
+    ast::Statement,
+    checker,
+    diagnostic::Diagnostic,
+    interpreter::{Interpreter, RuntimeError},
+    optimizer, parser, resolver,
+    scanner::{self, ScanError},
+    stack,
+    value::{Native, RValue},
+    vm::{self, CompileError, Vm},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum BackendKind {
+    /// tree-walking interpreter
+    #[default]
+    Tree,
+    /// bytecode compiler and stack-based virtual machine
+    Vm,
+}
+
+/// runs scripts for a host program. globals persist between calls to
+/// [`Engine::eval`], so one script can build on what an earlier one
+/// defined. scripts can be run on any thread: when the thread's stack
+/// runs low, the engine continues on a piece of stack of its own
+///
+/// ```
+/// use compiler::Engine;
+///
+/// let mut engine = Engine::new();
+/// engine.register("double", |x: i64| x * 2);
+/// engine.eval("var port = double(4000);").unwrap();
+/// assert_eq!(engine.get::<i64>("port"), Ok(8000));
+/// ```
+pub struct Engine {
+    backend: Backend,
+}
+
+impl Engine {
+    /// an engine running scripts on the tree-walking interpreter
+    pub fn new() -> Self {
+        Self::with_backend(BackendKind::Tree)
+    }
+
+    pub fn with_backend(kind: BackendKind) -> Self {
+        let backend = match kind {
+            BackendKind::Tree => Backend::Tree(Interpreter::new()),
+            BackendKind::Vm => Backend::Vm(Vm::new()),
+        };
+        Self { backend }
+    }
+
+    /// scans, parses and runs a script
+    pub fn eval(&mut self, source: &str) -> Result<(), RunError> {
+        let stmts = parse(source)?;
+        self.execute(stmts)
+    }
+
+    /// resolves, checks, optimizes and runs parsed statements
+    pub fn execute(&mut self, stmts: Vec<Statement>) -> Result<(), RunError> {
+        stack::ensure(|| {
+            resolver::resolve(&stmts).map_err(RunError::Resolve)?;
+            checker::check(&stmts).map_err(RunError::Check)?;
+            let stmts = optimizer::optimize(stmts);
+            match &mut self.backend {
+                Backend::Tree(interpreter) => {
+                    for stmt in stmts {
+                        interpreter.evaluate(stmt).map_err(RunError::Runtime)?;
+                    }
+                }
+                Backend::Vm(vm) => {
+                    let script = vm::compile(&stmts).map_err(RunError::Compile)?;
+                    vm.execute(script).map_err(RunError::Runtime)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// the value of a global variable
+    pub fn global(&self, name: &str) -> Option<RValue> {
+        match &self.backend {
+            Backend::Tree(interpreter) => interpreter.global(name),
+            Backend::Vm(vm) => vm.global(name),
+        }
+    }
+
+    /// the value of a global variable converted to a rust type
+    pub fn get<T: FromValue>(&self, name: &str) -> Result<T, GetError> {
+        let val = self
+            .global(name)
+            .ok_or_else(|| GetError::Undefined(name.to_string()))?;
+        T::from_value(&val).map_err(|e| GetError::Mismatch(name.to_string(), e))
+    }
+
+    /// defines a global variable, replacing any previous one of the
+    /// same name
+    pub fn set(&mut self, name: &str, val: impl IntoValue) {
+        let val = val.into_value();
+        match &mut self.backend {
+            Backend::Tree(interpreter) => interpreter.define_global(name, val),
+            Backend::Vm(vm) => vm.define_global(name, val),
+        }
+    }
+
+    /// the global variables, sorted by name
+    pub fn globals(&self) -> Vec<(String, RValue)> {
+        let mut globals: Vec<_> = match &self.backend {
+            Backend::Tree(interpreter) => interpreter
+                .globals()
+                .map(|(name, val)| (name.to_string(), val.clone()))
+                .collect(),
+            Backend::Vm(vm) => vm
+                .globals()
+                .map(|(name, val)| (name.to_string(), val.clone()))
+                .collect(),
+        };
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals
+    }
+
+    /// makes a rust function callable from scripts. its arguments are
+    /// converted with [`FromValue`], so a call with the wrong types is a
+    /// runtime error in the script
+    pub fn register<Args>(&mut self, name: &str, fun: impl IntoNative<Args>) {
+        self.set(name, RValue::Native(Rc::new(fun.into_native(name))));
+    }
+
+    /// like [`Engine::register`], for functions that take a variable
+    /// number of arguments or want to see the values as they are
+    pub fn register_native(&mut self, native: Native) {
+        let name = native.name().to_string();
+        self.set(&name, RValue::Native(Rc::new(native)));
+    }
+
+    /// sends the output of `print` to `out` instead of stdout
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        match &mut self.backend {
+            Backend::Tree(interpreter) => interpreter.set_output(Box::new(out)),
+            Backend::Vm(vm) => vm.set_output(Box::new(out)),
+        }
+    }
+
+    /// makes `input` read lines from `input` instead of stdin
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        match &mut self.backend {
+            Backend::Tree(interpreter) => interpreter.set_input(Box::new(input)),
+            Backend::Vm(vm) => vm.set_input(Box::new(input)),
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// why [`Engine::get`] couldn't read a global variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GetError {
+    /// there is no global of that name
+    Undefined(String),
+    /// the variable holds a value that can't be converted. holds its
+    /// name and what went wrong
+    Mismatch(String, String),
+}
+
+impl std::fmt::Display for GetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetError::Undefined(name) => write!(f, "variable '{name}' does not exist"),
+            GetError::Mismatch(name, e) => write!(f, "variable '{name}': {e}"),
+        }
+    }
+}
+
+impl std::error::Error for GetError {}
+
+/// the state of whichever backend runs the scripts
+enum Backend {
+    Tree(Interpreter),
+    Vm(Vm),
+}
+
+/// scans and parses a script without running it
+pub fn parse(source: &str) -> Result<Vec<Statement>, RunError> {
+    let tokens = scanner::scan(source.to_string()).map_err(RunError::Scan)?;
+    Ok(parser::parse(tokens).map_err(RunError::Parse)?.collect())
+}
+
+/// the stage in which running a script failed. the command line tool
+/// exits with a code for each stage, counting up from 80 in the order
+/// the stages run. the codes below are left to what `sysexits.h` means
+/// by them, like 64 for a wrong command line
+#[derive(Debug)]
+pub enum RunError {
+    /// exit code 80
+    Scan(ScanError),
+    /// exit code 81
+    Parse(parser::Errors),
+    /// exit code 82
+    Resolve(Vec<resolver::Error>),
+    /// exit code 83
+    Check(Vec<Diagnostic>),
+    /// exit code 84
+    Compile(CompileError),
+    /// exit code 85, also the one of native executables
+    Runtime(RuntimeError),
+}
+
+/// exit code of [`RunError::Runtime`]
+pub(crate) const RUNTIME_EXIT_CODE: u8 = 85;
+
+impl RunError {
+    /// the exit code the command line tool reports the error with
+    pub fn exit_code(&self) -> ExitCode {
+        let code = match self {
+            RunError::Scan(_) => 80,
+            RunError::Parse(_) => 81,
+            RunError::Resolve(_) => 82,
+            RunError::Check(_) => 83,
+            RunError::Compile(_) => 84,
+            RunError::Runtime(_) => RUNTIME_EXIT_CODE,
+        };
+        ExitCode::from(code)
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            RunError::Scan(e) => vec![e.diagnostic()],
+            RunError::Parse(e) => e.errors().iter().map(parser::Error::diagnostic).collect(),
+            RunError::Resolve(e) => e.iter().map(resolver::Error::diagnostic).collect(),
+            RunError::Check(e) => e.clone(),
+            RunError::Compile(e) => vec![e.diagnostic()],
+            RunError::Runtime(e) => vec![e.diagnostic()],
+        }
+    }
+
+    /// prints the diagnostics to stderr
+    pub fn report(&self, file: &str, source: &str) {
+        for diagnostic in self.diagnostics() {
+            eprintln!("{}", diagnostic.render(file, source));
+        }
+    }
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Scan(e) => e.fmt(f),
+            RunError::Parse(e) => match e.errors() {
+                [first, ..] => first.fmt(f),
+                [] => write!(f, "parse error"),
+            },
+            RunError::Resolve(e) => match e.as_slice() {
+                [first, ..] => first.fmt(f),
+                [] => write!(f, "resolve error"),
+            },
+            RunError::Check(e) => match e.as_slice() {
+                [first, ..] => write!(f, "type error at {}: {}", first.start(), first.message()),
+                [] => write!(f, "type error"),
+            },
+            RunError::Compile(e) => e.fmt(f),
+            RunError::Runtime(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
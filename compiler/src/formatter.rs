@@ -0,0 +1,594 @@
+use std::{fmt, iter::Peekable, process::ExitCode, vec};
+
+use crate::{
+    ast::{annotated, Block, Expr, ForClauses, Function, Statement},
+    diagnostic::Diagnostic,
+    parser,
+    scanner::{self, Comment, Location, TokenType},
+    stack, RunError,
+};
+
+const INDENT: &str = "    ";
+
+/// lays a script out canonically: four spaces of indentation, braces on
+/// the line of the statement they belong to and parentheses only where
+/// precedence needs them. comments are kept and single blank lines
+/// between statements survive. a comment between the tokens of an
+/// expression or of the header of a function, class, `if` or loop has
+/// no place in the layout, the script isn't formatted then
+pub fn format(source: &str) -> Result<String, Error> {
+    let mut tokens = scanner::scan(source.to_string())
+        .map_err(RunError::Scan)
+        .map_err(Error::Invalid)?;
+    let comments = tokens.take_comments();
+    let stmts: Vec<_> = parser::parse(tokens)
+        .map_err(RunError::Parse)
+        .map_err(Error::Invalid)?
+        .collect();
+
+    let mut spans = vec![];
+    for stmt in &stmts {
+        stack::ensure(|| enclosed(stmt, &mut spans));
+    }
+    if let Some(comment) = comments.iter().find(|comment| {
+        spans
+            .iter()
+            .any(|(start, end)| *start < comment.location && comment.location < *end)
+    }) {
+        return Err(Error::Comment(comment.clone()));
+    }
+    Ok(write(&stmts, comments, source.lines().collect()))
+}
+
+/// why a script can't be formatted
+#[derive(Debug)]
+pub enum Error {
+    /// the script doesn't scan or parse
+    Invalid(RunError),
+    /// a comment the layout has no place for
+    Comment(Comment),
+}
+
+impl Error {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Error::Invalid(e) => e.diagnostics(),
+            Error::Comment(comment) => vec![Diagnostic::error(
+                self.to_string(),
+                comment.location,
+                comment.location,
+            )
+            .with_help(
+                "comments can't be kept inside an expression or a header, move it before or after the statement",
+            )],
+        }
+    }
+
+    /// the exit code the command line tool reports the error with
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Invalid(e) => e.exit_code(),
+            Error::Comment(_) => ExitCode::FAILURE,
+        }
+    }
+
+    /// prints the diagnostics to stderr
+    pub fn report(&self, file: &str, source: &str) {
+        for diagnostic in self.diagnostics() {
+            eprintln!("{}", diagnostic.render(file, source));
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Invalid(e) => e.fmt(f),
+            Error::Comment(_) => write!(f, "can't keep this comment in place"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// collects the spans between the first and last token of expressions
+/// and headers, which are written on one line without the comments in
+/// them
+fn enclosed(stmt: &Statement, spans: &mut Vec<(Location, Location)>) {
+    let mut header = |from: Location, body: &Statement| {
+        if let Statement::Block(block) = body {
+            spans.push((from, block.open.location_start));
+        }
+    };
+    match stmt {
+        Statement::Block(block) => match block.for_loop {
+            Some(_) => {
+                let body = loop_body(block);
+                header(block.open.location_start, body);
+                enclosed(body, spans);
+            }
+            None => block.stmts.iter().for_each(|stmt| enclosed(stmt, spans)),
+        },
+        Statement::Function(fun) => function(fun, spans),
+        Statement::Class(class) => class.methods.iter().for_each(|fun| function(fun, spans)),
+        Statement::If(cond, then, otherwise) => {
+            header(cond.first_token().location_start, then);
+            if let (Statement::Block(then), Some(otherwise)) = (&**then, otherwise) {
+                header(then.close.location_start, otherwise);
+            }
+            enclosed(then, spans);
+            if let Some(otherwise) = otherwise {
+                enclosed(otherwise, spans);
+            }
+        }
+        Statement::While(cond, body) => {
+            header(cond.first_token().location_start, body);
+            enclosed(body, spans);
+        }
+        Statement::ForIn(ident, .., body) => {
+            header(ident.token().location_start, body);
+            enclosed(body, spans);
+        }
+        Statement::Return(_, None) | Statement::Empty => (),
+        Statement::Return(..) | Statement::Var(..) | Statement::Print(_) | Statement::Expr(_) => {
+            if let Some(start) = start(stmt) {
+                spans.push((start, end(stmt)));
+            }
+        }
+    }
+
+    fn function(fun: &Function, spans: &mut Vec<(Location, Location)>) {
+        spans.push((
+            fun.name.token().location_start,
+            fun.body.open.location_start,
+        ));
+        fun.body.stmts.iter().for_each(|stmt| enclosed(stmt, spans));
+    }
+}
+
+/// the body of the source of a `for` loop, inside the blocks it was
+/// lowered to
+fn loop_body(block: &Block) -> &Statement {
+    let Some(Statement::While(_, body)) = block
+        .stmts
+        .iter()
+        .find(|s| matches!(s, Statement::While(..)))
+    else {
+        unreachable!("for loops are lowered to while loops")
+    };
+    match (&**body, block.for_loop.is_some_and(|clauses| clauses.incr)) {
+        (Statement::Block(inner), true) => &inner.stmts[0],
+        (body, _) => body,
+    }
+}
+
+/// lays out a syntax tree that has no source of its own, such as one
+/// rewritten by the optimizer. it has no comments or blank lines to keep
+pub fn format_tree(stmts: &[Statement]) -> String {
+    write(stmts, vec![], vec![])
+}
+
+fn write(stmts: &[Statement], comments: Vec<Comment>, lines: Vec<&str>) -> String {
+    let mut formatter = Formatter {
+        out: String::new(),
+        depth: 0,
+        comments: comments.into_iter().peekable(),
+        lines,
+        fresh: true,
+        line: 0,
+        last: None,
+    };
+    for stmt in stmts {
+        stack::ensure(|| formatter.statement(stmt));
+    }
+    formatter.leading(None);
+    formatter.out
+}
+
+struct Formatter<'a> {
+    out: String,
+    depth: usize,
+    /// comments that haven't been written yet
+    comments: Peekable<vec::IntoIter<Comment>>,
+    /// the source, to find blank lines
+    lines: Vec<&'a str>,
+    /// nothing has been written in the current block yet
+    fresh: bool,
+    /// the line of the source the statement or comment written last
+    /// starts on
+    line: u64,
+    /// the last token of the statement written last, as long as nothing
+    /// has been written after it. a comment behind it goes on its line
+    last: Option<Location>,
+}
+
+impl Formatter<'_> {
+    fn statement(&mut self, stmt: &Statement) {
+        let Some(start) = start(stmt) else {
+            return;
+        };
+        self.leading(Some(start));
+        self.gap(start.line());
+        self.indent();
+        self.last = None;
+
+        match stmt {
+            Statement::Block(block) => match block.for_loop {
+                Some(clauses) => self.for_loop(block, clauses),
+                None => self.block(block),
+            },
+            Statement::Function(fun) => {
+                self.out.push_str("fun ");
+                self.function(fun);
+            }
+            Statement::Class(class) => {
+                self.out.push_str("class ");
+                self.out.push_str(class.name.name());
+                if let Some(superclass) = &class.superclass {
+                    self.out.push_str(" < ");
+                    self.out.push_str(superclass.name());
+                }
+                let close = class.close.location_start;
+                if class.methods.is_empty() && !self.comment_before(close) {
+                    self.out.push_str(" {}");
+                } else {
+                    self.out.push_str(" {\n");
+                    self.depth += 1;
+                    self.fresh = true;
+                    self.last = None;
+                    for method in &class.methods {
+                        let start = method.name.token().location_start;
+                        self.leading(Some(start));
+                        self.gap(start.line());
+                        self.indent();
+                        self.last = None;
+                        self.function(method);
+                        self.out.push('\n');
+                        self.last = Some(method.body.close.location_start);
+                    }
+                    self.leading(Some(close));
+                    self.depth -= 1;
+                    self.indent();
+                    self.out.push('}');
+                }
+            }
+            Statement::Return(_, None) => self.out.push_str("return;"),
+            Statement::Return(_, Some(val)) => {
+                self.out.push_str(&format!("return {};", expr(val)));
+            }
+            Statement::If(cond, then, otherwise) => {
+                self.out.push_str(&format!("if {} ", expr(cond)));
+                self.body(then);
+                if let Some(otherwise) = otherwise {
+                    self.out.push_str(" else ");
+                    self.body(otherwise);
+                }
+            }
+            Statement::While(cond, body) => {
+                self.out.push_str(&format!("while {} ", expr(cond)));
+                self.body(body);
+            }
+            Statement::ForIn(ident, _, items, body) => {
+                self.out
+                    .push_str(&format!("for (var {} in {}) ", ident.name(), expr(items)));
+                self.body(body);
+            }
+            Statement::Var(..) | Statement::Expr(_) => {
+                self.out.push_str(&simple(stmt));
+                self.out.push(';');
+            }
+            Statement::Print(val) => self.out.push_str(&format!("print {};", expr(val))),
+            Statement::Empty => unreachable!("empty statements have no start"),
+        }
+
+        self.out.push('\n');
+        self.last = Some(end(stmt));
+    }
+
+    /// writes a function from its name on, for declarations and methods
+    fn function(&mut self, fun: &Function) {
+        self.out
+            .push_str(&format!("{}{} ", fun.name.name(), fun.signature()));
+        self.block(&fun.body);
+    }
+
+    /// writes the block a `for` loop was lowered to as the loop
+    fn for_loop(&mut self, block: &Block, clauses: ForClauses) {
+        let mut stmts = block.stmts.iter();
+        let init = match clauses.init {
+            true => stmts.next().map_or(String::new(), simple),
+            false => String::new(),
+        };
+        let Some(Statement::While(cond, body)) = stmts.next() else {
+            unreachable!("for loops are lowered to while loops")
+        };
+        let cond = match clauses.cond {
+            true => format!(" {}", expr(cond)),
+            false => String::new(),
+        };
+        let (body, incr) = match (clauses.incr, &**body) {
+            (true, Statement::Block(inner)) => match inner.stmts.as_slice() {
+                [body, Statement::Expr(incr)] => (body, format!(" {}", expr(incr))),
+                _ => unreachable!("the increment follows the body of a for loop"),
+            },
+            (_, body) => (body, String::new()),
+        };
+        self.out.push_str(&format!("for ({init};{cond};{incr}) "));
+        self.body(body);
+    }
+
+    /// the block of an `if`, `else` or a loop
+    fn body(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(block) => self.block(block),
+            _ => unreachable!("the parser only accepts blocks as bodies"),
+        }
+    }
+
+    /// writes a block from its opening brace up to and including its
+    /// closing brace
+    fn block(&mut self, block: &Block) {
+        let close = block.close.location_start;
+        let empty = block.stmts.iter().all(|s| matches!(s, Statement::Empty));
+        if empty && !self.comment_before(close) {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push_str("{\n");
+        self.depth += 1;
+        self.fresh = true;
+        self.last = None;
+        for stmt in &block.stmts {
+            self.statement(stmt);
+        }
+        self.leading(Some(close));
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+    }
+
+    /// writes the comments before `location`, or all of the remaining
+    /// ones. a comment on the line the last statement ended on stays
+    /// behind it, the others get lines of their own
+    fn leading(&mut self, location: Option<Location>) {
+        while let Some(comment) = self
+            .comments
+            .next_if(|c| location.is_none_or(|location| c.location < location))
+        {
+            match self.last.take() {
+                Some(end) if comment.location.line() == end.line() && comment.location > end => {
+                    self.out.pop();
+                    self.out.push(' ');
+                }
+                _ => {
+                    self.gap(comment.location.line());
+                    self.indent();
+                }
+            }
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+        }
+    }
+
+    fn comment_before(&mut self, location: Location) -> bool {
+        self.comments.peek().is_some_and(|c| c.location < location)
+    }
+
+    /// keeps a blank line from the source in front of what starts on
+    /// `line`. there are none at the start of a block or between things
+    /// sharing a line
+    fn gap(&mut self, line: u64) {
+        let blank = line != self.line
+            && line
+                .checked_sub(1)
+                .and_then(|prev| self.lines.get(prev as usize))
+                .is_some_and(|prev| prev.trim().is_empty());
+        if blank && !self.fresh {
+            self.out.push('\n');
+        }
+        self.fresh = false;
+        self.line = line;
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+}
+
+/// a variable declaration or expression without its semicolon, as they
+/// appear in the initializer of a `for` loop
+fn simple(stmt: &Statement) -> String {
+    match stmt {
+        Statement::Var(ident, ty, None) => format!("var {}", annotated(ident.name(), ty.as_ref())),
+        Statement::Var(ident, ty, Some(init)) => format!(
+            "var {} = {}",
+            annotated(ident.name(), ty.as_ref()),
+            expr(init)
+        ),
+        Statement::Expr(e) => expr(e),
+        _ => unreachable!("only declarations and expressions initialize loops"),
+    }
+}
+
+/// where a statement starts, used to place the comments in front of it.
+/// empty statements aren't written and have none
+fn start(stmt: &Statement) -> Option<Location> {
+    stmt.first_token().map(|token| token.location_start)
+}
+
+/// where the last token of a statement starts, a comment after it on
+/// the same line stays there
+fn end(stmt: &Statement) -> Location {
+    match stmt {
+        Statement::Block(block) => block.close.location_start,
+        Statement::Function(fun) => fun.body.close.location_start,
+        Statement::Class(class) => class.close.location_start,
+        Statement::Return(keyword, val) => val.as_ref().map_or(keyword.location_start, last),
+        Statement::If(_, then, otherwise) => end(otherwise.as_ref().unwrap_or(then)),
+        Statement::While(_, body) | Statement::ForIn(.., body) => end(body),
+        Statement::Var(ident, ty, init) => match (init, ty) {
+            (Some(init), _) => last(init),
+            (None, Some(ty)) => ty.0.location_start,
+            (None, None) => ident.token().location_start,
+        },
+        Statement::Print(e) | Statement::Expr(e) => last(e),
+        Statement::Empty => Location::default(),
+    }
+}
+
+/// where the rightmost token of an expression starts
+fn last(e: &Expr) -> Location {
+    match e {
+        Expr::Literal(tok)
+        | Expr::Call(_, tok, _)
+        | Expr::Index(_, tok, _)
+        | Expr::Interpolation(_, tok) => tok.location_start,
+        Expr::Variable(ident) | Expr::This(ident) | Expr::Get(_, ident) | Expr::Super(_, ident) => {
+            ident.token().location_start
+        }
+        Expr::Unary(_, e)
+        | Expr::Binary(_, _, e)
+        | Expr::Grouping(e)
+        | Expr::Assignment(_, _, e)
+        | Expr::Set(_, _, e)
+        | Expr::SetIndex(_, _, _, e) => last(e),
+        Expr::List(open, items) => items.last().map_or(open.location_start, last),
+        Expr::Map(open, entries) => entries
+            .last()
+            .map_or(open.location_start, |(_, val)| last(val)),
+    }
+}
+
+const ASSIGNMENT: u8 = 1;
+const UNARY: u8 = 8;
+const POSTFIX: u8 = 9;
+const PRIMARY: u8 = 10;
+
+/// how tightly an expression binds. an operand binding looser than its
+/// operator needs parentheses
+fn precedence(e: &Expr) -> u8 {
+    match e {
+        Expr::Assignment(..) | Expr::Set(..) | Expr::SetIndex(..) => ASSIGNMENT,
+        Expr::Binary(_, op, _) => binary(&op.token_type),
+        Expr::Unary(..) => UNARY,
+        Expr::Call(..) | Expr::Get(..) | Expr::Index(..) => POSTFIX,
+        Expr::Grouping(e) => precedence(e),
+        Expr::Literal(_)
+        | Expr::Interpolation(..)
+        | Expr::Variable(_)
+        | Expr::This(_)
+        | Expr::Super(..)
+        | Expr::List(..)
+        | Expr::Map(..) => PRIMARY,
+    }
+}
+
+fn binary(op: &TokenType) -> u8 {
+    match op {
+        TokenType::Or => 2,
+        TokenType::And => 3,
+        TokenType::EqualEqual | TokenType::BangEqual => 4,
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => 5,
+        TokenType::Minus | TokenType::Plus => 6,
+        TokenType::Star | TokenType::Slash | TokenType::Percent => 7,
+        op => unreachable!("{op:?} is not a binary operator"),
+    }
+}
+
+/// an expression without the parentheses around it
+fn ungrouped(e: &Expr) -> &Expr {
+    match e {
+        Expr::Grouping(e) => ungrouped(e),
+        e => e,
+    }
+}
+
+fn expr(e: &Expr) -> String {
+    operand(e, ASSIGNMENT)
+}
+
+/// writes an expression, in parentheses if it binds looser than `min`.
+/// the parentheses of the source are dropped and put back where needed
+fn operand(e: &Expr, min: u8) -> String {
+    stack::ensure(|| {
+        let text = match e {
+            Expr::Grouping(e) => return operand(e, min),
+            Expr::Unary(op, e) => {
+                // `- -x` would read as `--x`
+                let space = match ungrouped(e) {
+                    Expr::Unary(inner, _)
+                        if op.token_type == TokenType::Minus
+                            && inner.token_type == op.token_type =>
+                    {
+                        " "
+                    }
+                    _ => "",
+                };
+                format!("{}{space}{}", op.lexeme, operand(e, UNARY))
+            }
+            Expr::Binary(l, op, r) => {
+                // operators are left associative, so a right operand of the
+                // same precedence keeps its parentheses
+                let prec = binary(&op.token_type);
+                format!(
+                    "{} {} {}",
+                    operand(l, prec),
+                    op.lexeme,
+                    operand(r, prec + 1)
+                )
+            }
+            Expr::Literal(tok) => tok.lexeme.clone(),
+            Expr::Interpolation(parts, end) => {
+                let mut text = String::new();
+                for (piece, e) in parts {
+                    text.push_str(&piece.lexeme);
+                    text.push_str(&expr(e));
+                }
+                text.push_str(&end.lexeme);
+                text
+            }
+            Expr::Variable(ident) | Expr::This(ident) => ident.name().clone(),
+            Expr::Assignment(ident, _, val) => format!("{} = {}", ident.name(), expr(val)),
+            Expr::Call(callee, _, args) => format!("{}({})", operand(callee, POSTFIX), list(args)),
+            Expr::Get(object, name) => format!("{}.{}", operand(object, POSTFIX), name.name()),
+            Expr::Set(object, name, val) => {
+                format!(
+                    "{}.{} = {}",
+                    operand(object, POSTFIX),
+                    name.name(),
+                    expr(val)
+                )
+            }
+            Expr::Super(_, method) => format!("super.{}", method.name()),
+            Expr::List(_, items) => format!("[{}]", list(items)),
+            Expr::Map(_, entries) => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, val)| format!("{}: {}", expr(key), expr(val)))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Expr::Index(collection, _, index) => {
+                format!("{}[{}]", operand(collection, POSTFIX), expr(index))
+            }
+            Expr::SetIndex(collection, _, index, val) => format!(
+                "{}[{}] = {}",
+                operand(collection, POSTFIX),
+                expr(index),
+                expr(val)
+            ),
+        };
+
+        match precedence(e) < min {
+            true => format!("({text})"),
+            false => text,
+        }
+    })
+}
+
+fn list(items: &[Expr]) -> String {
+    items.iter().map(expr).collect::<Vec<_>>().join(", ")
+}
@@ -1,67 +1,146 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    rc::Rc,
+};
 
-use super::value::{LValue, RValue};
+use crate::{
+    ast::Ident,
+    value::{self, RValue},
+};
 
+use super::error::RuntimeError;
+
+/// a scope shared between the interpreter and every function
+/// value that was defined inside it
+pub type Scope = Rc<RefCell<Environment>>;
+
+/// a local scope. variables are stored in declaration order, which is
+/// the order the resolver numbered their slots in
 #[derive(Default)]
 pub struct Environment {
-    parent: Option<Box<Environment>>,
-    vars: HashMap<String, Option<RValue>>,
+    parent: Option<Scope>,
+    slots: Vec<RValue>,
+    /// name of the variable in each slot, for the debugger
+    names: Vec<String>,
 }
 
 impl Environment {
-    pub fn new_scope(&mut self) {
-        let mut newscope = Environment::default();
-        std::mem::swap(self, &mut newscope);
-        self.parent = Some(Box::new(newscope));
+    /// creates an empty scope nested in `parent`, or in the globals if
+    /// there is none
+    pub fn new_scope(parent: Option<&Scope>) -> Scope {
+        Rc::new(RefCell::new(Environment {
+            parent: parent.cloned(),
+            slots: Vec::new(),
+            names: Vec::new(),
+        }))
     }
 
-    pub fn end_scope(&mut self) -> Box<Environment> {
-        let mut newscope = self
-            .parent
-            .take()
-            .expect("end_scope shouldn't be called without parent");
-        std::mem::swap(self, &mut newscope);
-        newscope
+    /// adds the next variable of the scope
+    pub fn define(&mut self, name: &str, val: RValue) {
+        self.slots.push(val);
+        self.names.push(name.to_string());
     }
 
-    pub fn new_var(&mut self, name: LValue, val: Option<RValue>) {
-        match self.vars.entry(name) {
-            Entry::Occupied(o) => panic!(
-                "variable '{}' already exists in this scope. you cannot assign values with var",
-                o.key()
-            ),
-            Entry::Vacant(v) => {
-                v.insert(val);
-            }
+    pub fn parent(&self) -> Option<&Scope> {
+        self.parent.as_ref()
+    }
+
+    /// the variables defined so far, in slot order
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &RValue)> {
+        self.names.iter().map(String::as_str).zip(&self.slots)
+    }
+
+    /// takes the variables out of the scope
+    pub fn take_slots(&mut self) -> Vec<RValue> {
+        std::mem::take(&mut self.slots)
+    }
+
+    pub fn get(scope: &Scope, depth: usize, slot: usize) -> RValue {
+        Self::ancestor(scope, depth).borrow().slots[slot].clone()
+    }
+
+    pub fn set(scope: &Scope, depth: usize, slot: usize, val: RValue) {
+        Self::ancestor(scope, depth).borrow_mut().slots[slot] = val;
+    }
+
+    fn ancestor(scope: &Scope, depth: usize) -> Scope {
+        let mut scope = scope.clone();
+        for _ in 0..depth {
+            let parent = scope.borrow().parent.clone();
+            scope = parent.expect("resolver only counts existing scopes");
         }
+        scope
     }
+}
 
-    pub fn set_var(&mut self, name: LValue, val: RValue) {
-        match self.get_entry(name) {
-            Entry::Vacant(v) => panic!("unable to assign '{}'. variable does not exist", v.key()),
-            Entry::Occupied(mut o) => o.insert(Some(val)),
-        };
+impl Drop for Environment {
+    fn drop(&mut self) {
+        value::drop_all(self.take_slots());
     }
+}
 
-    pub fn get_var(&self, name: &LValue) -> &RValue {
-        self.vars
-            .get(name)
-            .map_or_else(
-                || match &self.parent {
-                    Some(p) => Some(p.get_var(name)),
-                    None => panic!("variable '{name}' does not exist"),
-                },
-                |x| x.as_ref(),
-            )
-            .unwrap_or(&RValue::Null)
+/// variables declared at the top level. they are looked up by name as
+/// functions may use globals that are only declared later on
+#[derive(Default)]
+pub struct Globals {
+    vars: HashMap<String, RValue>,
+}
+
+impl Globals {
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &RValue)> {
+        self.vars.iter().map(|(name, val)| (name.as_str(), val))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RValue> {
+        self.vars.get(name)
     }
 
-    fn get_entry(&mut self, name: LValue) -> Entry<LValue, Option<RValue>> {
-        match self.vars.entry(name) {
-            Entry::Vacant(v) if self.parent.is_some() => {
-                self.parent.as_mut().unwrap().get_entry(v.into_key())
+    /// defines a variable, replacing any previous one of the same name
+    pub fn define(&mut self, name: String, val: RValue) {
+        self.vars.insert(name, val);
+    }
+
+    pub fn new_var(&mut self, name: &Ident, val: RValue) -> Result<(), RuntimeError> {
+        match self.vars.entry(name.name().clone()) {
+            Entry::Occupied(o) => Err(RuntimeError::new(
+                name.token(),
+                format!(
+                    "variable '{}' already exists in this scope. you cannot assign values with var",
+                    o.key()
+                ),
+            )),
+            Entry::Vacant(v) => {
+                v.insert(val);
+                Ok(())
             }
-            e => e,
         }
     }
+
+    pub fn set_var(&mut self, name: &Ident, val: RValue) -> Result<(), RuntimeError> {
+        match self.vars.get_mut(name.name()) {
+            Some(var) => {
+                *var = val;
+                Ok(())
+            }
+            None => Err(RuntimeError::new(
+                name.token(),
+                format!(
+                    "unable to assign '{}'. variable does not exist",
+                    name.name()
+                ),
+            )
+            .with_help(format!("declare it with 'var {}' first", name.name()))),
+        }
+    }
+
+    pub fn get_var(&self, name: &Ident) -> Result<RValue, RuntimeError> {
+        self.vars.get(name.name()).cloned().ok_or_else(|| {
+            RuntimeError::new(
+                name.token(),
+                format!("variable '{}' does not exist", name.name()),
+            )
+            .with_help(format!("declare it with 'var {}' first", name.name()))
+        })
+    }
 }
@@ -0,0 +1,60 @@
+use std::fmt;
+
+use crate::{
+    diagnostic::Diagnostic,
+    scanner::{Location, Token},
+};
+
+/// an error raised while executing a script. points at the token
+/// whose evaluation failed
+#[derive(Debug)]
+pub struct RuntimeError {
+    token: Box<Token>,
+    message: String,
+    help: Option<String>,
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, message: impl Into<String>) -> Self {
+        Self {
+            token: Box::new(token.clone()),
+            message: message.into(),
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn location(&self) -> Location {
+        self.token.location_start
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(
+            self.message.clone(),
+            self.token.location_start,
+            self.token.location_end,
+        );
+        match &self.help {
+            Some(help) => diagnostic.with_help(help.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "runtime error at {} near '{}': {}",
+            self.location(),
+            self.token.lexeme,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -1,112 +1,601 @@
 mod environment;
+mod error;
 mod value;
 
-use environment::Environment;
-use value::{RValue, Value};
+use environment::{Environment, Globals, Scope};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+pub use error::RuntimeError;
+pub use value::Function;
+use value::Value;
 
 use crate::{
-    ast::{Expr, Statement},
-    scanner::TokenType,
+    ast::{self, Binding, Expr, Ident, Statement},
+    scanner::{Token, TokenType},
+    stack, stdlib,
+    value::{Class, Instance, Key, Native, RValue},
 };
 
-#[derive(Default)]
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// runs before every statement the interpreter executes. lets the
+/// debugger pause the script and look around
+pub trait Hook {
+    /// an error stops the script
+    fn statement(&mut self, interpreter: &mut Interpreter, stmt: &Statement) -> Result<()>;
+}
+
+/// how control leaves a statement
+enum Flow {
+    Next,
+    Return(RValue),
+}
+
 pub struct Interpreter {
-    env: Environment,
+    globals: Globals,
+    /// innermost local scope of the code being executed, `None` at the
+    /// top level
+    env: Option<Scope>,
+    /// where `print` writes to
+    out: stdlib::Output,
+    /// where `input` reads from
+    input: stdlib::Input,
+    hook: Option<Box<dyn Hook>>,
+    /// number of script functions being executed
+    calls: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self {
+            globals: Globals::default(),
+            env: None,
+            out: stdlib::stdout(),
+            input: stdlib::stdin(),
+            hook: None,
+            calls: 0,
+        }
+    }
 }
 
 impl Interpreter {
+    /// an interpreter with the standard library defined
     pub fn new() -> Self {
-        Self::default()
+        let mut interpreter = Self::default();
+        for native in stdlib::natives(&interpreter.out, &interpreter.input) {
+            interpreter.define_native(native);
+        }
+        interpreter
+    }
+
+    /// makes a rust function callable from scripts as a global
+    pub fn define_native(&mut self, native: Native) {
+        let name = native.name().to_string();
+        self.globals.define(name, RValue::Native(Rc::new(native)));
     }
 
-    pub fn evaluate(&mut self, stmt: Statement) {
-        self.statement(&stmt);
+    /// executes a top level statement that went through the resolver.
+    /// on error the interpreter is left in the global scope so it can
+    /// keep being used
+    pub fn evaluate(&mut self, stmt: Statement) -> Result<()> {
+        stack::ensure(|| self.statement(&stmt)).map(drop)
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &RValue)> {
+        self.globals.iter()
+    }
+
+    pub fn global(&self, name: &str) -> Option<RValue> {
+        self.globals.get(name).cloned()
+    }
+
+    /// defines a global, replacing any previous one of the same name
+    pub fn define_global(&mut self, name: &str, val: RValue) {
+        self.globals.define(name.to_string(), val);
+    }
+
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        *self.out.borrow_mut() = out;
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        *self.input.borrow_mut() = Some(input);
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hook = Some(hook);
+    }
+
+    /// how many script functions are being executed, 0 at the top level
+    pub fn call_depth(&self) -> usize {
+        self.calls
+    }
+
+    /// the variables of each local scope around the code being
+    /// executed, innermost first
+    pub fn locals(&self) -> Vec<Vec<(String, RValue)>> {
+        let mut scopes = vec![];
+        let mut scope = self.env.clone();
+        while let Some(current) = scope {
+            let env = current.borrow();
+            let vars = env
+                .vars()
+                .map(|(name, val)| (name.to_string(), val.clone()))
+                .collect();
+            scopes.push(vars);
+            scope = env.parent().cloned();
+        }
+        scopes
+    }
+
+    /// evaluates an expression in the innermost scope of the code being
+    /// executed. it has to be resolved with [`resolver::resolve_in`]
+    /// against the names in [`Interpreter::locals`]
+    ///
+    /// [`resolver::resolve_in`]: crate::resolver::resolve_in
+    pub fn evaluate_expr(&mut self, expr: &Expr) -> Result<RValue> {
+        stack::ensure(|| self.rval(expr))
     }
 }
 
 impl Interpreter {
-    fn var_decl(&mut self, ident: crate::ast::Ident, val: Option<RValue>) {
-        println!("setting {} = {val:?}", ident.name());
-        self.env.new_var(ident.into_name(), val)
+    /// declares a variable in the innermost scope. locals are defined
+    /// in the order the resolver numbered them
+    fn var_decl(&mut self, ident: &Ident, val: RValue) -> Result<()> {
+        match &self.env {
+            Some(scope) => {
+                scope.borrow_mut().define(ident.name(), val);
+                Ok(())
+            }
+            None => self.globals.new_var(ident, val),
+        }
+    }
+
+    fn lookup(&self, ident: &Ident) -> Result<RValue> {
+        match (ident.binding(), &self.env) {
+            (Binding::Local { depth, slot }, Some(scope)) => {
+                Ok(Environment::get(scope, depth, slot))
+            }
+            _ => self.globals.get_var(ident),
+        }
+    }
+
+    fn assign(&mut self, ident: &Ident, val: RValue) -> Result<()> {
+        match (ident.binding(), &self.env) {
+            (Binding::Local { depth, slot }, Some(scope)) => {
+                Environment::set(scope, depth, slot, val);
+                Ok(())
+            }
+            _ => self.globals.set_var(ident, val),
+        }
     }
 
-    fn statement(&mut self, stmt: &Statement) {
+    fn statement(&mut self, stmt: &Statement) -> Result<Flow> {
+        // taken out while it runs so it can evaluate code without
+        // being called again
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.statement(self, stmt);
+            self.hook = Some(hook);
+            result?;
+        }
         match stmt {
             Statement::Print(expr) => {
-                let val = self.expr(expr);
-                self.print_stmt(val.as_rval(&self.env));
+                let val = self.rval(expr)?;
+                self.print_stmt(&val, expr.first_token())?;
             }
-            Statement::Expr(expr) => drop(self.expr(expr)),
-            Statement::Var(ident, expr) => {
-                let val = expr.as_ref().map(|e| self.expr(e).into_rval(&self.env));
-                self.var_decl(ident.clone(), val);
+            Statement::Expr(expr) => drop(self.expr(expr)?),
+            Statement::Var(ident, _, expr) => {
+                let val = match expr {
+                    Some(e) => self.rval(e)?,
+                    None => RValue::Null,
+                };
+                let val = match ident.is_decimal() {
+                    true => val.promote(),
+                    false => val,
+                };
+                self.var_decl(ident, val)?;
             }
-            Statement::Block(stmts) => {
-                self.env.new_scope();
-                stmts.iter().for_each(|x| self.statement(x));
-                self.env.end_scope();
+            Statement::Block(block) => {
+                let scope = Environment::new_scope(self.env.as_ref());
+                return self.with_scope(scope, |this| this.block(&block.stmts));
+            }
+            Statement::Function(decl) => {
+                let fun = RValue::Function(Function::new(decl.clone(), self.env.clone()));
+                self.var_decl(&decl.name, fun)?;
+            }
+            Statement::Class(decl) => self.class_decl(decl)?,
+            Statement::Return(_, expr) => {
+                let val = match expr {
+                    Some(e) => self.rval(e)?,
+                    None => RValue::Null,
+                };
+                return Ok(Flow::Return(val));
             }
             Statement::If(cond, when_true, when_false) => {
-                if self.expr(cond).as_rval(&self.env).is_truthy() {
-                    self.statement(when_true)
+                if self.condition(cond)? {
+                    return self.statement(when_true);
                 } else if let Some(when_false) = when_false {
-                    self.statement(when_false)
+                    return self.statement(when_false);
                 }
             }
             Statement::While(cond, body) => {
-                let cond = self.expr(cond);
-                while cond.as_rval(&self.env).is_truthy() {
-                    self.statement(body);
+                while self.condition(cond)? {
+                    if let Flow::Return(val) = self.statement(body)? {
+                        return Ok(Flow::Return(val));
+                    }
+                }
+            }
+            Statement::ForIn(ident, keyword, items, body) => {
+                let items = self
+                    .rval(items)?
+                    .iterable()
+                    .map_err(|msg| RuntimeError::new(keyword, msg))?;
+                // the length is checked every time as the body may change it
+                for i in 0.. {
+                    let Some(item) = items.borrow().get(i).cloned() else {
+                        break;
+                    };
+                    let scope = Environment::new_scope(self.env.as_ref());
+                    let flow = self.with_scope(scope, |this| {
+                        this.var_decl(ident, item)?;
+                        this.statement(body)
+                    })?;
+                    if let Flow::Return(val) = flow {
+                        return Ok(Flow::Return(val));
+                    }
                 }
             }
             Statement::Empty => (),
         }
+        Ok(Flow::Next)
     }
 
-    fn expr(&mut self, expr: &Expr) -> Value {
-        match expr {
-            Expr::Unary(tok, _expr) => match tok.token_type {
-                _ => panic!("unexpected token type: {tok:?}"),
+    /// runs statements in the current scope until one of them returns
+    fn block(&mut self, stmts: &[Statement]) -> Result<Flow> {
+        for stmt in stmts {
+            if let Flow::Return(val) = self.statement(stmt)? {
+                return Ok(Flow::Return(val));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// runs `f` with `scope` as the innermost scope, restoring the
+    /// previous one afterwards, even if `f` fails
+    fn with_scope(
+        &mut self,
+        scope: Scope,
+        f: impl FnOnce(&mut Self) -> Result<Flow>,
+    ) -> Result<Flow> {
+        let previous = self.env.replace(scope);
+        let flow = f(self);
+        self.env = previous;
+        flow
+    }
+
+    fn class_decl(&mut self, decl: &ast::Class) -> Result<()> {
+        let superclass = match &decl.superclass {
+            Some(name) => match self.lookup(name)? {
+                RValue::Class(class) => Some(class),
+                val => {
+                    return Err(RuntimeError::new(
+                        name.token(),
+                        format!("superclass must be a class, not {}", val.type_name()),
+                    ))
+                }
             },
-            Expr::Binary(l, tok, r) => {
-                let lhs = self.expr(l);
-                match tok.token_type {
-                    TokenType::Or => {
-                        if lhs.as_rval(&self.env).is_truthy() {
-                            return Value::from(true);
-                        } else {
-                            return Value::from(self.expr(r).as_rval(&self.env).is_truthy());
+            None => None,
+        };
+
+        // declared up front so methods can refer to their own class
+        self.var_decl(&decl.name, RValue::Null)?;
+
+        let closure = match &superclass {
+            Some(superclass) => {
+                let scope = Environment::new_scope(self.env.as_ref());
+                scope
+                    .borrow_mut()
+                    .define("super", RValue::Class(superclass.clone()));
+                Some(scope)
+            }
+            None => self.env.clone(),
+        };
+        let methods = decl
+            .methods
+            .iter()
+            .map(|method| {
+                let fun = Function::method(method.clone(), closure.clone());
+                (method.name.name().clone(), RValue::Function(fun))
+            })
+            .collect();
+
+        let class = Class::new(decl.name.name().clone(), superclass, methods);
+        self.assign(&decl.name, RValue::Class(Rc::new(class)))
+    }
+
+    fn instantiate(
+        &mut self,
+        class: &Rc<Class>,
+        args: Vec<RValue>,
+        paren: &Token,
+    ) -> Result<RValue> {
+        let instance = Rc::new(RefCell::new(Instance::new(class.clone())));
+        match class.find_method("init") {
+            Some(RValue::Function(init)) => {
+                drop(self.call(&init.bind(instance.clone()), args, paren)?)
+            }
+            Some(_) => unreachable!("classes only hold functions of the backend that made them"),
+            None if !args.is_empty() => {
+                return Err(RuntimeError::new(
+                    paren,
+                    format!("expected 0 arguments but got {}", args.len()),
+                ))
+            }
+            None => (),
+        }
+        Ok(RValue::Instance(instance))
+    }
+
+    fn get_property(&mut self, instance: &Rc<RefCell<Instance>>, name: &Ident) -> Result<RValue> {
+        if let Some(val) = instance.borrow().field(name.name()) {
+            return Ok(val.clone());
+        }
+        let class = instance.borrow().class().clone();
+        match class.find_method(name.name()) {
+            Some(RValue::Function(method)) => Ok(RValue::Function(method.bind(instance.clone()))),
+            _ => Err(RuntimeError::new(
+                name.token(),
+                format!("undefined property '{}'", name.name()),
+            )),
+        }
+    }
+
+    fn call(&mut self, fun: &Function, args: Vec<RValue>, paren: &Token) -> Result<RValue> {
+        if args.len() != fun.arity() {
+            return Err(RuntimeError::new(
+                paren,
+                format!("expected {} arguments but got {}", fun.arity(), args.len()),
+            ));
+        }
+
+        // the top level counts as a call too
+        if self.calls + 1 == stack::MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(paren, "stack overflow"));
+        }
+
+        let scope = Environment::new_scope(fun.closure());
+        for (param, arg) in fun.params().iter().zip(args) {
+            let arg = match param.is_decimal() {
+                true => arg.promote(),
+                false => arg,
+            };
+            scope.borrow_mut().define(param.name(), arg);
+        }
+        self.calls += 1;
+        let flow = stack::ensure(|| self.with_scope(scope, |this| this.block(fun.body())));
+        self.calls -= 1;
+        let flow = flow?;
+
+        if fun.is_initializer() {
+            let this = fun.closure().expect("initializers are always bound");
+            return Ok(Environment::get(this, 0, 0));
+        }
+        match flow {
+            Flow::Return(val) if fun.returns_decimal() => Ok(val.promote()),
+            Flow::Return(val) => Ok(val),
+            Flow::Next => Ok(RValue::Null),
+        }
+    }
+
+    fn rval(&mut self, expr: &Expr) -> Result<RValue> {
+        self.expr(expr)?.into_rval(self)
+    }
+
+    /// evaluates `expr` and checks whether it counts as true
+    fn condition(&mut self, expr: &Expr) -> Result<bool> {
+        let val = self.rval(expr)?;
+        val.is_truthy()
+            .map_err(|msg| RuntimeError::new(expr.first_token(), msg))
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Value> {
+        stack::ensure(|| {
+            let val = match expr {
+                Expr::Unary(tok, expr) => {
+                    let val = match tok.token_type {
+                        TokenType::Bang => Ok(RValue::Boolean(!self.condition(expr)?)),
+                        TokenType::Minus => -&self.rval(expr)?,
+                        _ => Err("unsupported unary operator".into()),
+                    };
+                    Value::R(val.map_err(|msg| RuntimeError::new(tok, msg))?)
+                }
+                Expr::Binary(l, tok, r) => {
+                    match tok.token_type {
+                        TokenType::Or => {
+                            if self.condition(l)? {
+                                return Ok(Value::from(true));
+                            } else {
+                                return Ok(Value::from(self.condition(r)?));
+                            }
+                        }
+                        TokenType::And => {
+                            if !self.condition(l)? {
+                                return Ok(Value::from(false));
+                            } else {
+                                return Ok(Value::from(self.condition(r)?));
+                            }
                         }
+                        _ => (),
+                    }
+                    let lhs = self.rval(l)?;
+                    let rhs = self.rval(r)?;
+                    let val = match tok.token_type {
+                        TokenType::Plus => &lhs + &rhs,
+                        TokenType::Minus => &lhs - &rhs,
+                        TokenType::Star => &lhs * &rhs,
+                        TokenType::Slash => &lhs / &rhs,
+                        TokenType::Percent => &lhs % &rhs,
+                        TokenType::EqualEqual => Ok(RValue::Boolean(lhs == rhs)),
+                        TokenType::BangEqual => Ok(RValue::Boolean(lhs != rhs)),
+                        TokenType::Greater => lhs
+                            .compare(&rhs)
+                            .map(|ord| RValue::Boolean(ord.is_some_and(Ordering::is_gt))),
+                        TokenType::GreaterEqual => lhs
+                            .compare(&rhs)
+                            .map(|ord| RValue::Boolean(ord.is_some_and(Ordering::is_ge))),
+                        TokenType::Less => lhs
+                            .compare(&rhs)
+                            .map(|ord| RValue::Boolean(ord.is_some_and(Ordering::is_lt))),
+                        TokenType::LessEqual => lhs
+                            .compare(&rhs)
+                            .map(|ord| RValue::Boolean(ord.is_some_and(Ordering::is_le))),
+                        _ => Err("unsupported binary operator".into()),
+                    };
+                    Value::R(val.map_err(|msg| RuntimeError::new(tok, msg))?)
+                }
+                Expr::Grouping(expr) => self.expr(expr)?,
+                Expr::Literal(l) => Value::new(l.clone()),
+                Expr::Interpolation(parts, end) => {
+                    let mut text = String::new();
+                    for (piece, expr) in parts {
+                        text.push_str(piece.token_type.as_string_piece());
+                        text.push_str(&self.rval(expr)?.to_string());
                     }
-                    TokenType::And => {
-                        if !lhs.as_rval(&self.env).is_truthy() {
-                            return Value::from(false);
-                        } else {
-                            return Value::from(self.expr(r).as_rval(&self.env).is_truthy());
+                    text.push_str(end.token_type.as_string_piece());
+                    Value::R(RValue::String(text))
+                }
+                Expr::Variable(ident) => Value::L(ident.clone()),
+                Expr::Assignment(lhs, _, rhs) => {
+                    let val = self.rval(rhs)?;
+                    let val = match lhs.is_decimal() {
+                        true => val.promote(),
+                        false => val,
+                    };
+                    self.assign(lhs, val)?;
+                    Value::L(lhs.clone())
+                }
+                Expr::Call(callee, paren, args) => {
+                    let callee = self.rval(callee)?;
+                    let args = args
+                        .iter()
+                        .map(|arg| self.rval(arg))
+                        .collect::<Result<_>>()?;
+                    match callee {
+                        RValue::Function(fun) => Value::R(self.call(&fun, args, paren)?),
+                        RValue::Native(native) => Value::R(
+                            native
+                                .call(&args)
+                                .map_err(|msg| RuntimeError::new(paren, msg))?,
+                        ),
+                        RValue::Class(class) => Value::R(self.instantiate(&class, args, paren)?),
+                        _ => {
+                            return Err(RuntimeError::new(
+                                paren,
+                                format!(
+                                    "can only call functions and classes, not {}",
+                                    callee.type_name()
+                                ),
+                            ))
                         }
                     }
-                    _ => (),
                 }
-                let rhs = self.expr(r);
-                match tok.token_type {
-                    TokenType::Plus => Value::R(lhs.as_rval(&self.env) + rhs.as_rval(&self.env)),
-                    TokenType::Minus => Value::R(lhs.as_rval(&self.env) - rhs.as_rval(&self.env)),
-                    TokenType::Star => Value::R(lhs.as_rval(&self.env) * rhs.as_rval(&self.env)),
-                    TokenType::Slash => Value::R(lhs.as_rval(&self.env) / rhs.as_rval(&self.env)),
-                    _ => panic!("unexpected token type: {tok:?}"),
+                Expr::Get(object, name) => match self.rval(object)? {
+                    RValue::Instance(instance) => Value::R(self.get_property(&instance, name)?),
+                    val => {
+                        return Err(RuntimeError::new(
+                            name.token(),
+                            format!("only instances have properties, not {}", val.type_name()),
+                        ))
+                    }
+                },
+                Expr::Set(object, name, val) => {
+                    let RValue::Instance(instance) = self.rval(object)? else {
+                        return Err(RuntimeError::new(
+                            name.token(),
+                            "only instances have fields",
+                        ));
+                    };
+                    let val = self.rval(val)?;
+                    instance
+                        .borrow_mut()
+                        .set_field(name.name().clone(), val.clone());
+                    Value::R(val)
                 }
-            }
-            Expr::Grouping(expr) => self.expr(expr),
-            Expr::Literal(l) => Value::new(l.clone()),
-            Expr::Assignment(lhs, _, rhs) => {
-                let val = self.expr(rhs).into_rval(&self.env);
-                self.env.set_var(lhs.name().clone(), val);
-                Value::L(lhs.name().clone())
-            }
-        }
+                Expr::List(_, elements) => {
+                    let elements = elements
+                        .iter()
+                        .map(|element| self.rval(element))
+                        .collect::<Result<_>>()?;
+                    Value::R(RValue::List(Rc::new(RefCell::new(elements))))
+                }
+                Expr::Map(brace, entries) => {
+                    let mut map = BTreeMap::new();
+                    for (key, val) in entries {
+                        let key = self.rval(key)?;
+                        let val = self.rval(val)?;
+                        let key =
+                            Key::try_from(&key).map_err(|msg| RuntimeError::new(brace, msg))?;
+                        map.insert(key, val);
+                    }
+                    Value::R(RValue::Map(Rc::new(RefCell::new(map.into()))))
+                }
+                Expr::Index(object, bracket, index) => {
+                    let object = self.rval(object)?;
+                    let index = self.rval(index)?;
+                    Value::R(
+                        object
+                            .index(&index)
+                            .map_err(|msg| RuntimeError::new(bracket, msg))?,
+                    )
+                }
+                Expr::SetIndex(object, bracket, index, val) => {
+                    let object = self.rval(object)?;
+                    let index = self.rval(index)?;
+                    let val = self.rval(val)?;
+                    object
+                        .set_index(&index, val.clone())
+                        .map_err(|msg| RuntimeError::new(bracket, msg))?;
+                    Value::R(val)
+                }
+                Expr::This(keyword) => Value::L(keyword.clone()),
+                Expr::Super(keyword, method) => {
+                    let Binding::Local { depth, slot } = keyword.binding() else {
+                        unreachable!("resolver binds 'super' to a local")
+                    };
+                    let scope = self.env.as_ref().expect("'super' is only used in methods");
+                    let superclass = Environment::get(scope, depth, slot);
+                    // `this` is bound in the scope right inside the one of `super`
+                    let instance = Environment::get(scope, depth - 1, 0);
+                    let (RValue::Class(superclass), RValue::Instance(instance)) =
+                        (superclass, instance)
+                    else {
+                        unreachable!("'super' and 'this' are bound by the interpreter")
+                    };
+                    match superclass.find_method(method.name()) {
+                        Some(RValue::Function(fun)) => {
+                            Value::R(RValue::Function(fun.bind(instance)))
+                        }
+                        _ => {
+                            return Err(RuntimeError::new(
+                                method.token(),
+                                format!("undefined property '{}'", method.name()),
+                            ))
+                        }
+                    }
+                }
+            };
+            Ok(val)
+        })
     }
 
-    fn print_stmt(&self, val: &RValue) {
-        println!("val={val:?}");
+    fn print_stmt(&mut self, val: &RValue, tok: &Token) -> Result<()> {
+        writeln!(self.out.borrow_mut(), "{val}")
+            .map_err(|e| RuntimeError::new(tok, format!("unable to print: {e}")))
     }
 }
@@ -1,28 +1,109 @@
-use crate::scanner::{Token, TokenType};
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    ast::{self, Ident, Statement},
+    scanner::{Token, TokenType},
+    value::{Instance, RValue},
+};
+
+use super::{
+    environment::{Environment, Scope},
+    error::RuntimeError,
+    Interpreter,
+};
+
+#[derive(Clone)]
+pub struct Function {
+    decl: Rc<ast::Function>,
+    /// scope the function was declared in, `None` at the top level.
+    /// kept alive for as long as the function value is
+    closure: Option<Scope>,
+    /// `init` methods always return `this`
+    is_initializer: bool,
+}
 
-use super::environment::Environment;
+impl Function {
+    pub fn new(decl: Rc<ast::Function>, closure: Option<Scope>) -> Self {
+        Self {
+            decl,
+            closure,
+            is_initializer: false,
+        }
+    }
 
-#[derive(Debug, Clone)]
-pub enum RValue {
-    Boolean(bool),
-    String(String),
-    Int(i64),
-    Decimal(f64),
-    Null,
-}
+    pub fn method(decl: Rc<ast::Function>, closure: Option<Scope>) -> Self {
+        let is_initializer = decl.name.name() == "init";
+        Self {
+            decl,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// returns a copy of the method with `this` bound to `instance`
+    pub fn bind(&self, instance: Rc<RefCell<Instance>>) -> Self {
+        let scope = Environment::new_scope(self.closure.as_ref());
+        scope
+            .borrow_mut()
+            .define("this", RValue::Instance(instance));
+        Self {
+            closure: Some(scope),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_initializer(&self) -> bool {
+        self.is_initializer
+    }
+
+    pub fn closure(&self) -> Option<&Scope> {
+        self.closure.as_ref()
+    }
 
-impl RValue {
-    pub fn is_truthy(&self) -> bool {
-        match *self {
-            RValue::Boolean(b) => b,
-            RValue::Int(i) => i != 0,
-            RValue::Null => false,
-            _ => panic!("can't establish truthyness for {self:?}"),
+    /// takes the variables of the scope the function was declared in out
+    /// of it, unless something else still uses the scope
+    pub(crate) fn take_captured(&mut self) -> Vec<RValue> {
+        match self.closure.take().map(Rc::try_unwrap) {
+            Some(Ok(scope)) => scope.into_inner().take_slots(),
+            _ => vec![],
         }
     }
+
+    pub fn arity(&self) -> usize {
+        self.decl.params.len()
+    }
+
+    pub fn returns_decimal(&self) -> bool {
+        self.decl.returns_decimal()
+    }
+
+    pub fn params(&self) -> &[Ident] {
+        &self.decl.params
+    }
+
+    pub fn body(&self) -> &[Statement] {
+        &self.decl.body.stmts
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        let same_closure = match (&self.closure, &other.closure) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        Rc::ptr_eq(&self.decl, &other.decl) && same_closure
+    }
 }
 
-pub type LValue = String;
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.decl.name.name())
+    }
+}
+
+pub type LValue = Ident;
 
 pub enum Value {
     R(RValue),
@@ -32,26 +113,21 @@ pub enum Value {
 impl Value {
     pub fn new(val: Token) -> Self {
         match val.token_type {
-            TokenType::Identifier(d) => Self::L(d),
+            TokenType::True => Self::R(RValue::Boolean(true)),
+            TokenType::False => Self::R(RValue::Boolean(false)),
+            TokenType::Nil => Self::R(RValue::Null),
 
             TokenType::String(s) => Self::R(RValue::String(s)),
             TokenType::Integer(i) => Self::R(RValue::Int(i)),
             TokenType::Decimal(d) => Self::R(RValue::Decimal(d)),
-            _ => panic!("unexpected token type {val:?}"),
+            _ => unreachable!("parser only builds literals from literal tokens: {val:?}"),
         }
     }
 
-    pub fn as_rval<'a>(&'a self, env: &'a Environment) -> &'a RValue {
+    pub fn into_rval(self, interpreter: &Interpreter) -> Result<RValue, RuntimeError> {
         match self {
-            Self::R(rval) => rval,
-            Self::L(lval) => env.get_var(lval),
-        }
-    }
-
-    pub fn into_rval(self, env: &Environment) -> RValue {
-        match self {
-            Self::R(rval) => rval,
-            Self::L(lval) => env.get_var(&lval).clone(),
+            Self::R(rval) => Ok(rval),
+            Self::L(lval) => interpreter.lookup(&lval),
         }
     }
 }
@@ -61,56 +137,3 @@ impl From<bool> for Value {
         Self::R(RValue::Boolean(value))
     }
 }
-
-impl<'l, 'r> std::ops::Add<&'r RValue> for &'l RValue {
-    type Output = RValue;
-
-    fn add(self, rhs: &'r RValue) -> Self::Output {
-        match (self, rhs) {
-            (RValue::Int(x), RValue::Int(y)) => RValue::Int(x + y),
-            (RValue::Int(i), RValue::Decimal(d)) => RValue::Decimal(*i as f64 + d),
-            (RValue::Decimal(d), RValue::Int(i)) => RValue::Decimal(d + *i as f64),
-            (RValue::String(a), RValue::String(b)) => RValue::String(a.clone() + b),
-            _ => panic!("Invalid types for addition"),
-        }
-    }
-}
-
-impl<'l, 'r> std::ops::Sub<&'r RValue> for &'l RValue {
-    type Output = RValue;
-
-    fn sub(self, rhs: &'r RValue) -> Self::Output {
-        match (self, rhs) {
-            (RValue::Int(x), RValue::Int(y)) => RValue::Int(x - y),
-            (RValue::Int(i), RValue::Decimal(d)) => RValue::Decimal(*i as f64 - d),
-            (RValue::Decimal(d), RValue::Int(i)) => RValue::Decimal(d - *i as f64),
-            _ => panic!("Invalid types for subtraction"),
-        }
-    }
-}
-
-impl<'l, 'r> std::ops::Mul<&'r RValue> for &'l RValue {
-    type Output = RValue;
-
-    fn mul(self, rhs: &'r RValue) -> Self::Output {
-        match (self, rhs) {
-            (RValue::Int(x), RValue::Int(y)) => RValue::Int(x * y),
-            (RValue::Int(i), RValue::Decimal(d)) => RValue::Decimal(*i as f64 * d),
-            (RValue::Decimal(d), RValue::Int(i)) => RValue::Decimal(d * *i as f64),
-            _ => panic!("Invalid types for subtraction"),
-        }
-    }
-}
-
-impl<'l, 'r> std::ops::Div<&'r RValue> for &'l RValue {
-    type Output = RValue;
-
-    fn div(self, rhs: &'r RValue) -> Self::Output {
-        match (self, rhs) {
-            (RValue::Int(x), RValue::Int(y)) => RValue::Int(x / y),
-            (RValue::Int(i), RValue::Decimal(d)) => RValue::Decimal(*i as f64 / d),
-            (RValue::Decimal(d), RValue::Int(i)) => RValue::Decimal(d / *i as f64),
-            _ => panic!("Invalid types for subtraction"),
-        }
-    }
-}
@@ -0,0 +1,797 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Expr, Ident, Statement, TypeName},
+    scanner::{Token, TokenType},
+    stack,
+    vm::CompileError,
+};
+
+use super::{
+    BinaryOp, Block, BlockId, CompareOp, Function, Global, Inst, Module, Phi, Terminator, Type,
+    Value, MAX_PARAMS,
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// lowers a script that went through the resolver and the checker. the
+/// top level code becomes the first function and functions declared at
+/// the top level the others. parameters without a type annotation are
+/// ints, and so are the results of functions that return a value
+/// without annotating its type
+pub fn lower(stmts: &[Statement]) -> Result<Module> {
+    let mut module = Module::default();
+    let mut names = HashMap::new();
+    module
+        .functions
+        .push(declare("script".to_string(), vec![], Type::Int));
+    let mut defaulted = vec![Defaulted::default()];
+    let mut decls = vec![];
+    for stmt in stmts {
+        let Statement::Function(decl) = stmt else {
+            continue;
+        };
+        if decl.params.len() > MAX_PARAMS {
+            return Err(CompileError::new(
+                decl.name.token(),
+                format!("native code supports functions with up to {MAX_PARAMS} parameters"),
+            ));
+        }
+        let mut params = vec![];
+        for ty in &decl.param_types {
+            params.push(annotated(ty.as_ref(), Type::Int)?);
+        }
+        let returns = match returns_value(&decl.body.stmts) {
+            true => annotated(decl.returns.as_ref(), Type::Int)?,
+            false => annotated(decl.returns.as_ref(), Type::Nil)?,
+        };
+        if names
+            .insert(decl.name.name().clone(), module.functions.len())
+            .is_some()
+        {
+            return Err(CompileError::new(
+                decl.name.token(),
+                format!("native code can't declare '{}' twice", decl.name.name()),
+            ));
+        }
+        module
+            .functions
+            .push(declare(decl.name.name().clone(), params, returns));
+        defaulted.push(Defaulted {
+            params: decl.param_types.iter().map(Option::is_none).collect(),
+            returns: decl.returns.is_none(),
+        });
+        decls.push(decl);
+    }
+
+    let mut lowering = Lowering {
+        module,
+        functions: names,
+        defaulted,
+        globals: HashMap::new(),
+    };
+    let mut script = Builder::new(&mut lowering, 0);
+    for stmt in stmts {
+        stack::ensure(|| script.statement(stmt))?;
+    }
+    let zero = script.constant(Type::Int, 0);
+    script.terminate(Terminator::Return(zero));
+    let script = script.finish();
+    lowering.module.functions[0] = script;
+
+    // functions come after the script so they see all of its globals
+    for (i, decl) in decls.into_iter().enumerate() {
+        let index = i + 1;
+        let mut builder = Builder::new(&mut lowering, index);
+        builder.scopes.push(HashMap::new());
+        for (param, val) in decl.params.iter().zip(builder.function.params.clone()) {
+            let ty = builder.function.types[val.0];
+            let var = builder.declare(param, ty);
+            let entry = builder.current;
+            builder.write(var, entry, val);
+        }
+        for stmt in &decl.body.stmts {
+            stack::ensure(|| builder.statement(stmt))?;
+        }
+        match builder.function.returns {
+            Type::Nil => {
+                let nil = builder.constant(Type::Nil, 0);
+                builder.terminate(Terminator::Return(nil));
+            }
+            ty => builder.terminate(Terminator::Error {
+                line: decl.body.close.location_start.line() + 1,
+                message: format!("'{}' ended without returning {}", decl.name.name(), a(ty)),
+            }),
+        }
+        let function = builder.finish();
+        lowering.module.functions[index] = function;
+    }
+    Ok(lowering.module)
+}
+
+/// a function with its signature but no code yet
+fn declare(name: String, params: Vec<Type>, returns: Type) -> Function {
+    Function {
+        name,
+        params: (0..params.len()).map(Value).collect(),
+        returns,
+        blocks: vec![],
+        types: params,
+    }
+}
+
+/// the type of an annotation, or `default` without one
+fn annotated(ty: Option<&TypeName>, default: Type) -> Result<Type> {
+    match ty.map(TypeName::name) {
+        None => Ok(default),
+        Some("int") => Ok(Type::Int),
+        Some("bool") => Ok(Type::Bool),
+        Some("nil") => Ok(Type::Nil),
+        Some(_) => Err(unsupported_type(&ty.expect("annotation is present").0)),
+    }
+}
+
+/// whether any `return` of a function body has a value
+fn returns_value(stmts: &[Statement]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Statement::Return(_, val) => val.is_some(),
+        Statement::Block(block) => returns_value(&block.stmts),
+        Statement::If(_, when_true, when_false) => {
+            returns_value(std::slice::from_ref(when_true))
+                || when_false
+                    .as_ref()
+                    .is_some_and(|stmt| returns_value(std::slice::from_ref(stmt)))
+        }
+        Statement::While(_, body) | Statement::ForIn(.., body) => {
+            returns_value(std::slice::from_ref(body))
+        }
+        _ => false,
+    })
+}
+
+fn unsupported_type(token: &Token) -> CompileError {
+    CompileError::new(token, "native code only supports int and bool values")
+}
+
+fn unsupported(token: &Token, what: &str) -> CompileError {
+    CompileError::new(token, format!("native code doesn't support {what}"))
+}
+
+fn mismatch(token: &Token, expected: Type, found: Type) -> CompileError {
+    CompileError::new(
+        token,
+        format!("mismatched types: expected {expected}, found {found}"),
+    )
+}
+
+/// explains a mismatch with a type that wasn't annotated but defaulted
+/// to an int
+fn defaulted_to_int(error: CompileError, found: Type) -> CompileError {
+    error.with_help(format!(
+        "parameters and returns without a type annotation are ints in native code, annotate this one with ': {found}'"
+    ))
+}
+
+/// the type with an article, for messages
+fn a(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "an int",
+        Type::Bool => "a bool",
+        Type::Nil => "nil",
+    }
+}
+
+/// what the functions being lowered share
+struct Lowering {
+    module: Module,
+    /// index of each function in the module by name
+    functions: HashMap<String, usize>,
+    /// which types of each function's signature weren't annotated, by
+    /// index in the module
+    defaulted: Vec<Defaulted>,
+    /// index of each global in the module by name
+    globals: HashMap<String, usize>,
+}
+
+/// which types of a function's signature come from the defaults of
+/// [`lower`] rather than from annotations
+#[derive(Default)]
+struct Defaulted {
+    params: Vec<bool>,
+    returns: bool,
+}
+
+/// a local variable, which lives in ssa values rather than memory
+#[derive(Clone, Copy)]
+struct Var {
+    index: usize,
+    ty: Type,
+}
+
+/// lowers the body of one function. locals are turned into ssa values
+/// as they are read, placing phis where the blocks reaching a read
+/// assign different values. a block is sealed once all blocks jumping
+/// to it are known, and reads in a block that isn't sealed yet get a
+/// phi whose incoming values are filled in when it is
+struct Builder<'a> {
+    lowering: &'a mut Lowering,
+    /// index of the function in the module, 0 for the script
+    index: usize,
+    function: Function,
+    current: BlockId,
+    /// local scopes, innermost last. the top level of the script has
+    /// none, its variables are globals
+    scopes: Vec<HashMap<String, Var>>,
+    /// the value each local has at the end of a block
+    defs: HashMap<(usize, BlockId), Value>,
+    locals: usize,
+    preds: Vec<Vec<BlockId>>,
+    sealed: Vec<bool>,
+    /// phis of unsealed blocks waiting for their incoming values
+    incomplete: HashMap<BlockId, Vec<(Var, Value)>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(lowering: &'a mut Lowering, index: usize) -> Self {
+        let declared = &lowering.module.functions[index];
+        let function = Function {
+            name: declared.name.clone(),
+            params: declared.params.clone(),
+            returns: declared.returns,
+            blocks: vec![],
+            types: declared.types.clone(),
+        };
+        let mut builder = Self {
+            lowering,
+            index,
+            function,
+            current: BlockId(0),
+            scopes: vec![],
+            defs: HashMap::new(),
+            locals: 0,
+            preds: vec![],
+            sealed: vec![],
+            incomplete: HashMap::new(),
+        };
+        let entry = builder.block();
+        builder.seal(entry);
+        builder
+    }
+
+    fn finish(self) -> Function {
+        self.function
+    }
+
+    fn block(&mut self) -> BlockId {
+        self.function.blocks.push(Block::new());
+        self.preds.push(vec![]);
+        self.sealed.push(false);
+        BlockId(self.function.blocks.len() - 1)
+    }
+
+    fn value(&mut self, ty: Type) -> Value {
+        self.function.types.push(ty);
+        Value(self.function.types.len() - 1)
+    }
+
+    fn emit(&mut self, inst: Inst) {
+        self.function.blocks[self.current.0].insts.push(inst);
+    }
+
+    fn constant(&mut self, ty: Type, value: i64) -> Value {
+        let dst = self.value(ty);
+        self.emit(Inst::Const { dst, value });
+        dst
+    }
+
+    /// ends the current block. code after it, like that following a
+    /// `return`, goes in a new block nothing jumps to
+    fn terminate(&mut self, terminator: Terminator) {
+        for succ in terminator.successors() {
+            self.preds[succ.0].push(self.current);
+        }
+        self.function.blocks[self.current.0].terminator = terminator;
+        self.current = self.block();
+        self.seal(self.current);
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        for (var, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.add_incoming(var, block, phi);
+        }
+        self.sealed[block.0] = true;
+    }
+
+    fn declare(&mut self, ident: &Ident, ty: Type) -> Var {
+        let var = Var {
+            index: self.locals,
+            ty,
+        };
+        self.locals += 1;
+        self.scopes
+            .last_mut()
+            .expect("locals are declared in a scope")
+            .insert(ident.name().clone(), var);
+        var
+    }
+
+    fn write(&mut self, var: Var, block: BlockId, val: Value) {
+        self.defs.insert((var.index, block), val);
+    }
+
+    /// the value a local has at the end of a block
+    fn read(&mut self, var: Var, block: BlockId) -> Value {
+        if let Some(val) = self.defs.get(&(var.index, block)) {
+            return *val;
+        }
+        let val = if !self.sealed[block.0] {
+            let phi = self.phi(var, block);
+            self.incomplete.entry(block).or_default().push((var, phi));
+            phi
+        } else if let [pred] = self.preds[block.0][..] {
+            self.read(var, pred)
+        } else if self.preds[block.0].is_empty() {
+            // nothing reaches the block, so any value will do
+            let dst = self.value(var.ty);
+            self.function.blocks[block.0]
+                .insts
+                .push(Inst::Const { dst, value: 0 });
+            dst
+        } else {
+            let phi = self.phi(var, block);
+            // reads in loops find the phi instead of coming back here
+            self.write(var, block, phi);
+            self.add_incoming(var, block, phi);
+            phi
+        };
+        self.write(var, block, val);
+        val
+    }
+
+    fn phi(&mut self, var: Var, block: BlockId) -> Value {
+        let dst = self.value(var.ty);
+        self.function.blocks[block.0].phis.push(Phi {
+            dst,
+            incoming: vec![],
+        });
+        dst
+    }
+
+    fn add_incoming(&mut self, var: Var, block: BlockId, phi: Value) {
+        for pred in self.preds[block.0].clone() {
+            let val = self.read(var, pred);
+            let phis = &mut self.function.blocks[block.0].phis;
+            let phi = phis
+                .iter_mut()
+                .find(|p| p.dst == phi)
+                .expect("phi is in its block");
+            phi.incoming.push((pred, val));
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Var> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn scoped(&mut self, stmts: &[Statement]) -> Result<()> {
+        self.scopes.push(HashMap::new());
+        let result = stmts.iter().try_for_each(|stmt| self.statement(stmt));
+        self.scopes.pop();
+        result
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> Result<()> {
+        match stmt {
+            Statement::Block(block) => self.scoped(&block.stmts)?,
+            Statement::Function(decl) if self.index != 0 || !self.scopes.is_empty() => {
+                return Err(CompileError::new(
+                    decl.name.token(),
+                    "native code only supports functions declared at the top level",
+                ));
+            }
+            // lowered on their own after the script
+            Statement::Function(_) => (),
+            Statement::Class(decl) => return Err(unsupported(decl.name.token(), "classes")),
+            Statement::Return(keyword, val) => {
+                let (val, ty) = match val {
+                    Some(val) => self.expr(val)?,
+                    None => (self.constant(Type::Nil, 0), Type::Nil),
+                };
+                if ty != self.function.returns {
+                    let error = mismatch(keyword, self.function.returns, ty);
+                    return Err(match self.lowering.defaulted[self.index].returns {
+                        true => defaulted_to_int(error, ty),
+                        false => error,
+                    });
+                }
+                self.terminate(Terminator::Return(val));
+            }
+            Statement::If(cond, when_true, when_false) => {
+                let cond = self.condition(cond)?;
+                let then = self.block();
+                let merge = self.block();
+                let otherwise = match when_false {
+                    Some(_) => self.block(),
+                    None => merge,
+                };
+                self.branch(cond, then, otherwise);
+                self.seal(then);
+                self.current = then;
+                self.statement(when_true)?;
+                self.terminate(Terminator::Jump(merge));
+                if let Some(when_false) = when_false {
+                    self.seal(otherwise);
+                    self.current = otherwise;
+                    self.statement(when_false)?;
+                    self.terminate(Terminator::Jump(merge));
+                }
+                self.seal(merge);
+                self.current = merge;
+            }
+            Statement::While(cond, body) => self.while_loop(cond, body)?,
+            Statement::ForIn(ident, ..) => {
+                return Err(unsupported(ident.token(), "iterating over collections"))
+            }
+            Statement::Var(ident, ty, init) => {
+                let Some(init) = init else {
+                    return Err(CompileError::new(
+                        ident.token(),
+                        format!("native code needs '{}' to have a value", ident.name()),
+                    ));
+                };
+                let (val, found) = self.expr(init)?;
+                let ty = annotated(ty.as_ref(), found)?;
+                if ty != found {
+                    return Err(mismatch(init.first_token(), ty, found));
+                }
+                match self.scopes.is_empty() {
+                    true => {
+                        let global = self.global(ident, ty)?;
+                        self.emit(Inst::Store { global, value: val });
+                    }
+                    false => {
+                        let var = self.declare(ident, ty);
+                        self.write(var, self.current, val);
+                    }
+                }
+            }
+            Statement::Print(Expr::Literal(Token {
+                token_type: TokenType::String(s),
+                ..
+            })) => {
+                let strings = &mut self.lowering.module.strings;
+                strings.push(s.clone());
+                let string = strings.len() - 1;
+                self.emit(Inst::PrintString { string });
+            }
+            Statement::Print(val) => {
+                let (value, _) = self.expr(val)?;
+                self.emit(Inst::Print { value });
+            }
+            Statement::Expr(val) => drop(self.expr(val)?),
+            Statement::Empty => (),
+        }
+        Ok(())
+    }
+
+    /// a `while` loop, or a `for` loop whose initializer already ran
+    fn while_loop(&mut self, cond: &Expr, body: &Statement) -> Result<()> {
+        let header = self.block();
+        self.terminate(Terminator::Jump(header));
+        self.current = header;
+        let cond = self.condition(cond)?;
+        let inner = self.block();
+        let exit = self.block();
+        self.branch(cond, inner, exit);
+        self.seal(inner);
+        self.current = inner;
+        self.statement(body)?;
+        self.terminate(Terminator::Jump(header));
+        self.seal(header);
+        self.seal(exit);
+        self.current = exit;
+        Ok(())
+    }
+
+    fn branch(&mut self, cond: Value, then: BlockId, otherwise: BlockId) {
+        self.terminate(Terminator::Branch {
+            cond,
+            then,
+            otherwise,
+        });
+    }
+
+    /// the global a top level variable is stored in, the one declared
+    /// before if it's declared again
+    fn global(&mut self, ident: &Ident, ty: Type) -> Result<usize> {
+        let name = ident.name();
+        if self.lowering.functions.contains_key(name) {
+            return Err(CompileError::new(
+                ident.token(),
+                format!("native code can't reuse the name of the function '{name}'"),
+            ));
+        }
+        let globals = &mut self.lowering.module.globals;
+        match self.lowering.globals.get(name) {
+            Some(&global) if globals[global].ty == ty => Ok(global),
+            Some(&global) => Err(CompileError::new(
+                ident.token(),
+                format!(
+                    "native code needs '{name}' to stay {}, not become {}",
+                    a(globals[global].ty),
+                    a(ty)
+                ),
+            )),
+            None => {
+                globals.push(Global {
+                    name: name.clone(),
+                    ty,
+                });
+                self.lowering
+                    .globals
+                    .insert(name.clone(), globals.len() - 1);
+                Ok(globals.len() - 1)
+            }
+        }
+    }
+
+    /// a bool that is true if `e` is truthy
+    fn condition(&mut self, e: &Expr) -> Result<Value> {
+        let (val, ty) = self.expr(e)?;
+        Ok(self.truthy(val, ty))
+    }
+
+    fn truthy(&mut self, val: Value, ty: Type) -> Value {
+        match ty {
+            Type::Bool => val,
+            Type::Nil => self.constant(Type::Bool, 0),
+            Type::Int => {
+                let zero = self.constant(Type::Int, 0);
+                self.compare(CompareOp::Ne, val, zero)
+            }
+        }
+    }
+
+    fn compare(&mut self, op: CompareOp, lhs: Value, rhs: Value) -> Value {
+        let dst = self.value(Type::Bool);
+        self.emit(Inst::Compare { dst, op, lhs, rhs });
+        dst
+    }
+
+    fn expr(&mut self, e: &Expr) -> Result<(Value, Type)> {
+        stack::ensure(|| match e {
+            Expr::Literal(tok) => {
+                let (ty, value) = match &tok.token_type {
+                    TokenType::Integer(i) => (Type::Int, *i),
+                    TokenType::True => (Type::Bool, 1),
+                    TokenType::False => (Type::Bool, 0),
+                    TokenType::Nil => (Type::Nil, 0),
+                    _ => return Err(unsupported_type(tok)),
+                };
+                Ok((self.constant(ty, value), ty))
+            }
+            Expr::Grouping(inner) => self.expr(inner),
+            Expr::Unary(op, operand) => {
+                let (val, ty) = self.expr(operand)?;
+                if !matches!(op.token_type, TokenType::Minus) {
+                    let truthy = self.truthy(val, ty);
+                    let dst = self.value(Type::Bool);
+                    self.emit(Inst::Not {
+                        dst,
+                        operand: truthy,
+                    });
+                    return Ok((dst, Type::Bool));
+                }
+                if ty != Type::Int {
+                    return Err(mismatch(op, Type::Int, ty));
+                }
+                let dst = self.value(Type::Int);
+                let line = op.location_start.line() + 1;
+                self.emit(Inst::Neg {
+                    dst,
+                    operand: val,
+                    line,
+                });
+                Ok((dst, Type::Int))
+            }
+            Expr::Binary(l, op, r) => match op.token_type {
+                TokenType::And | TokenType::Or => self.logical(l, op, r),
+                _ => self.binary(l, op, r),
+            },
+            Expr::Variable(ident) => match self.lookup(ident.name()) {
+                Some(var) => Ok((self.read(var, self.current), var.ty)),
+                None => {
+                    let global = self.global_named(ident)?;
+                    let ty = self.lowering.module.globals[global].ty;
+                    let dst = self.value(ty);
+                    self.emit(Inst::Load { dst, global });
+                    Ok((dst, ty))
+                }
+            },
+            Expr::Assignment(ident, _, val) => {
+                let (val, found) = self.expr(val)?;
+                let (ty, global) = match self.lookup(ident.name()) {
+                    Some(var) => (var.ty, None),
+                    None => {
+                        let global = self.global_named(ident)?;
+                        (self.lowering.module.globals[global].ty, Some(global))
+                    }
+                };
+                if ty != found {
+                    return Err(CompileError::new(
+                        ident.token(),
+                        format!(
+                            "native code needs '{}' to stay {}, not become {}",
+                            ident.name(),
+                            a(ty),
+                            a(found)
+                        ),
+                    ));
+                }
+                match global {
+                    Some(global) => self.emit(Inst::Store { global, value: val }),
+                    None => {
+                        let var = self.lookup(ident.name()).expect("local was found");
+                        self.write(var, self.current, val);
+                    }
+                }
+                Ok((val, ty))
+            }
+            Expr::Call(callee, paren, args) => self.call(callee, paren, args),
+            Expr::Get(_, name) | Expr::Set(_, name, _) => {
+                Err(unsupported(name.token(), "properties"))
+            }
+            Expr::This(ident) | Expr::Super(ident, _) => Err(unsupported(ident.token(), "classes")),
+            Expr::List(bracket, _) => Err(unsupported(bracket, "lists")),
+            Expr::Map(brace, _) => Err(unsupported(brace, "maps")),
+            Expr::Index(_, bracket, _) | Expr::SetIndex(_, bracket, ..) => {
+                Err(unsupported(bracket, "indexing"))
+            }
+            Expr::Interpolation(parts, _) => Err(unsupported_type(&parts[0].0)),
+        })
+    }
+
+    /// the global a variable that isn't a local refers to
+    fn global_named(&self, ident: &Ident) -> Result<usize> {
+        let name = ident.name();
+        if let Some(global) = self.lowering.globals.get(name) {
+            return Ok(*global);
+        }
+        let message = match self.lowering.functions.contains_key(name) {
+            true => format!("native code can only call '{name}', not use it as a value"),
+            false => format!("native code doesn't support '{name}'"),
+        };
+        Err(CompileError::new(ident.token(), message))
+    }
+
+    /// `and` and `or`, which only evaluate the right side if the left
+    /// doesn't decide the result
+    fn logical(&mut self, l: &Expr, op: &Token, r: &Expr) -> Result<(Value, Type)> {
+        let lhs = self.condition(l)?;
+        let left = self.current;
+        let right = self.block();
+        let merge = self.block();
+        match op.token_type {
+            TokenType::And => self.branch(lhs, right, merge),
+            _ => self.branch(lhs, merge, right),
+        }
+        self.seal(right);
+        self.current = right;
+        let rhs = self.condition(r)?;
+        let right = self.current;
+        self.terminate(Terminator::Jump(merge));
+        self.seal(merge);
+        self.current = merge;
+
+        let dst = self.value(Type::Bool);
+        // the left side is the result when it decides
+        self.function.blocks[merge.0].phis.push(Phi {
+            dst,
+            incoming: vec![(left, lhs), (right, rhs)],
+        });
+        Ok((dst, Type::Bool))
+    }
+
+    fn binary(&mut self, l: &Expr, op: &Token, r: &Expr) -> Result<(Value, Type)> {
+        let (lhs, lty) = self.expr(l)?;
+        let (rhs, rty) = self.expr(r)?;
+        let arithmetic = match op.token_type {
+            TokenType::Plus => BinaryOp::Add,
+            TokenType::Minus => BinaryOp::Sub,
+            TokenType::Star => BinaryOp::Mul,
+            TokenType::Slash => BinaryOp::Div,
+            TokenType::Percent => BinaryOp::Rem,
+            _ => {
+                let (cmp, equality) = match op.token_type {
+                    TokenType::EqualEqual => (CompareOp::Eq, true),
+                    TokenType::BangEqual => (CompareOp::Ne, true),
+                    TokenType::Less => (CompareOp::Lt, false),
+                    TokenType::LessEqual => (CompareOp::Le, false),
+                    TokenType::Greater => (CompareOp::Gt, false),
+                    TokenType::GreaterEqual => (CompareOp::Ge, false),
+                    _ => unreachable!("binary operator"),
+                };
+                if equality && lty != rty {
+                    // values of different types are never equal
+                    let val = (cmp == CompareOp::Ne) as i64;
+                    return Ok((self.constant(Type::Bool, val), Type::Bool));
+                }
+                for ty in [lty, rty] {
+                    if !equality && ty != Type::Int {
+                        return Err(mismatch(op, Type::Int, ty));
+                    }
+                }
+                return Ok((self.compare(cmp, lhs, rhs), Type::Bool));
+            }
+        };
+        for ty in [lty, rty] {
+            if ty != Type::Int {
+                return Err(mismatch(op, Type::Int, ty));
+            }
+        }
+        let dst = self.value(Type::Int);
+        let line = op.location_start.line() + 1;
+        self.emit(Inst::Binary {
+            dst,
+            op: arithmetic,
+            lhs,
+            rhs,
+            line,
+        });
+        Ok((dst, Type::Int))
+    }
+
+    fn call(&mut self, callee: &Expr, paren: &Token, args: &[Expr]) -> Result<(Value, Type)> {
+        let function = match callee {
+            Expr::Variable(ident) if self.lookup(ident.name()).is_none() => {
+                match self.lowering.functions.get(ident.name()) {
+                    Some(function) => *function,
+                    None => return Err(unsupported(ident.token(), &format!("'{}'", ident.name()))),
+                }
+            }
+            _ => {
+                return Err(CompileError::new(
+                    callee.first_token(),
+                    "native code can only call functions declared at the top level",
+                ))
+            }
+        };
+        let declared = &self.lowering.module.functions[function];
+        let params: Vec<_> = declared
+            .params
+            .iter()
+            .map(|p| declared.types[p.0])
+            .collect();
+        let returns = declared.returns;
+        if params.len() != args.len() {
+            return Err(CompileError::new(
+                paren,
+                format!("expected {} arguments but got {}", params.len(), args.len()),
+            ));
+        }
+        let mut vals = vec![];
+        for (i, (arg, expected)) in args.iter().zip(params).enumerate() {
+            let (val, ty) = self.expr(arg)?;
+            if ty != expected {
+                let error = mismatch(arg.first_token(), expected, ty);
+                return Err(match self.lowering.defaulted[function].params[i] {
+                    true => defaulted_to_int(error, ty),
+                    false => error,
+                });
+            }
+            vals.push(val);
+        }
+        let dst = self.value(returns);
+        let line = paren.location_start.line() + 1;
+        self.emit(Inst::Call {
+            dst,
+            function,
+            args: vals,
+            line,
+        });
+        Ok((dst, returns))
+    }
+}
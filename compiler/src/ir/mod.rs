@@ -0,0 +1,461 @@
+//! an intermediate representation in static single assignment form for
+//! the int and bool subset of the language. scripts are lowered to it
+//! from the syntax tree, improved by [`PassManager`] and turned into
+//! assembly by [`crate::native`]
+
+mod lower;
+mod passes;
+
+use std::fmt;
+
+pub use lower::lower;
+pub use passes::{
+    FoldConstants, MergeBlocks, Pass, PassManager, RemoveDeadValues, RemoveUnreachable,
+    SimplifyPhis,
+};
+
+/// the most parameters a function can have, those passed in registers
+pub const MAX_PARAMS: usize = 6;
+
+/// the types values can have in native code. nil is what functions
+/// without a return value return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+    Nil,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Bool => "bool",
+            Type::Nil => "nil",
+        };
+        f.write_str(name)
+    }
+}
+
+/// the result of an instruction, phi or parameter. assigned exactly once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Value(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub usize);
+
+/// a whole script. the first function holds its top level code
+#[derive(Debug, Default)]
+pub struct Module {
+    /// variables declared at the top level, which functions share
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+    /// string literals that are printed
+    pub strings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Global {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Value>,
+    pub returns: Type,
+    /// the first block is where the function starts
+    pub blocks: Vec<Block>,
+    /// the type of every value, indexed by it
+    pub types: Vec<Type>,
+}
+
+impl Function {
+    /// the blocks whose terminator leads to each block
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                preds[succ.0].push(BlockId(i));
+            }
+        }
+        preds
+    }
+
+    /// replaces every use of `old` with `new`
+    pub fn replace_uses(&mut self, old: Value, new: Value) {
+        for block in &mut self.blocks {
+            for operand in block.operands_mut() {
+                if *operand == old {
+                    *operand = new;
+                }
+            }
+        }
+    }
+}
+
+/// a straight line of instructions. control only enters at the top and
+/// leaves through the terminator
+#[derive(Debug)]
+pub struct Block {
+    pub phis: Vec<Phi>,
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+impl Block {
+    pub fn new() -> Self {
+        Self {
+            phis: vec![],
+            insts: vec![],
+            terminator: Terminator::Unreachable,
+        }
+    }
+
+    /// every value the block reads
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        let mut operands: Vec<_> = self
+            .phis
+            .iter_mut()
+            .flat_map(|phi| phi.incoming.iter_mut().map(|(_, val)| val))
+            .collect();
+        for inst in &mut self.insts {
+            operands.extend(inst.operands_mut());
+        }
+        if let Terminator::Branch { cond, .. } | Terminator::Return(cond) = &mut self.terminator {
+            operands.push(cond);
+        }
+        operands
+    }
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// picks a value depending on the block control came from
+#[derive(Debug)]
+pub struct Phi {
+    pub dst: Value,
+    pub incoming: Vec<(BlockId, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        }
+    }
+
+    /// the result, `None` if the operation fails at runtime
+    pub fn apply(self, lhs: i64, rhs: i64) -> Option<i64> {
+        match self {
+            BinaryOp::Add => lhs.checked_add(rhs),
+            BinaryOp::Sub => lhs.checked_sub(rhs),
+            BinaryOp::Mul => lhs.checked_mul(rhs),
+            BinaryOp::Div => lhs.checked_div(rhs),
+            BinaryOp::Rem => lhs.checked_rem(rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    pub fn apply(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Inst {
+    /// ints are themselves, bools 0 or 1 and nil 0
+    Const {
+        dst: Value,
+        value: i64,
+    },
+    /// integer arithmetic. overflows and division by zero stop the
+    /// program with an error naming `line`
+    Binary {
+        dst: Value,
+        op: BinaryOp,
+        lhs: Value,
+        rhs: Value,
+        line: u64,
+    },
+    Neg {
+        dst: Value,
+        operand: Value,
+        line: u64,
+    },
+    Compare {
+        dst: Value,
+        op: CompareOp,
+        lhs: Value,
+        rhs: Value,
+    },
+    /// negates a bool
+    Not {
+        dst: Value,
+        operand: Value,
+    },
+    /// calls nested too deep stop the program with an error naming
+    /// `line`
+    Call {
+        dst: Value,
+        function: usize,
+        args: Vec<Value>,
+        line: u64,
+    },
+    Load {
+        dst: Value,
+        global: usize,
+    },
+    Store {
+        global: usize,
+        value: Value,
+    },
+    /// prints a value as `print` does for its type
+    Print {
+        value: Value,
+    },
+    PrintString {
+        string: usize,
+    },
+}
+
+impl Inst {
+    /// the value the instruction defines
+    pub fn dst(&self) -> Option<Value> {
+        match self {
+            Inst::Const { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::Neg { dst, .. }
+            | Inst::Compare { dst, .. }
+            | Inst::Not { dst, .. }
+            | Inst::Call { dst, .. }
+            | Inst::Load { dst, .. } => Some(*dst),
+            Inst::Store { .. } | Inst::Print { .. } | Inst::PrintString { .. } => None,
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Inst::Binary { lhs, rhs, .. } | Inst::Compare { lhs, rhs, .. } => vec![lhs, rhs],
+            Inst::Neg { operand, .. } | Inst::Not { operand, .. } => vec![operand],
+            Inst::Call { args, .. } => args.iter_mut().collect(),
+            Inst::Store { value, .. } | Inst::Print { value } => vec![value],
+            Inst::Const { .. } | Inst::Load { .. } | Inst::PrintString { .. } => vec![],
+        }
+    }
+
+    /// whether the instruction does nothing but define its value, so
+    /// it can go if the value isn't used
+    pub fn is_pure(&self) -> bool {
+        matches!(
+            self,
+            Inst::Const { .. } | Inst::Compare { .. } | Inst::Not { .. } | Inst::Load { .. }
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// goes to `then` if `cond` is true
+    Branch {
+        cond: Value,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    Return(Value),
+    /// stops the program with a runtime error on a line of the script
+    Error {
+        line: u64,
+        message: String,
+    },
+    /// control never gets to the end of the block
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::Error { .. } | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Error { .. } | Terminator::Unreachable => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// the textual form of the representation:
+///
+/// ```text
+/// global @count: int
+///
+/// fn @double(%0: int) -> int {
+/// bb0:
+///     %1 = const 2
+///     %2 = mul %0, %1
+///     ret %2
+/// }
+/// ```
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global @{}: {}", global.name, global.ty)?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            self.function(f, function)?;
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    fn function(&self, f: &mut fmt::Formatter<'_>, function: &Function) -> fmt::Result {
+        let params: Vec<_> = function
+            .params
+            .iter()
+            .map(|param| format!("{param}: {}", function.types[param.0]))
+            .collect();
+        writeln!(
+            f,
+            "fn @{}({}) -> {} {{",
+            function.name,
+            params.join(", "),
+            function.returns
+        )?;
+        for (i, block) in function.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for phi in &block.phis {
+                let incoming: Vec<_> = phi
+                    .incoming
+                    .iter()
+                    .map(|(block, val)| format!("[{block}: {val}]"))
+                    .collect();
+                writeln!(
+                    f,
+                    "    {} = phi {} {}",
+                    phi.dst,
+                    function.types[phi.dst.0],
+                    incoming.join(", ")
+                )?;
+            }
+            for inst in &block.insts {
+                write!(f, "    ")?;
+                self.inst(f, inst)?;
+                writeln!(f)?;
+            }
+            write!(f, "    ")?;
+            match &block.terminator {
+                Terminator::Jump(target) => writeln!(f, "jump {target}")?,
+                Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise,
+                } => writeln!(f, "br {cond}, {then}, {otherwise}")?,
+                Terminator::Return(val) => writeln!(f, "ret {val}")?,
+                Terminator::Error { line, message } => {
+                    writeln!(f, "error on line {line}, {message:?}")?
+                }
+                Terminator::Unreachable => writeln!(f, "unreachable")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+
+    fn inst(&self, f: &mut fmt::Formatter<'_>, inst: &Inst) -> fmt::Result {
+        match inst {
+            Inst::Const { dst, value } => write!(f, "{dst} = const {value}"),
+            Inst::Binary {
+                dst, op, lhs, rhs, ..
+            } => {
+                let name = format!("{op:?}").to_lowercase();
+                write!(f, "{dst} = {name} {lhs}, {rhs}")
+            }
+            Inst::Neg { dst, operand, .. } => write!(f, "{dst} = neg {operand}"),
+            Inst::Compare { dst, op, lhs, rhs } => {
+                let name = format!("{op:?}").to_lowercase();
+                write!(f, "{dst} = {name} {lhs}, {rhs}")
+            }
+            Inst::Not { dst, operand } => write!(f, "{dst} = not {operand}"),
+            Inst::Call {
+                dst,
+                function,
+                args,
+                ..
+            } => {
+                let args: Vec<_> = args.iter().map(Value::to_string).collect();
+                let name = &self.functions[*function].name;
+                write!(f, "{dst} = call @{name}({})", args.join(", "))
+            }
+            Inst::Load { dst, global } => {
+                write!(f, "{dst} = load @{}", self.globals[*global].name)
+            }
+            Inst::Store { global, value } => {
+                write!(f, "store @{}, {value}", self.globals[*global].name)
+            }
+            Inst::Print { value } => write!(f, "print {value}"),
+            Inst::PrintString { string } => write!(f, "print {:?}", self.strings[*string]),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{BlockId, Function, Inst, Module, Terminator, Value};
+
+/// a transformation of a function that keeps what it does
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    /// returns whether the function changed
+    fn run(&self, function: &mut Function) -> bool;
+}
+
+/// runs passes over each function of a module, again and again until
+/// none of them changes it. the default runs all the passes here
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    /// a pass manager without any passes
+    pub fn new() -> Self {
+        Self { passes: vec![] }
+    }
+
+    pub fn with(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn run(&self, module: &mut Module) {
+        for function in &mut module.functions {
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for pass in &self.passes {
+                    changed |= pass.run(function);
+                }
+            }
+        }
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+            .with(SimplifyPhis)
+            .with(FoldConstants)
+            .with(MergeBlocks)
+            .with(RemoveUnreachable)
+            .with(RemoveDeadValues)
+    }
+}
+
+/// replaces phis that always pick the same value with that value
+pub struct SimplifyPhis;
+
+impl Pass for SimplifyPhis {
+    fn name(&self) -> &'static str {
+        "simplify-phis"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let mut changed = false;
+        while let Some((block, phi, val)) = trivial_phi(function) {
+            function.blocks[block].phis.retain(|p| p.dst != phi);
+            function.replace_uses(phi, val);
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// a phi whose incoming values are all the same one or the phi itself,
+/// with its block and the value
+fn trivial_phi(function: &Function) -> Option<(usize, Value, Value)> {
+    for (i, block) in function.blocks.iter().enumerate() {
+        'phis: for phi in &block.phis {
+            let mut same = None;
+            for &(_, val) in &phi.incoming {
+                if val == phi.dst || Some(val) == same {
+                    continue;
+                }
+                if same.is_some() {
+                    continue 'phis;
+                }
+                same = Some(val);
+            }
+            if let Some(same) = same {
+                return Some((i, phi.dst, same));
+            }
+        }
+    }
+    None
+}
+
+/// computes operations on constants, leaving those that fail for when
+/// the program runs, and turns branches on constants into jumps
+pub struct FoldConstants;
+
+impl Pass for FoldConstants {
+    fn name(&self) -> &'static str {
+        "fold-constants"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let mut consts = HashMap::new();
+        for block in &function.blocks {
+            for inst in &block.insts {
+                if let Inst::Const { dst, value } = inst {
+                    consts.insert(*dst, *value);
+                }
+            }
+        }
+
+        let mut changed = false;
+        for block in &mut function.blocks {
+            for inst in &mut block.insts {
+                let folded = match *inst {
+                    Inst::Binary {
+                        dst, op, lhs, rhs, ..
+                    } => consts
+                        .get(&lhs)
+                        .zip(consts.get(&rhs))
+                        .and_then(|(&lhs, &rhs)| op.apply(lhs, rhs))
+                        .map(|value| (dst, value)),
+                    Inst::Neg { dst, operand, .. } => consts
+                        .get(&operand)
+                        .and_then(|val| val.checked_neg())
+                        .map(|value| (dst, value)),
+                    Inst::Compare { dst, op, lhs, rhs } => consts
+                        .get(&lhs)
+                        .zip(consts.get(&rhs))
+                        .map(|(&lhs, &rhs)| (dst, op.apply(lhs, rhs) as i64)),
+                    Inst::Not { dst, operand } => {
+                        consts.get(&operand).map(|&val| (dst, (val == 0) as i64))
+                    }
+                    _ => None,
+                };
+                if let Some((dst, value)) = folded {
+                    *inst = Inst::Const { dst, value };
+                    consts.insert(dst, value);
+                    changed = true;
+                }
+            }
+        }
+
+        for i in 0..function.blocks.len() {
+            let Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } = function.blocks[i].terminator
+            else {
+                continue;
+            };
+            let Some(&cond) = consts.get(&cond) else {
+                continue;
+            };
+            let (taken, skipped) = match cond != 0 {
+                true => (then, otherwise),
+                false => (otherwise, then),
+            };
+            function.blocks[i].terminator = Terminator::Jump(taken);
+            if taken != skipped {
+                for phi in &mut function.blocks[skipped.0].phis {
+                    phi.incoming.retain(|(pred, _)| pred.0 != i);
+                }
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// joins a block to the one before it when that is the only block
+/// jumping to it
+pub struct MergeBlocks;
+
+impl Pass for MergeBlocks {
+    fn name(&self) -> &'static str {
+        "merge-blocks"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let mut changed = false;
+        loop {
+            let preds = function.predecessors();
+            let merge = function.blocks.iter().enumerate().find_map(|(i, block)| {
+                let Terminator::Jump(next) = block.terminator else {
+                    return None;
+                };
+                let alone = next.0 != i && next.0 != 0 && preds[next.0] == [BlockId(i)];
+                (alone && function.blocks[next.0].phis.is_empty()).then_some((i, next))
+            });
+            let Some((i, next)) = merge else {
+                return changed;
+            };
+
+            let insts = std::mem::take(&mut function.blocks[next.0].insts);
+            let terminator = std::mem::replace(
+                &mut function.blocks[next.0].terminator,
+                Terminator::Unreachable,
+            );
+            for succ in terminator.successors() {
+                for phi in &mut function.blocks[succ.0].phis {
+                    for (pred, _) in &mut phi.incoming {
+                        if *pred == next {
+                            *pred = BlockId(i);
+                        }
+                    }
+                }
+            }
+            let block = &mut function.blocks[i];
+            block.insts.extend(insts);
+            block.terminator = terminator;
+            changed = true;
+        }
+    }
+}
+
+/// removes the blocks control can't get to from the start
+pub struct RemoveUnreachable;
+
+impl Pass for RemoveUnreachable {
+    fn name(&self) -> &'static str {
+        "remove-unreachable"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let mut reachable = vec![false; function.blocks.len()];
+        let mut stack = vec![BlockId(0)];
+        while let Some(block) = stack.pop() {
+            if !std::mem::replace(&mut reachable[block.0], true) {
+                stack.extend(function.blocks[block.0].terminator.successors());
+            }
+        }
+        if reachable.iter().all(|&r| r) {
+            return false;
+        }
+
+        let mut renamed = vec![None; reachable.len()];
+        let mut count = 0;
+        for (i, &r) in reachable.iter().enumerate() {
+            if r {
+                renamed[i] = Some(BlockId(count));
+                count += 1;
+            }
+        }
+        let blocks = std::mem::take(&mut function.blocks);
+        for (mut block, r) in blocks.into_iter().zip(&reachable) {
+            if !r {
+                continue;
+            }
+            for phi in &mut block.phis {
+                phi.incoming.retain(|(pred, _)| reachable[pred.0]);
+                for (pred, _) in &mut phi.incoming {
+                    *pred = renamed[pred.0].expect("predecessor is reachable");
+                }
+            }
+            for succ in block.terminator.successors_mut() {
+                *succ = renamed[succ.0].expect("successor is reachable");
+            }
+            function.blocks.push(block);
+        }
+        true
+    }
+}
+
+/// removes phis and instructions without side effects whose values
+/// aren't used
+pub struct RemoveDeadValues;
+
+impl Pass for RemoveDeadValues {
+    fn name(&self) -> &'static str {
+        "remove-dead-values"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let used: HashSet<Value> = function
+            .blocks
+            .iter_mut()
+            .flat_map(|block| block.operands_mut().into_iter().map(|val| *val))
+            .collect();
+        let mut changed = false;
+        for block in &mut function.blocks {
+            let phis = block.phis.len();
+            block.phis.retain(|phi| used.contains(&phi.dst));
+            let insts = block.insts.len();
+            block.insts.retain(|inst| {
+                !inst.is_pure() || inst.dst().is_some_and(|dst| used.contains(&dst))
+            });
+            changed |= phis != block.phis.len() || insts != block.insts.len();
+        }
+        changed
+    }
+}
@@ -0,0 +1,26 @@
+//! a lox interpreter that can be embedded into rust programs through
+//! [`Engine`]
+
+pub mod ast;
+pub mod checker;
+pub mod debugger;
+pub mod diagnostic;
+mod engine;
+pub mod formatter;
+pub mod interpreter;
+pub mod ir;
+pub mod lsp;
+pub mod native;
+pub mod optimizer;
+pub mod parser;
+pub mod repl;
+pub mod resolver;
+pub mod scanner;
+mod stack;
+mod stdlib;
+pub mod value;
+pub mod vm;
+
+pub use engine::{
+    parse, BackendKind, Engine, FromValue, GetError, IntoNative, IntoValue, NativeResult, RunError,
+};
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{annotated, Block, Class, Expr, Function, Ident, Statement},
+    checker,
+    diagnostic::Diagnostic,
+    parser, resolver,
+    scanner::{self, Location, Token},
+    stack,
+};
+
+/// a zero based line and a column counted in characters
+pub type Pos = (u64, u64);
+
+const END: Pos = (u64::MAX, u64::MAX);
+
+fn pos(location: Location) -> Pos {
+    (location.line(), location.column())
+}
+
+/// a stretch of source, from the first character to the last one
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    fn token(token: &Token) -> Self {
+        Self {
+            start: token.location_start,
+            end: token.location_end,
+        }
+    }
+
+    fn between(start: &Token, end: &Token) -> Self {
+        Self {
+            start: start.location_start,
+            end: end.location_end,
+        }
+    }
+
+    /// whether a cursor at `pos` is on the span. a cursor right after it
+    /// counts, which is where it is after typing a name
+    fn touches(&self, (line, column): Pos) -> bool {
+        self.start.line() == line
+            && self.start.column() <= column
+            && column <= self.end.column() + 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    Method,
+}
+
+/// something declared in the script
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// the name in the declaration
+    pub selection: Span,
+    /// the whole declaration
+    pub range: Span,
+    /// the declaration the way it is shown on hover
+    pub detail: String,
+    pub doc: Option<String>,
+    /// the declarations inside this one that show up in an outline
+    pub children: Vec<usize>,
+    /// where the symbol can be referred to by its name. methods are
+    /// reached through an instance and never are
+    visible: Option<(Pos, Pos)>,
+}
+
+/// what the editor is told about a document. built from the partial
+/// syntax tree when the script doesn't parse
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub symbols: Vec<Symbol>,
+    /// the symbols at the top of an outline
+    pub outline: Vec<usize>,
+    /// every name referring to a symbol, declarations included
+    occurrences: Vec<(Span, usize)>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let tokens = match scanner::scan(source.to_string()) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                return Self {
+                    diagnostics: vec![e.diagnostic()],
+                    ..Self::default()
+                }
+            }
+        };
+
+        let mut diagnostics = vec![];
+        let stmts = match parser::parse(tokens) {
+            Ok(stmts) => {
+                let stmts: Vec<_> = stmts.collect();
+                if let Err(errors) = resolver::resolve(&stmts) {
+                    diagnostics.extend(errors.iter().map(resolver::Error::diagnostic));
+                }
+                if let Err(errors) = checker::check(&stmts) {
+                    diagnostics.extend(errors);
+                }
+                stmts
+            }
+            Err(errors) => {
+                diagnostics.extend(errors.errors().iter().map(parser::Error::diagnostic));
+                errors.into_partial()
+            }
+        };
+
+        let mut walker = Walker::default();
+        walker.scopes.push(Scope::new(END));
+        for stmt in &stmts {
+            stack::ensure(|| walker.statement(stmt));
+        }
+        // globals may be used above their declaration, in functions
+        // called after it
+        for (span, name) in std::mem::take(&mut walker.pending) {
+            if let Some(&symbol) = walker.scopes[0].names.get(&name) {
+                walker.occurrences.push((span, symbol));
+            }
+        }
+
+        Self {
+            diagnostics,
+            symbols: walker.symbols,
+            outline: walker.outline,
+            occurrences: walker.occurrences,
+        }
+    }
+
+    /// the symbol whose name is at `pos`, and where that name is
+    pub fn symbol_at(&self, pos: Pos) -> Option<(usize, Span)> {
+        self.occurrences
+            .iter()
+            .find(|(span, _)| span.touches(pos))
+            .map(|(span, symbol)| (*symbol, *span))
+    }
+
+    /// every place the symbol is named, its declaration included
+    pub fn references(&self, symbol: usize) -> impl Iterator<Item = Span> + '_ {
+        self.occurrences
+            .iter()
+            .filter(move |(_, s)| *s == symbol)
+            .map(|(span, _)| *span)
+    }
+
+    /// the symbols that can be referred to by name at `pos`
+    pub fn visible(&self, pos: Pos) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter().filter(move |symbol| {
+            symbol
+                .visible
+                .is_some_and(|(start, end)| start <= pos && pos <= end)
+        })
+    }
+}
+
+struct Scope {
+    names: HashMap<String, usize>,
+    /// where the scope closes
+    end: Pos,
+}
+
+impl Scope {
+    fn new(end: Pos) -> Self {
+        Self {
+            names: HashMap::new(),
+            end,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Walker {
+    symbols: Vec<Symbol>,
+    occurrences: Vec<(Span, usize)>,
+    outline: Vec<usize>,
+    /// innermost last. the first one holds the globals
+    scopes: Vec<Scope>,
+    /// names that weren't declared yet where they were used
+    pending: Vec<(Span, String)>,
+    /// the declaration new declarations are outlined under
+    parent: Option<usize>,
+    /// the classes whose methods are being walked, innermost last
+    classes: Vec<usize>,
+    superclasses: HashMap<usize, usize>,
+}
+
+impl Walker {
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(block) => self.block(block),
+            Statement::Function(fun) => {
+                let range = Span::between(fun.name.token(), &fun.body.close);
+                let detail = format!("fun {}{}", fun.name.name(), fun.signature());
+                let symbol = self.declare(&fun.name, SymbolKind::Function, range, detail);
+                self.function(fun, symbol);
+            }
+            Statement::Class(class) => self.class(class),
+            Statement::Return(_, val) => {
+                if let Some(val) = val {
+                    self.expr(val);
+                }
+            }
+            Statement::If(cond, then, otherwise) => {
+                self.expr(cond);
+                self.statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            Statement::While(cond, body) => {
+                self.expr(cond);
+                self.statement(body);
+            }
+            Statement::ForIn(ident, _, items, body) => {
+                self.expr(items);
+                self.scopes.push(Scope::new(end_of(body)));
+                let detail = format!("var {}", ident.name());
+                let range = Span::token(ident.token());
+                self.declare(ident, SymbolKind::Variable, range, detail);
+                self.statement(body);
+                self.scopes.pop();
+            }
+            Statement::Var(ident, ty, init) => {
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                let detail = format!("var {}", annotated(ident.name(), ty.as_ref()));
+                let range = Span::token(ident.token());
+                self.declare(ident, SymbolKind::Variable, range, detail);
+            }
+            Statement::Print(expr) | Statement::Expr(expr) => self.expr(expr),
+            Statement::Empty => (),
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes
+            .push(Scope::new(pos(block.close.location_start)));
+        for stmt in &block.stmts {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    /// walks the parameters and body of a function or method
+    fn function(&mut self, fun: &Function, symbol: usize) {
+        let parent = self.parent.replace(symbol);
+        self.scopes
+            .push(Scope::new(pos(fun.body.close.location_start)));
+        for param in &fun.params {
+            let detail = format!("parameter {} of {}", param.name(), fun.name.name());
+            let range = Span::token(param.token());
+            self.declare(param, SymbolKind::Parameter, range, detail);
+        }
+        for stmt in &fun.body.stmts {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+        self.parent = parent;
+    }
+
+    fn class(&mut self, class: &Class) {
+        let name = class.name.name();
+        let detail = match &class.superclass {
+            Some(superclass) => format!("class {name} < {}", superclass.name()),
+            None => format!("class {name}"),
+        };
+        let range = Span::between(class.name.token(), &class.close);
+        let symbol = self.declare(&class.name, SymbolKind::Class, range, detail);
+        if let Some(superclass) = &class.superclass {
+            if let Some(superclass) = self.reference(superclass) {
+                self.superclasses.insert(symbol, superclass);
+            }
+        }
+
+        let parent = self.parent.replace(symbol);
+        let methods: Vec<_> = class
+            .methods
+            .iter()
+            .map(|method| {
+                let detail = format!("{name}.{}{}", method.name.name(), method.signature());
+                let range = Span::between(method.name.token(), &method.body.close);
+                let symbol = self.add(&method.name, SymbolKind::Method, range, detail, None);
+                self.occurrences
+                    .push((Span::token(method.name.token()), symbol));
+                symbol
+            })
+            .collect();
+        self.parent = parent;
+
+        // methods can call each other in any order, so they are all
+        // known before any body is walked
+        self.classes.push(symbol);
+        for (method, symbol) in class.methods.iter().zip(methods) {
+            self.function(method, symbol);
+        }
+        self.classes.pop();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        stack::ensure(|| match expr {
+            Expr::Unary(_, e) | Expr::Grouping(e) => self.expr(e),
+            Expr::Binary(l, _, r) | Expr::Index(l, _, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            Expr::Literal(_) | Expr::This(_) => (),
+            Expr::Variable(ident) => {
+                self.reference(ident);
+            }
+            Expr::Assignment(ident, _, val) => {
+                self.expr(val);
+                self.reference(ident);
+            }
+            Expr::Call(callee, _, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Get(object, name) => {
+                self.expr(object);
+                self.this_method(object, name);
+            }
+            Expr::Set(object, name, val) => {
+                self.expr(object);
+                self.this_method(object, name);
+                self.expr(val);
+            }
+            Expr::Super(_, method) => {
+                let superclass = self
+                    .classes
+                    .last()
+                    .and_then(|class| self.superclasses.get(class));
+                if let Some(method_symbol) = superclass.and_then(|&s| self.method(s, method.name()))
+                {
+                    self.occurrences
+                        .push((Span::token(method.token()), method_symbol));
+                }
+            }
+            Expr::List(_, items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, val) in entries {
+                    self.expr(key);
+                    self.expr(val);
+                }
+            }
+            Expr::SetIndex(object, _, index, val) => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(val);
+            }
+            Expr::Interpolation(parts, _) => {
+                for (_, e) in parts {
+                    self.expr(e);
+                }
+            }
+        })
+    }
+
+    /// links `this.name` to a method of the class it is used in. other
+    /// properties depend on the instance and aren't known
+    fn this_method(&mut self, object: &Expr, name: &Ident) {
+        let (Expr::This(_), Some(&class)) = (object, self.classes.last()) else {
+            return;
+        };
+        if let Some(method) = self.method(class, name.name()) {
+            self.occurrences.push((Span::token(name.token()), method));
+        }
+    }
+
+    /// looks a method up in a class and then its superclasses
+    fn method(&self, class: usize, name: &str) -> Option<usize> {
+        let mut class = Some(class);
+        // a class can't inherit from itself, but a script can say so
+        for _ in 0..self.symbols.len() {
+            let current = class?;
+            let method = self.symbols[current]
+                .children
+                .iter()
+                .copied()
+                .find(|&child| {
+                    let child = &self.symbols[child];
+                    child.kind == SymbolKind::Method && child.name == name
+                });
+            if method.is_some() {
+                return method;
+            }
+            class = self.superclasses.get(&current).copied();
+        }
+        None
+    }
+
+    /// adds a symbol reachable by name from here to the end of the scope
+    fn declare(&mut self, ident: &Ident, kind: SymbolKind, range: Span, detail: String) -> usize {
+        let scope = self.scopes.last().expect("the globals are never popped");
+        let visible = match self.scopes.len() {
+            1 => ((0, 0), END),
+            _ => (pos(ident.token().location_start), scope.end),
+        };
+        let symbol = self.add(ident, kind, range, detail, Some(visible));
+        self.occurrences.push((Span::token(ident.token()), symbol));
+        self.scopes
+            .last_mut()
+            .expect("the globals are never popped")
+            .names
+            .insert(ident.name().clone(), symbol);
+        symbol
+    }
+
+    fn add(
+        &mut self,
+        ident: &Ident,
+        kind: SymbolKind,
+        range: Span,
+        detail: String,
+        visible: Option<(Pos, Pos)>,
+    ) -> usize {
+        let symbol = self.symbols.len();
+        self.symbols.push(Symbol {
+            name: ident.name().clone(),
+            kind,
+            selection: Span::token(ident.token()),
+            range,
+            detail,
+            doc: ident.doc().map(str::to_string),
+            children: vec![],
+            visible,
+        });
+
+        // locals would clutter the outline
+        let outlined = match kind {
+            SymbolKind::Function | SymbolKind::Class | SymbolKind::Method => true,
+            SymbolKind::Variable => self.scopes.len() == 1,
+            SymbolKind::Parameter => false,
+        };
+        if outlined {
+            match self.parent {
+                Some(parent) => self.symbols[parent].children.push(symbol),
+                None => self.outline.push(symbol),
+            }
+        }
+        symbol
+    }
+
+    /// links a name to the innermost declaration of it
+    fn reference(&mut self, ident: &Ident) -> Option<usize> {
+        let span = Span::token(ident.token());
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(ident.name()).copied());
+        match found {
+            Some(symbol) => self.occurrences.push((span, symbol)),
+            None => self.pending.push((span, ident.name().clone())),
+        }
+        found
+    }
+}
+
+/// where the block of a loop closes
+fn end_of(body: &Statement) -> Pos {
+    match body {
+        Statement::Block(block) => pos(block.close.location_start),
+        _ => END,
+    }
+}
@@ -0,0 +1,527 @@
+mod analysis;
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+use crate::{diagnostic::Diagnostic, scanner::Location, value::RValue, Engine};
+use analysis::{Analysis, Pos, Span, Symbol, SymbolKind};
+use serde_json::{json, Value};
+
+const KEYWORDS: [&str; 17] = [
+    "and", "class", "else", "false", "for", "fun", "if", "in", "nil", "or", "print", "return",
+    "super", "this", "true", "var", "while",
+];
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const CONTENT_LENGTH: &str = "Content-Length:";
+
+/// longer messages are skipped instead of being read into memory
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+/// a language server speaking JSON-RPC over stdin and stdout, or any
+/// other pair of streams. documents are synced in full and analysed
+/// again on every change
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    /// the built-in functions, offered in completions
+    natives: Vec<String>,
+    shutdown: bool,
+}
+
+struct Document {
+    text: String,
+    /// byte offset of the start of every line
+    lines: Vec<usize>,
+    analysis: Analysis,
+}
+
+type Response = Result<Value, (i64, String)>;
+
+impl Server {
+    pub fn new() -> Self {
+        let natives = Engine::new()
+            .globals()
+            .into_iter()
+            .filter(|(_, val)| matches!(val, RValue::Native(_)))
+            .map(|(name, _)| name)
+            .collect();
+        Self {
+            natives,
+            ..Self::default()
+        }
+    }
+
+    /// serves over stdin and stdout until the client says to exit
+    pub fn run(&mut self) -> anyhow::Result<ExitCode> {
+        self.serve(io::stdin().lock(), io::stdout().lock())
+    }
+
+    /// serves the messages read from `input` until the client says to
+    /// exit or the input ends, writing the replies to `output`
+    pub fn serve(
+        &mut self,
+        input: impl BufRead,
+        mut output: impl Write,
+    ) -> anyhow::Result<ExitCode> {
+        let mut messages = Messages {
+            input,
+            resumed: false,
+        };
+        while let Some(message) = messages.read()? {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    let response = json!({
+                        "jsonrpc": "2.0",
+                        "id": null,
+                        "error": {"code": PARSE_ERROR, "message": e},
+                    });
+                    write_message(&mut output, &response)?;
+                    continue;
+                }
+            };
+            let method = message["method"].as_str().unwrap_or_default();
+            let params = &message["params"];
+            match message.get("id") {
+                // the client answering a request, the server never makes any
+                Some(_) if method.is_empty() => (),
+                Some(id) => {
+                    let response = match self.request(method, params) {
+                        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                        Err((code, message)) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {"code": code, "message": message},
+                        }),
+                    };
+                    write_message(&mut output, &response)?;
+                }
+                None if method == "exit" => {
+                    return Ok(match self.shutdown {
+                        true => ExitCode::SUCCESS,
+                        false => ExitCode::FAILURE,
+                    });
+                }
+                None => {
+                    for notification in self.notification(method, params) {
+                        write_message(&mut output, &notification)?;
+                    }
+                }
+            }
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Response {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                },
+                "serverInfo": {"name": "lox", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, doc, pos) = self.position(params)?;
+                Ok(match doc.analysis.symbol_at(pos) {
+                    Some((symbol, _)) => {
+                        let symbol = &doc.analysis.symbols[symbol];
+                        json!({"uri": uri, "range": doc.range(symbol.selection)})
+                    }
+                    None => Value::Null,
+                })
+            }
+            "textDocument/references" => {
+                let (uri, doc, pos) = self.position(params)?;
+                let Some((symbol, _)) = doc.analysis.symbol_at(pos) else {
+                    return Ok(Value::Null);
+                };
+                let declaration = doc.analysis.symbols[symbol].selection.start;
+                let include = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                let locations: Vec<_> = doc
+                    .analysis
+                    .references(symbol)
+                    .filter(|span| include || span.start != declaration)
+                    .map(|span| json!({"uri": uri, "range": doc.range(span)}))
+                    .collect();
+                Ok(json!(locations))
+            }
+            "textDocument/hover" => {
+                let (_, doc, pos) = self.position(params)?;
+                Ok(match doc.analysis.symbol_at(pos) {
+                    Some((symbol, span)) => {
+                        let symbol = &doc.analysis.symbols[symbol];
+                        let mut value = format!("```lox\n{}\n```", symbol.detail);
+                        if let Some(doc) = &symbol.doc {
+                            value.push_str("\n\n");
+                            value.push_str(doc);
+                        }
+                        json!({
+                            "contents": {"kind": "markdown", "value": value},
+                            "range": doc.range(span),
+                        })
+                    }
+                    None => Value::Null,
+                })
+            }
+            "textDocument/documentSymbol" => {
+                let (_, doc) = self.document(params)?;
+                let symbols: Vec<_> = doc
+                    .analysis
+                    .outline
+                    .iter()
+                    .map(|&symbol| doc.outline(symbol))
+                    .collect();
+                Ok(json!(symbols))
+            }
+            "textDocument/completion" => {
+                let (_, doc, pos) = self.position(params)?;
+                Ok(json!(self.completions(doc, pos)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method '{method}'"))),
+        }
+    }
+
+    /// handles a notification, returns the ones to send back
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // the whole text is sent, so only the last change matters
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, vec![])];
+            }
+            _ => None,
+        };
+        let Some(text) = text else {
+            return vec![];
+        };
+
+        let doc = Document::new(text.to_string());
+        let diagnostics = doc
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| doc.diagnostic(diagnostic))
+            .collect();
+        self.documents.insert(uri.to_string(), doc);
+        vec![publish(uri, diagnostics)]
+    }
+
+    fn document<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a Document), (i64, String)> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or((INVALID_PARAMS, "missing the document uri".to_string()))?;
+        let doc = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("'{uri}' isn't open")))?;
+        Ok((uri, doc))
+    }
+
+    /// the document and position a request is about
+    fn position<'a>(
+        &'a self,
+        params: &'a Value,
+    ) -> Result<(&'a str, &'a Document, Pos), (i64, String)> {
+        let (uri, doc) = self.document(params)?;
+        let position = &params["position"];
+        let (Some(line), Some(character)) =
+            (position["line"].as_u64(), position["character"].as_u64())
+        else {
+            return Err((INVALID_PARAMS, "missing the position".to_string()));
+        };
+        Ok((uri, doc, (line, doc.column(line, character))))
+    }
+
+    fn completions(&self, doc: &Document, (line, column): Pos) -> Vec<Value> {
+        let text = doc.line(line);
+        let before: Vec<_> = text.chars().take(column as usize).collect();
+        let word = before
+            .iter()
+            .rev()
+            .take_while(|c| c.is_alphanumeric() || **c == '_')
+            .count();
+        // after a dot only properties make sense, the methods are the
+        // ones that are known
+        if before.len() > word && before[before.len() - word - 1] == '.' {
+            return doc
+                .analysis
+                .symbols
+                .iter()
+                .filter(|symbol| symbol.kind == SymbolKind::Method)
+                .map(completion)
+                .collect();
+        }
+
+        let mut items: Vec<_> = KEYWORDS
+            .iter()
+            .map(|keyword| json!({"label": keyword, "kind": 14}))
+            .collect();
+        items.extend(
+            self.natives
+                .iter()
+                .map(|name| json!({"label": name, "kind": 3, "detail": "built-in function"})),
+        );
+        let mut seen = HashMap::new();
+        // inner declarations come later and shadow the outer ones
+        for symbol in doc.analysis.visible((line, column)) {
+            seen.insert(&symbol.name, symbol);
+        }
+        items.extend(seen.into_values().map(completion));
+        items
+    }
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let analysis = Analysis::new(&text);
+        Self {
+            text,
+            lines,
+            analysis,
+        }
+    }
+
+    fn line(&self, line: u64) -> &str {
+        let Some(&start) = self.lines.get(line as usize) else {
+            return "";
+        };
+        let end = self
+            .lines
+            .get(line as usize + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        &self.text[start..end]
+    }
+
+    /// converts a column counted in UTF-16 code units, the way positions
+    /// are sent by the client, to one counted in characters
+    fn column(&self, line: u64, character: u64) -> u64 {
+        let mut units = 0;
+        let mut column = 0;
+        for c in self.line(line).chars() {
+            if units >= character {
+                break;
+            }
+            units += c.len_utf16() as u64;
+            column += 1;
+        }
+        column
+    }
+
+    /// the client's position of the character at `location`, or of the
+    /// one after it
+    fn position(&self, location: Location, after: bool) -> Value {
+        let column = location.column() as usize + after as usize;
+        let character: usize = self
+            .line(location.line())
+            .chars()
+            .take(column)
+            .map(char::len_utf16)
+            .sum();
+        json!({"line": location.line(), "character": character})
+    }
+
+    fn range(&self, span: Span) -> Value {
+        json!({
+            "start": self.position(span.start, false),
+            "end": self.position(span.end, true),
+        })
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) -> Value {
+        let mut message = diagnostic.message().to_string();
+        if let Some(help) = diagnostic.help() {
+            message.push_str("\nhelp: ");
+            message.push_str(help);
+        }
+        json!({
+            "range": self.range(Span {
+                start: diagnostic.start(),
+                end: diagnostic.end(),
+            }),
+            "severity": 1,
+            "source": "lox",
+            "message": message,
+        })
+    }
+
+    fn outline(&self, symbol: usize) -> Value {
+        let symbol = &self.analysis.symbols[symbol];
+        let children: Vec<_> = symbol
+            .children
+            .iter()
+            .map(|&child| self.outline(child))
+            .collect();
+        json!({
+            "name": symbol.name,
+            "detail": symbol.detail,
+            "kind": symbol_kind(symbol.kind),
+            "range": self.range(symbol.range),
+            "selectionRange": self.range(symbol.selection),
+            "children": children,
+        })
+    }
+}
+
+/// the number the protocol gives a kind of symbol
+fn symbol_kind(kind: SymbolKind) -> u8 {
+    match kind {
+        SymbolKind::Variable | SymbolKind::Parameter => 13,
+        SymbolKind::Function => 12,
+        SymbolKind::Class => 5,
+        SymbolKind::Method => 6,
+    }
+}
+
+fn completion(symbol: &Symbol) -> Value {
+    let kind = match symbol.kind {
+        SymbolKind::Variable | SymbolKind::Parameter => 6,
+        SymbolKind::Function => 3,
+        SymbolKind::Class => 7,
+        SymbolKind::Method => 2,
+    };
+    let mut item = json!({
+        "label": symbol.name,
+        "kind": kind,
+        "detail": symbol.detail,
+    });
+    if let Some(doc) = &symbol.doc {
+        item["documentation"] = json!(doc);
+    }
+    item
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+/// the messages a client sends, each after a header with its length
+struct Messages<R> {
+    input: R,
+    /// the input was scanned forward past the `Content-Length:` of the
+    /// next message
+    resumed: bool,
+}
+
+impl<R: BufRead> Messages<R> {
+    /// reads a message with its `Content-Length` header, returns None
+    /// when the input ends. a message that isn't valid is an error
+    /// describing why, with as much of it skipped as can be. without a
+    /// length the end of the message isn't known, so the input is
+    /// skipped up to the header of the next one
+    fn read(&mut self) -> io::Result<Option<Result<Value, String>>> {
+        let mut length = Ok(None);
+        loop {
+            let mut header = String::new();
+            if std::mem::take(&mut self.resumed) {
+                header.push_str(CONTENT_LENGTH);
+            }
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    let value = value.trim();
+                    length = value
+                        .parse::<usize>()
+                        .map(Some)
+                        .map_err(|e| format!("invalid Content-Length '{value}': {e}"));
+                }
+            }
+        }
+
+        let length = match length {
+            Ok(Some(length)) => length,
+            Ok(None) => {
+                self.skip_to_header()?;
+                return Ok(Some(Err("message without a Content-Length header".into())));
+            }
+            Err(e) => {
+                self.skip_to_header()?;
+                return Ok(Some(Err(e)));
+            }
+        };
+        if length > MAX_MESSAGE_LENGTH {
+            io::copy(
+                &mut io::Read::take(&mut self.input, length as u64),
+                &mut io::sink(),
+            )?;
+            return Ok(Some(Err(format!(
+                "message of {length} bytes is longer than {MAX_MESSAGE_LENGTH}"
+            ))));
+        }
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body)?;
+        Ok(Some(
+            serde_json::from_slice(&body).map_err(|e| format!("invalid JSON: {e}")),
+        ))
+    }
+
+    /// skips the input up to and including the next `Content-Length:`,
+    /// or all of it if there is none
+    fn skip_to_header(&mut self) -> io::Result<()> {
+        let header = CONTENT_LENGTH.as_bytes();
+        let mut matched = 0;
+        loop {
+            let buf = self.input.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            for (i, &byte) in buf.iter().enumerate() {
+                if byte.eq_ignore_ascii_case(&header[matched]) {
+                    matched += 1;
+                } else {
+                    // the header starts with the only `C` in it
+                    matched = byte.eq_ignore_ascii_case(&header[0]) as usize;
+                }
+                if matched == header.len() {
+                    self.input.consume(i + 1);
+                    self.resumed = true;
+                    return Ok(());
+                }
+            }
+            let len = buf.len();
+            self.input.consume(len);
+        }
+    }
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
@@ -1,38 +1,238 @@
-mod ast;
-mod interpreter;
-mod parser;
-mod scanner;
+use std::{cell::Cell, path::Path, process::ExitCode, rc::Rc};
 
-use std::io::{stdin, Write};
+use clap::{Parser, Subcommand, ValueEnum};
+use compiler::{
+    checker, debugger, formatter, interpreter::Interpreter, lsp, native, optimizer, parse, repl,
+    resolver, BackendKind, Engine, RunError,
+};
 
-use interpreter::Interpreter;
+#[derive(Parser)]
+#[command(version, about, long_about=None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-fn main() -> anyhow::Result<()> {
-    repl()
+    /// script to run. starts a repl if omitted
+    script: Option<String>,
+
+    /// how scripts are executed
+    #[arg(long, value_enum, default_value_t = Backend::Tree)]
+    backend: Backend,
+
+    /// print what the script compiles to instead of running it
+    #[arg(long, value_enum, requires = "script")]
+    emit: Option<Emit>,
 }
 
-fn repl() -> anyhow::Result<()> {
-    let mut interpreter = Interpreter::new();
-    loop {
-        print!("> ");
-        std::io::stdout().flush()?;
+/// the choices of [`BackendKind`] on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// tree-walking interpreter
+    Tree,
+    /// bytecode compiler and stack-based virtual machine
+    Vm,
+}
+
+impl From<Backend> for BackendKind {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Tree => BackendKind::Tree,
+            Backend::Vm => BackendKind::Vm,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// syntax tree as parsed
+    Ast,
+    /// script after constant folding and dead code elimination, laid
+    /// out by the formatter
+    AstOpt,
+    /// intermediate representation native code is generated from
+    Ir,
+    /// x86-64 assembly of the native executable
+    Asm,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// rewrite scripts in the canonical layout
+    Fmt {
+        /// only report the files that aren't formatted, without changing
+        /// them. fails if there are any
+        #[arg(long)]
+        check: bool,
+
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// run a script on the tree-walking interpreter, pausing it to
+    /// step through and inspect it
+    Debug { script: String },
+    /// run a language server over stdin and stdout
+    Lsp,
+    /// compile a script that only uses ints and bools to a native
+    /// executable for x86-64 linux, linked with the system C compiler
+    Build {
+        script: String,
+
+        /// path of the executable. defaults to the script's name
+        /// without its extension
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            e.print()?;
+            return Ok(match e.use_stderr() {
+                true => ExitCode::from(64),
+                false => ExitCode::SUCCESS,
+            });
+        }
+    };
+
+    match cli.command {
+        Some(Command::Fmt { check, files }) => return fmt(&files, check),
+        Some(Command::Debug { script }) => return debug(&script),
+        Some(Command::Lsp) => return lsp::Server::new().run(),
+        Some(Command::Build { script, output }) => return build(&script, output),
+        None => (),
+    }
+
+    match (cli.script, cli.emit) {
+        (Some(path), Some(emit)) => self::emit(&path, emit),
+        (None, _) => repl::Repl::new(cli.backend.into())?
+            .run()
+            .map(|()| ExitCode::SUCCESS),
+        (Some(path), None) => run_file(&path, &mut Engine::with_backend(cli.backend.into())),
+    }
+}
 
-        let mut line = String::new();
-        stdin().read_line(&mut line)?;
+fn run_file(path: &str, engine: &mut Engine) -> anyhow::Result<ExitCode> {
+    let script = std::fs::read_to_string(path)?;
+    match engine.eval(&script) {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(e) => {
+            e.report(path, &script);
+            Ok(e.exit_code())
+        }
+    }
+}
 
-        if let Err(e) = run(line, &mut interpreter) {
-            eprintln!("{e}");
+fn debug(path: &str) -> anyhow::Result<ExitCode> {
+    let script = std::fs::read_to_string(path)?;
+    let quit = Rc::new(Cell::new(false));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_hook(Box::new(debugger::Debugger::new(
+        path,
+        &script,
+        quit.clone(),
+    )?));
+    let result = parse(&script).and_then(|stmts| {
+        resolver::resolve(&stmts).map_err(RunError::Resolve)?;
+        checker::check(&stmts).map_err(RunError::Check)?;
+        for stmt in stmts {
+            interpreter.evaluate(stmt).map_err(RunError::Runtime)?;
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(_) if quit.get() => Ok(ExitCode::SUCCESS),
+        Err(e) => {
+            e.report(path, &script);
+            Ok(e.exit_code())
         }
     }
 }
 
-fn run(script: String, interpreter: &mut Interpreter) -> anyhow::Result<()> {
-    // println!("running {script}");
-    let tokens = scanner::scan(script)?;
-    // println!("scanned: {tokens:#?}");
+/// compiles a script to a native executable
+fn build(path: &str, output: Option<String>) -> anyhow::Result<ExitCode> {
+    let script = std::fs::read_to_string(path)?;
+    let module = match native::compile(&script) {
+        Ok(module) => module,
+        Err(e) => {
+            e.report(path, &script);
+            return Ok(e.exit_code());
+        }
+    };
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(path).file_stem().unwrap_or("a.out".as_ref());
+        stem.to_string_lossy().into_owned()
+    });
+    native::link(&native::assemble(&module), Path::new(&output))?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    let stmts = parser::parse(tokens)?;
-    // println!("parsed: {stmts:#?}");
-    stmts.for_each(|x| interpreter.evaluate(x));
-    Ok(())
+/// prints the syntax tree of a script that resolves and checks, or
+/// what it compiles to natively
+fn emit(path: &str, emit: Emit) -> anyhow::Result<ExitCode> {
+    let script = std::fs::read_to_string(path)?;
+    let result = parse(&script).and_then(|stmts| {
+        resolver::resolve(&stmts).map_err(RunError::Resolve)?;
+        checker::check(&stmts).map_err(RunError::Check)?;
+        Ok(stmts)
+    });
+    let stmts = match result {
+        Ok(stmts) => stmts,
+        Err(e) => {
+            e.report(path, &script);
+            return Ok(e.exit_code());
+        }
+    };
+
+    match emit {
+        Emit::Ast => {
+            for stmt in stmts {
+                println!("{stmt:#?}");
+            }
+        }
+        Emit::AstOpt => print!("{}", formatter::format_tree(&optimizer::optimize(stmts))),
+        Emit::Ir | Emit::Asm => {
+            let module = match native::lower(stmts) {
+                Ok(module) => module,
+                Err(e) => {
+                    let e = RunError::Compile(e);
+                    e.report(path, &script);
+                    return Ok(e.exit_code());
+                }
+            };
+            match emit {
+                Emit::Ir => print!("{module}"),
+                _ => print!("{}", native::assemble(&module)),
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// formats every file, even after one of them failed
+fn fmt(files: &[String], check: bool) -> anyhow::Result<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    for path in files {
+        let script = std::fs::read_to_string(path)?;
+        let formatted = match formatter::format(&script) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                e.report(path, &script);
+                code = e.exit_code();
+                continue;
+            }
+        };
+        if formatted == script {
+            continue;
+        }
+        if check {
+            eprintln!("{path} is not formatted");
+            code = ExitCode::FAILURE;
+        } else {
+            std::fs::write(path, formatted)?;
+        }
+    }
+    Ok(code)
 }
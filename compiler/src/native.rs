@@ -0,0 +1,474 @@
+//! native executables for x86-64 linux. scripts are lowered to the
+//! [`ir`], which is turned into assembly and linked with the system C
+//! compiler, `cc` or the one `CC` names. every value lives in a stack
+//! slot of its function and printing goes through the C library
+//!
+//! ```no_run
+//! let module = compiler::native::compile("print 6 * 7;").unwrap();
+//! let asm = compiler::native::assemble(&module);
+//! compiler::native::link(&asm, std::path::Path::new("answer")).unwrap();
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write as _},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    ast::Statement,
+    checker,
+    engine::RUNTIME_EXIT_CODE,
+    ir::{
+        self, BinaryOp, BlockId, CompareOp, Function, Inst, Module, PassManager, Terminator, Type,
+        Value,
+    },
+    optimizer, resolver, stack,
+    vm::CompileError,
+    RunError,
+};
+
+/// whether the executables [`link`] builds run where this crate was
+/// compiled. the assembly is only written for x86-64 linux
+pub const SUPPORTED: bool = cfg!(all(target_arch = "x86_64", target_os = "linux"));
+
+/// registers the arguments of a call are passed in
+const ARGS: [&str; ir::MAX_PARAMS] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// parses, resolves and checks a script, then lowers it
+pub fn compile(source: &str) -> Result<Module, RunError> {
+    let stmts = crate::parse(source)?;
+    resolver::resolve(&stmts).map_err(RunError::Resolve)?;
+    checker::check(&stmts).map_err(RunError::Check)?;
+    lower(stmts).map_err(RunError::Compile)
+}
+
+/// optimizes a script that went through the resolver and the checker,
+/// lowers it and runs the default passes over it
+pub fn lower(stmts: Vec<Statement>) -> Result<Module, CompileError> {
+    let stmts = optimizer::optimize(stmts);
+    let mut module = ir::lower(&stmts)?;
+    PassManager::default().run(&mut module);
+    Ok(module)
+}
+
+/// the assembly of a module in AT&T syntax. the script becomes `main`
+pub fn assemble(module: &Module) -> String {
+    let mut asm = Assembler {
+        module,
+        out: String::new(),
+        messages: vec![],
+    };
+    asm.line(".text");
+    for (i, function) in module.functions.iter().enumerate() {
+        asm.function(i, function);
+    }
+    asm.runtime();
+    asm.data();
+    asm.out
+}
+
+/// assembles `asm` and links it into an executable at `output`. fails
+/// on targets other than x86-64 linux, see [`SUPPORTED`]
+pub fn link(asm: &str, output: &Path) -> io::Result<()> {
+    if !SUPPORTED {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "native executables can only be built on x86-64 linux, not {} {}",
+                std::env::consts::ARCH,
+                std::env::consts::OS
+            ),
+        ));
+    }
+    let cc = std::env::var_os("CC").unwrap_or_else(|| "cc".into());
+    let mut child = Command::new(&cc)
+        .args(["-x", "assembler", "-", "-o"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("unable to run {cc:?}: {e}")))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(asm.as_bytes())?;
+    let result = child.wait_with_output()?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(io::Error::other(format!(
+            "{cc:?} failed to link the program: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(())
+}
+
+struct Assembler<'a> {
+    module: &'a Module,
+    out: String,
+    /// printf formats of the runtime errors, labeled by index
+    messages: Vec<String>,
+}
+
+impl Assembler<'_> {
+    fn line(&mut self, text: &str) {
+        let indent = match text.is_empty() || text.ends_with(':') {
+            true => "",
+            false => "    ",
+        };
+        let _ = writeln!(self.out, "{indent}{text}");
+    }
+
+    fn label(i: usize, block: BlockId) -> String {
+        format!(".Lf{i}_bb{}", block.0)
+    }
+
+    fn symbol(i: usize) -> String {
+        match i {
+            0 => "main".to_string(),
+            i => format!("lox_fn{i}"),
+        }
+    }
+
+    /// the label of a message, which is formatted with `printf`
+    fn message(&mut self, line: u64, message: String) -> String {
+        self.messages
+            .push(format!("runtime error on line {line}: {message}\n"));
+        format!(".Lmessage{}", self.messages.len() - 1)
+    }
+
+    fn function(&mut self, i: usize, function: &Function) {
+        // values live below the frame pointer, in a frame that keeps
+        // the stack aligned to 16 bytes for calls
+        let frame = (function.types.len() * 8).next_multiple_of(16);
+        let symbol = Self::symbol(i);
+        self.line("");
+        if i == 0 {
+            self.line(&format!(".globl {symbol}"));
+        }
+        self.line(&format!("# {}", function.name));
+        self.line(&format!("{symbol}:"));
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        if frame > 0 {
+            self.line(&format!("subq ${frame}, %rsp"));
+        }
+        for (param, reg) in function.params.iter().zip(ARGS) {
+            self.line(&format!("movq {reg}, {}", slot(*param)));
+        }
+
+        // jumps that fail at runtime go to code after the function
+        let mut errors = vec![];
+        for (b, block) in function.blocks.iter().enumerate() {
+            self.line(&format!("{}:", Self::label(i, BlockId(b))));
+            for inst in &block.insts {
+                self.inst(function, inst, &mut errors);
+            }
+            self.terminator(i, function, BlockId(b), &mut errors);
+        }
+        for (label, code) in errors {
+            self.line(&format!("{label}:"));
+            for line in code {
+                self.line(&line);
+            }
+        }
+    }
+
+    /// a label that stops the program with a message formatted with the
+    /// values of `args`
+    fn error(
+        &mut self,
+        errors: &mut Vec<(String, Vec<String>)>,
+        line: u64,
+        message: String,
+        args: &[Value],
+    ) -> String {
+        let message = self.message(line, message);
+        let mut code = vec![format!("leaq {message}(%rip), %rdi")];
+        for (arg, reg) in args.iter().zip(&ARGS[1..]) {
+            code.push(format!("movq {}, {reg}", slot(*arg)));
+        }
+        code.push("call lox_error".to_string());
+        let label = format!("{message}_raise");
+        errors.push((label.clone(), code));
+        label
+    }
+
+    fn inst(&mut self, function: &Function, inst: &Inst, errors: &mut Vec<(String, Vec<String>)>) {
+        match *inst {
+            Inst::Const { dst, value } => match i32::try_from(value) {
+                Ok(value) => self.line(&format!("movq ${value}, {}", slot(dst))),
+                Err(_) => {
+                    self.line(&format!("movabsq ${value}, %rax"));
+                    self.line(&format!("movq %rax, {}", slot(dst)));
+                }
+            },
+            Inst::Binary {
+                dst,
+                op,
+                lhs,
+                rhs,
+                line,
+            } => {
+                let overflow = format!(
+                    "integer overflow in %ld {} %ld",
+                    op.symbol().replace('%', "%%")
+                );
+                let overflow = self.error(errors, line, overflow, &[lhs, rhs]);
+                self.line(&format!("movq {}, %rax", slot(lhs)));
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                        let name = match op {
+                            BinaryOp::Add => "addq",
+                            BinaryOp::Sub => "subq",
+                            _ => "imulq",
+                        };
+                        self.line(&format!("{name} {}, %rax", slot(rhs)));
+                        self.line(&format!("jo {overflow}"));
+                        self.line(&format!("movq %rax, {}", slot(dst)));
+                    }
+                    BinaryOp::Div | BinaryOp::Rem => {
+                        let zero = self.error(errors, line, "division by zero".to_string(), &[]);
+                        self.line(&format!("movq {}, %rcx", slot(rhs)));
+                        self.line("testq %rcx, %rcx");
+                        self.line(&format!("je {zero}"));
+                        // the smallest int divided by -1 doesn't fit
+                        self.line("cmpq $-1, %rcx");
+                        self.line("jne 1f");
+                        self.line(&format!("movabsq ${}, %rdx", i64::MIN));
+                        self.line("cmpq %rdx, %rax");
+                        self.line(&format!("je {overflow}"));
+                        self.line("1:");
+                        self.line("cqto");
+                        self.line("idivq %rcx");
+                        let result = match op {
+                            BinaryOp::Div => "%rax",
+                            _ => "%rdx",
+                        };
+                        self.line(&format!("movq {result}, {}", slot(dst)));
+                    }
+                }
+            }
+            Inst::Neg { dst, operand, line } => {
+                let message = "integer overflow in -(%ld)".to_string();
+                let overflow = self.error(errors, line, message, &[operand]);
+                self.line(&format!("movq {}, %rax", slot(operand)));
+                self.line("negq %rax");
+                self.line(&format!("jo {overflow}"));
+                self.line(&format!("movq %rax, {}", slot(dst)));
+            }
+            Inst::Compare { dst, op, lhs, rhs } => {
+                let set = match op {
+                    CompareOp::Eq => "sete",
+                    CompareOp::Ne => "setne",
+                    CompareOp::Lt => "setl",
+                    CompareOp::Le => "setle",
+                    CompareOp::Gt => "setg",
+                    CompareOp::Ge => "setge",
+                };
+                self.line(&format!("movq {}, %rax", slot(lhs)));
+                self.line(&format!("cmpq {}, %rax", slot(rhs)));
+                self.line(&format!("{set} %al"));
+                self.line("movzbq %al, %rax");
+                self.line(&format!("movq %rax, {}", slot(dst)));
+            }
+            Inst::Not { dst, operand } => {
+                self.line(&format!("movq {}, %rax", slot(operand)));
+                self.line("xorq $1, %rax");
+                self.line(&format!("movq %rax, {}", slot(dst)));
+            }
+            Inst::Call {
+                dst,
+                function: callee,
+                ref args,
+                line,
+            } => {
+                // as deep as the interpreters go, rather than until the
+                // stack runs out. the top level counts as a call
+                let overflow = self.error(errors, line, "stack overflow".to_string(), &[]);
+                self.line("movq lox_depth(%rip), %rax");
+                self.line(&format!("cmpq ${}, %rax", stack::MAX_CALL_DEPTH - 1));
+                self.line(&format!("je {overflow}"));
+                self.line("incq lox_depth(%rip)");
+                for (arg, reg) in args.iter().zip(ARGS) {
+                    self.line(&format!("movq {}, {reg}", slot(*arg)));
+                }
+                self.line(&format!("call {}", Self::symbol(callee)));
+                self.line("decq lox_depth(%rip)");
+                self.line(&format!("movq %rax, {}", slot(dst)));
+            }
+            Inst::Load { dst, global } => {
+                self.line(&format!("movq lox_global{global}(%rip), %rax"));
+                self.line(&format!("movq %rax, {}", slot(dst)));
+            }
+            Inst::Store { global, value } => {
+                self.line(&format!("movq {}, %rax", slot(value)));
+                self.line(&format!("movq %rax, lox_global{global}(%rip)"));
+            }
+            Inst::Print { value } => match function.types[value.0] {
+                Type::Int => {
+                    self.line("leaq .Lformat_int(%rip), %rdi");
+                    self.line(&format!("movq {}, %rsi", slot(value)));
+                    self.line("xorl %eax, %eax");
+                    self.line("call printf@PLT");
+                }
+                Type::Bool => {
+                    self.line("leaq .Ltrue(%rip), %rdi");
+                    self.line("leaq .Lfalse(%rip), %rax");
+                    self.line(&format!("cmpq $0, {}", slot(value)));
+                    self.line("cmoveq %rax, %rdi");
+                    self.line("call puts@PLT");
+                }
+                Type::Nil => {
+                    self.line("leaq .Lnil(%rip), %rdi");
+                    self.line("call puts@PLT");
+                }
+            },
+            Inst::PrintString { string } => {
+                self.line(&format!("leaq .Lstring{string}(%rip), %rdi"));
+                self.line("call puts@PLT");
+            }
+        }
+    }
+
+    fn terminator(
+        &mut self,
+        i: usize,
+        function: &Function,
+        block: BlockId,
+        errors: &mut Vec<(String, Vec<String>)>,
+    ) {
+        match function.blocks[block.0].terminator {
+            Terminator::Jump(target) => {
+                self.copy_phis(function, block, target);
+                if target.0 != block.0 + 1 {
+                    self.line(&format!("jmp {}", Self::label(i, target)));
+                }
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let edge = match function.blocks[otherwise.0].phis.is_empty() {
+                    true => Self::label(i, otherwise),
+                    false => format!("{}_to_bb{}", Self::label(i, block), otherwise.0),
+                };
+                self.line(&format!("cmpq $0, {}", slot(cond)));
+                self.line(&format!("je {edge}"));
+                self.copy_phis(function, block, then);
+                self.line(&format!("jmp {}", Self::label(i, then)));
+                if !function.blocks[otherwise.0].phis.is_empty() {
+                    self.line(&format!("{edge}:"));
+                    self.copy_phis(function, block, otherwise);
+                    self.line(&format!("jmp {}", Self::label(i, otherwise)));
+                }
+            }
+            Terminator::Return(val) => {
+                self.line(&format!("movq {}, %rax", slot(val)));
+                self.line("leave");
+                self.line("ret");
+            }
+            Terminator::Error { line, ref message } => {
+                let message = message.replace('%', "%%");
+                let label = self.error(errors, line, message, &[]);
+                self.line(&format!("jmp {label}"));
+            }
+            Terminator::Unreachable => self.line("ud2"),
+        }
+    }
+
+    /// sets the phis of `to` to the values they take coming from
+    /// `from`. they all change at once, so the values go through the
+    /// stack in case a phi's value is another of the phis
+    fn copy_phis(&mut self, function: &Function, from: BlockId, to: BlockId) {
+        let phis = &function.blocks[to.0].phis;
+        for phi in phis {
+            let (_, val) = phi
+                .incoming
+                .iter()
+                .find(|(pred, _)| *pred == from)
+                .expect("phi has a value for each predecessor");
+            self.line(&format!("pushq {}", slot(*val)));
+        }
+        for phi in phis.iter().rev() {
+            self.line(&format!("popq {}", slot(phi.dst)));
+        }
+    }
+
+    /// prints a runtime error to stderr and exits. takes the format
+    /// then up to two values for it
+    fn runtime(&mut self) {
+        self.line("");
+        self.line("lox_error:");
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        self.line("movq %rdx, %rcx");
+        self.line("movq %rsi, %rdx");
+        self.line("movq %rdi, %rsi");
+        self.line("movl $2, %edi");
+        self.line("xorl %eax, %eax");
+        self.line("call dprintf@PLT");
+        self.line(&format!("movl ${RUNTIME_EXIT_CODE}, %edi"));
+        self.line("call exit@PLT");
+    }
+
+    fn data(&mut self) {
+        self.line("");
+        self.line(".section .rodata");
+        let mut strings = vec![
+            (".Lformat_int".to_string(), "%ld\n".to_string()),
+            (".Ltrue".to_string(), "true".to_string()),
+            (".Lfalse".to_string(), "false".to_string()),
+            (".Lnil".to_string(), "nil".to_string()),
+        ];
+        for (i, string) in self.module.strings.iter().enumerate() {
+            strings.push((format!(".Lstring{i}"), string.clone()));
+        }
+        for (i, message) in self.messages.iter().enumerate() {
+            strings.push((format!(".Lmessage{i}"), message.clone()));
+        }
+        for (label, string) in strings {
+            self.line(&format!("{label}:"));
+            self.line(&format!(".asciz \"{}\"", escape(&string)));
+        }
+
+        self.line("");
+        self.line(".bss");
+        self.line(".p2align 3");
+        // how many calls are running
+        self.line("lox_depth:");
+        self.line(".zero 8");
+        for (i, global) in self.module.globals.iter().enumerate() {
+            self.line(&format!("# {}", global.name));
+            self.line(&format!("lox_global{i}:"));
+            self.line(".zero 8");
+        }
+        self.line("");
+        self.line(".section .note.GNU-stack,\"\",@progbits");
+    }
+}
+
+/// where a value lives in the frame of its function
+fn slot(val: Value) -> String {
+    format!("-{}(%rbp)", (val.0 + 1) * 8)
+}
+
+/// a string as the assembler reads it between quotes
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{byte:03o}")),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,273 @@
+use std::{cmp::Ordering, rc::Rc};
+
+use crate::{
+    ast::{Block, Class, Expr, Function, Statement},
+    scanner::{Location, Token, TokenType},
+    stack,
+    value::RValue,
+};
+
+/// rewrites a script that went through the resolver and the checker so
+/// it does less work when it runs: operations on literals are computed
+/// up front, conditions that are always true or false are dropped along
+/// with the code they never run, and so are statements after a
+/// `return`. operations that fail, like a division by zero, are left in
+/// place to fail when they run
+pub fn optimize(stmts: Vec<Statement>) -> Vec<Statement> {
+    stack::ensure(|| block(stmts))
+}
+
+/// the statements of a block, up to the first one that always returns
+fn block(stmts: Vec<Statement>) -> Vec<Statement> {
+    let mut optimized = vec![];
+    for stmt in stmts {
+        let stmt = statement(stmt);
+        if matches!(stmt, Statement::Empty) {
+            continue;
+        }
+        let returns = always_returns(&stmt);
+        optimized.push(stmt);
+        if returns {
+            break;
+        }
+    }
+    optimized
+}
+
+fn statement(stmt: Statement) -> Statement {
+    match stmt {
+        // what is left of a `for` loop may no longer look like one
+        Statement::Block(Block {
+            open, stmts, close, ..
+        }) => Statement::Block(Block {
+            open,
+            stmts: block(stmts),
+            close,
+            for_loop: None,
+        }),
+        Statement::Function(decl) => Statement::Function(function(decl)),
+        Statement::Class(decl) => Statement::Class(class(decl)),
+        Statement::Return(keyword, val) => Statement::Return(keyword, val.map(expr)),
+        Statement::If(cond, when_true, when_false) => {
+            let cond = expr(cond);
+            match truthiness(&cond) {
+                Some(true) => statement(*when_true),
+                Some(false) => when_false.map_or(Statement::Empty, |stmt| statement(*stmt)),
+                None => Statement::If(
+                    cond,
+                    Box::new(statement(*when_true)),
+                    when_false.map(|stmt| Box::new(statement(*stmt))),
+                ),
+            }
+        }
+        Statement::While(cond, body) => {
+            let cond = expr(cond);
+            match truthiness(&cond) {
+                Some(false) => Statement::Empty,
+                _ => Statement::While(cond, Box::new(statement(*body))),
+            }
+        }
+        Statement::ForIn(ident, keyword, items, body) => {
+            Statement::ForIn(ident, keyword, expr(items), Box::new(statement(*body)))
+        }
+        Statement::Var(ident, ty, init) => Statement::Var(ident, ty, init.map(expr)),
+        Statement::Print(val) => Statement::Print(expr(val)),
+        Statement::Expr(val) => Statement::Expr(expr(val)),
+        Statement::Empty => Statement::Empty,
+    }
+}
+
+/// declarations are shared with the values of the functions made from
+/// them. one that is already shared is left as it is
+fn function(decl: Rc<Function>) -> Rc<Function> {
+    match Rc::try_unwrap(decl) {
+        Ok(mut decl) => {
+            decl.body.stmts = block(decl.body.stmts);
+            Rc::new(decl)
+        }
+        Err(decl) => decl,
+    }
+}
+
+fn class(decl: Rc<Class>) -> Rc<Class> {
+    match Rc::try_unwrap(decl) {
+        Ok(mut decl) => {
+            decl.methods = decl.methods.into_iter().map(function).collect();
+            Rc::new(decl)
+        }
+        Err(decl) => decl,
+    }
+}
+
+/// whether every way through the statement ends in a `return`
+fn always_returns(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Return(..) => true,
+        Statement::Block(block) => block.stmts.iter().any(always_returns),
+        Statement::If(_, when_true, Some(when_false)) => {
+            always_returns(when_true) && always_returns(when_false)
+        }
+        _ => false,
+    }
+}
+
+fn expr(e: Expr) -> Expr {
+    stack::ensure(|| {
+        match e {
+            Expr::Unary(op, operand) => {
+                let operand = expr(*operand);
+                let folded = value(&operand).and_then(|val| match op.token_type {
+                    TokenType::Minus => (-&val).ok(),
+                    TokenType::Bang => val.is_truthy().ok().map(|b| RValue::Boolean(!b)),
+                    _ => None,
+                });
+                match folded.and_then(|val| literal(val, op.location_start, &op)) {
+                    Some(folded) => folded,
+                    None => Expr::Unary(op, Box::new(operand)),
+                }
+            }
+            Expr::Binary(l, op, r) => {
+                let l = expr(*l);
+                let r = expr(*r);
+                let folded = match (value(&l), value(&r)) {
+                    (Some(lhs), Some(rhs)) => binary(&op, &lhs, &rhs),
+                    // the right side isn't evaluated when the left decides
+                    (Some(lhs), None) => match (&op.token_type, lhs.is_truthy()) {
+                        (TokenType::And, Ok(false)) => Some(RValue::Boolean(false)),
+                        (TokenType::Or, Ok(true)) => Some(RValue::Boolean(true)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match folded.and_then(|val| literal(val, l.first_token().location_start, &op)) {
+                    Some(folded) => folded,
+                    None => Expr::Binary(Box::new(l), op, Box::new(r)),
+                }
+            }
+            Expr::Grouping(inner) => match expr(*inner) {
+                literal @ Expr::Literal(_) => literal,
+                inner => Expr::Grouping(Box::new(inner)),
+            },
+            Expr::Interpolation(parts, end) => {
+                let parts: Vec<_> = parts
+                    .into_iter()
+                    .map(|(piece, val)| (piece, expr(val)))
+                    .collect();
+                let mut text = String::new();
+                for (piece, val) in &parts {
+                    let Some(val) = value(val) else {
+                        return Expr::Interpolation(parts, end);
+                    };
+                    text.push_str(piece.token_type.as_string_piece());
+                    text.push_str(&val.to_string());
+                }
+                text.push_str(end.token_type.as_string_piece());
+                let start = parts[0].0.location_start;
+                literal(RValue::String(text), start, &end).expect("strings are literals")
+            }
+            Expr::Assignment(ident, eq, val) => Expr::Assignment(ident, eq, Box::new(expr(*val))),
+            Expr::Call(callee, paren, args) => Expr::Call(
+                Box::new(expr(*callee)),
+                paren,
+                args.into_iter().map(expr).collect(),
+            ),
+            Expr::Get(object, name) => Expr::Get(Box::new(expr(*object)), name),
+            Expr::Set(object, name, val) => {
+                Expr::Set(Box::new(expr(*object)), name, Box::new(expr(*val)))
+            }
+            Expr::List(bracket, elements) => {
+                Expr::List(bracket, elements.into_iter().map(expr).collect())
+            }
+            Expr::Map(brace, entries) => Expr::Map(
+                brace,
+                entries
+                    .into_iter()
+                    .map(|(key, val)| (expr(key), expr(val)))
+                    .collect(),
+            ),
+            Expr::Index(object, bracket, index) => {
+                Expr::Index(Box::new(expr(*object)), bracket, Box::new(expr(*index)))
+            }
+            Expr::SetIndex(object, bracket, index, val) => Expr::SetIndex(
+                Box::new(expr(*object)),
+                bracket,
+                Box::new(expr(*index)),
+                Box::new(expr(*val)),
+            ),
+            e @ (Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super(..)) => e,
+        }
+    })
+}
+
+/// the result of a binary operation on two values, `None` if it fails
+fn binary(op: &Token, lhs: &RValue, rhs: &RValue) -> Option<RValue> {
+    let compare = |is: fn(Ordering) -> bool| {
+        lhs.compare(rhs)
+            .ok()
+            .map(|ord| RValue::Boolean(ord.is_some_and(is)))
+    };
+    match op.token_type {
+        TokenType::Plus => (lhs + rhs).ok(),
+        TokenType::Minus => (lhs - rhs).ok(),
+        TokenType::Star => (lhs * rhs).ok(),
+        TokenType::Slash => (lhs / rhs).ok(),
+        TokenType::Percent => (lhs % rhs).ok(),
+        TokenType::EqualEqual => Some(RValue::Boolean(lhs == rhs)),
+        TokenType::BangEqual => Some(RValue::Boolean(lhs != rhs)),
+        TokenType::Greater => compare(Ordering::is_gt),
+        TokenType::GreaterEqual => compare(Ordering::is_ge),
+        TokenType::Less => compare(Ordering::is_lt),
+        TokenType::LessEqual => compare(Ordering::is_le),
+        TokenType::And => Some(RValue::Boolean(
+            lhs.is_truthy().ok()? && rhs.is_truthy().ok()?,
+        )),
+        TokenType::Or => Some(RValue::Boolean(
+            lhs.is_truthy().ok()? || rhs.is_truthy().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+/// whether a condition is known to be true or false
+fn truthiness(cond: &Expr) -> Option<bool> {
+    value(cond)?.is_truthy().ok()
+}
+
+/// the value of a literal
+fn value(e: &Expr) -> Option<RValue> {
+    let Expr::Literal(tok) = e else {
+        return None;
+    };
+    match &tok.token_type {
+        TokenType::True => Some(RValue::Boolean(true)),
+        TokenType::False => Some(RValue::Boolean(false)),
+        TokenType::Nil => Some(RValue::Null),
+        TokenType::String(s) => Some(RValue::String(s.clone())),
+        TokenType::Integer(i) => Some(RValue::Int(*i)),
+        TokenType::Decimal(d) => Some(RValue::Decimal(*d)),
+        _ => None,
+    }
+}
+
+/// a literal standing in for the code from `start` to the end of `end`
+fn literal(val: RValue, start: Location, end: &Token) -> Option<Expr> {
+    let (token_type, lexeme) = match val {
+        RValue::Boolean(true) => (TokenType::True, "true".to_string()),
+        RValue::Boolean(false) => (TokenType::False, "false".to_string()),
+        RValue::Null => (TokenType::Nil, "nil".to_string()),
+        RValue::String(s) => {
+            let lexeme = format!("{s:?}");
+            (TokenType::String(s), lexeme)
+        }
+        RValue::Int(i) => (TokenType::Integer(i), i.to_string()),
+        RValue::Decimal(d) => (TokenType::Decimal(d), format!("{d:?}")),
+        _ => return None,
+    };
+    Some(Expr::Literal(Token {
+        token_type,
+        location_start: start,
+        location_end: end.location_end,
+        lexeme,
+        doc: None,
+    }))
+}
@@ -1 +1,77 @@
+use std::fmt::Display;
 
+use crate::{
+    diagnostic::Diagnostic,
+    scanner::{Token, TokenType},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IdentifierExpected(Box<Token>),
+    SemicolonExpected(Box<Token>),
+    /// the left side of `=` is not something that can be assigned to.
+    /// holds the `=` token
+    InvalidAssignmentTarget(Box<Token>),
+    /// token that didn't fit the grammar and what was expected instead
+    Unexpected(Box<Token>, String),
+    /// the token at which blocks or expressions got nested deeper than
+    /// [`MAX_NESTING`](super::MAX_NESTING)
+    TooDeep(Box<Token>),
+}
+
+impl Error {
+    pub fn token(&self) -> &Token {
+        match self {
+            Error::IdentifierExpected(tok)
+            | Error::SemicolonExpected(tok)
+            | Error::InvalidAssignmentTarget(tok)
+            | Error::Unexpected(tok, _)
+            | Error::TooDeep(tok) => tok,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let tok = self.token();
+        let diagnostic = Diagnostic::error(self.to_string(), tok.location_start, tok.location_end);
+        match self {
+            Error::SemicolonExpected(_) => {
+                diagnostic.with_help("statements need to be terminated with ';'")
+            }
+            Error::InvalidAssignmentTarget(_) => {
+                diagnostic.with_help("only variables and fields can be assigned to")
+            }
+            Error::TooDeep(_) => diagnostic.with_help(format!(
+                "blocks and expressions can be nested at most {} deep",
+                super::MAX_NESTING
+            )),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IdentifierExpected(next) => {
+                write!(f, "identifier expected, found {}", describe(next))
+            }
+            Error::SemicolonExpected(next) => {
+                write!(f, "semicolon expected before {}", describe(next))
+            }
+            Error::InvalidAssignmentTarget(_) => write!(f, "invalid assignment target"),
+            Error::Unexpected(next, msg) => {
+                write!(f, "unexpected {}: {msg}", describe(next))
+            }
+            Error::TooDeep(next) => write!(f, "nested too deeply at {}", describe(next)),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn describe(tok: &Token) -> String {
+    match tok.token_type {
+        TokenType::Eof => "end of file".into(),
+        _ => format!("'{}'", tok.lexeme),
+    }
+}
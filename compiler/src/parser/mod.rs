@@ -1,33 +1,15 @@
-use std::{fmt::Display, vec};
+mod error;
 
-use anyhow::anyhow;
+use std::{rc::Rc, vec};
+
+pub use error::Error;
 
 use crate::{
-    ast::{Expr, Ident, Statement},
+    ast::{Block, Class, Expr, ForClauses, Function, Ident, Statement, TypeName},
     scanner::{Token, TokenType, Tokens},
+    stack,
 };
 
-#[derive(Debug)]
-enum Error {
-    IdentifierExpected(Token),
-    SemicolonExpected(Token),
-}
-
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Error::IdentifierExpected(next) => {
-                write!(f, "identifier expected: before {next:?}")
-            }
-            Error::SemicolonExpected(next) => {
-                write!(f, "semicolon expected: before {next:?}")
-            }
-        }
-    }
-}
-
-impl std::error::Error for Error {}
-
 #[derive(Debug)]
 pub struct Statements {
     statements: vec::IntoIter<Statement>,
@@ -41,54 +23,273 @@ impl Iterator for Statements {
     }
 }
 
-pub fn parse(tokens: Tokens) -> anyhow::Result<Statements> {
+/// every error found in a script, together with the statements that
+/// could still be parsed around them
+#[derive(Debug)]
+pub struct Errors {
+    errors: Vec<Error>,
+    partial: Vec<Statement>,
+}
+
+impl Errors {
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// the statements that parsed successfully. statements containing
+    /// an error are left out. the interpreter never runs them, they are
+    /// for editor tooling
+    pub fn into_partial(self) -> Vec<Statement> {
+        self.partial
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// how deep blocks and expressions can be nested in each other. every
+/// pass after the parser recurses through them on the stack. chains
+/// like `a + b + c` or `f()()` don't count, the passes make room on the
+/// stack for each expression in them
+pub const MAX_NESTING: usize = 200;
+
+/// parses the whole script, recovering after errors so that all of
+/// them get reported at once
+pub fn parse(tokens: Tokens) -> std::result::Result<Statements, Errors> {
     let mut parser = Parser::new(tokens);
     let mut decls = Vec::new();
-    while let Some(decl) = parser.decleration()? {
-        decls.push(decl);
+    while parser.peek().token_type != TokenType::Eof {
+        if let Some(decl) = stack::ensure(|| parser.recovering_decleration()) {
+            decls.push(decl);
+        }
+    }
+
+    if !parser.errors.is_empty() {
+        return Err(Errors {
+            errors: parser.errors,
+            partial: decls,
+        });
     }
     Ok(Statements {
         statements: decls.into_iter(),
     })
 }
 
+/// parses a lone expression, such as one typed into the debugger
+pub fn parse_expression(tokens: Tokens) -> std::result::Result<Expr, Errors> {
+    let mut parser = Parser::new(tokens);
+    let expr =
+        stack::ensure(|| parser.expression()).and_then(|expr| match parser.peek().token_type {
+            TokenType::Eof => Ok(*expr),
+            _ => Err(parser.unexpected("expected the end of the expression")),
+        });
+    expr.map_err(|e| Errors {
+        errors: vec![e],
+        partial: vec![],
+    })
+}
+
 pub struct Parser {
     tokens: Tokens,
+    /// errors recovered from so far
+    errors: Vec<Error>,
+    /// number of blocks the parser is currently inside of
+    depth: usize,
+    /// number of blocks and expressions the parser is currently inside
+    /// of, up to [`MAX_NESTING`]
+    nesting: usize,
 }
 
 impl Parser {
     fn new(tokens: Tokens) -> Self {
-        Self { tokens }
+        Self {
+            tokens,
+            errors: vec![],
+            depth: 0,
+            nesting: 0,
+        }
+    }
+
+    /// like [`Parser::decleration`], but on error records it and skips
+    /// ahead to the start of the next statement
+    fn recovering_decleration(&mut self) -> Option<Statement> {
+        match self.decleration() {
+            Ok(stmt) => stmt,
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    /// discards tokens until what is likely a statement boundary: right
+    /// after a ';' or before a keyword that starts a statement. the
+    /// closing brace of an enclosing block is left for the block to
+    /// consume
+    fn synchronize(&mut self) {
+        let nested = self.depth > 0;
+        loop {
+            match self.peek().token_type {
+                TokenType::Eof
+                | TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                TokenType::RightBrace if nested => return,
+                _ => (),
+            }
+            if self.next().token_type == TokenType::Semicolon {
+                return;
+            }
+        }
     }
 
-    fn decleration(&mut self) -> anyhow::Result<Option<Statement>> {
-        if self.consume(&[TokenType::Eof]).is_some() {
+    fn decleration(&mut self) -> Result<Option<Statement>> {
+        if self.peek().token_type == TokenType::Eof {
             return Ok(None);
         }
 
-        if self.consume(&[TokenType::Var]).is_some() {
+        if let Some(keyword) = self.consume(&[TokenType::Var]) {
+            self.document_name(keyword);
             return Ok(Some(self.var_decl()?));
         }
 
+        if let Some(keyword) = self.consume(&[TokenType::Fun]) {
+            self.document_name(keyword);
+            return Ok(Some(Statement::Function(self.function()?)));
+        }
+
+        if let Some(keyword) = self.consume(&[TokenType::Class]) {
+            self.document_name(keyword);
+            return Ok(Some(self.class_decl()?));
+        }
+
         Ok(Some(self.statement()?))
     }
 
-    fn var_decl(&mut self) -> anyhow::Result<Statement> {
+    /// moves the doc comment in front of a declaration's keyword to the
+    /// name after it. methods have no keyword, their name holds it
+    /// already
+    fn document_name(&mut self, keyword: Token) {
+        if let (Some(doc), Some(name)) = (keyword.doc, self.tokens.peek_mut()) {
+            name.doc = Some(doc);
+        }
+    }
+
+    fn class_decl(&mut self) -> Result<Statement> {
+        let name = self.ident(None)?;
+
+        let superclass = match self.consume(&[TokenType::Less]) {
+            Some(_) => Some(self.ident(None)?),
+            None => None,
+        };
+
+        self.consume(&[TokenType::LeftBrace])
+            .ok_or_else(|| self.unexpected("expected '{' before class body"))?;
+        let mut methods = vec![];
+        while !matches!(
+            self.peek().token_type,
+            TokenType::RightBrace | TokenType::Eof
+        ) {
+            methods.push(self.function()?);
+        }
+        let close = self
+            .consume(&[TokenType::RightBrace])
+            .ok_or_else(|| self.unexpected("expected '}' after class body"))?;
+
+        Ok(Statement::Class(Rc::new(Class {
+            name,
+            superclass,
+            methods,
+            close,
+        })))
+    }
+
+    /// parses everything after the `fun` keyword. also used for methods
+    fn function(&mut self) -> Result<Rc<Function>> {
+        let name = self.ident(None)?;
+
+        self.consume(&[TokenType::LeftParen])
+            .ok_or_else(|| self.unexpected("expected '(' after function name"))?;
+        let mut params = vec![];
+        let mut param_types = vec![];
+        if self.consume(&[TokenType::RightParen]).is_none() {
+            loop {
+                params.push(self.ident(None)?);
+                param_types.push(self.annotation()?);
+                if self.consume(&[TokenType::Comma]).is_none() {
+                    break;
+                }
+            }
+            self.consume(&[TokenType::RightParen])
+                .ok_or_else(|| self.unexpected("expected ')' after parameters"))?;
+        }
+
+        let returns = self.annotation()?;
+
+        let body = self
+            .block()?
+            .ok_or_else(|| self.unexpected("expected '{' before function body"))?;
+
+        Ok(Rc::new(Function {
+            name,
+            params,
+            param_types,
+            returns,
+            body,
+        }))
+    }
+
+    /// an optional `: type` after a name or parameter list
+    fn annotation(&mut self) -> Result<Option<TypeName>> {
+        if self.consume(&[TokenType::Colon]).is_none() {
+            return Ok(None);
+        }
+        // `nil` is a keyword but also names the type of its value
+        let token_type = &self.peek().token_type;
+        if token_type.is_identifier() || *token_type == TokenType::Nil {
+            return Ok(Some(TypeName(self.next())));
+        }
+        Err(self.unexpected("expected a type after ':'"))
+    }
+
+    fn var_decl(&mut self) -> Result<Statement> {
         let ident = self.ident(None)?;
+        self.var_initializer(ident)
+    }
 
+    /// parses the rest of a variable declaration after its name
+    fn var_initializer(&mut self, ident: Ident) -> Result<Statement> {
+        let ty = self.annotation()?;
         let tok = self
             .consume(&[TokenType::Semicolon, TokenType::Equal])
             .ok_or_else(|| self.unexpected("expected ';' or '='"))?;
         if tok.token_type == TokenType::Semicolon {
-            return Ok(Statement::Var(ident, None));
+            return Ok(Statement::Var(ident, ty, None));
         }
         let val = *self.expression()?;
         self.semicolon()?;
-        Ok(Statement::Var(ident, Some(val)))
+        Ok(Statement::Var(ident, ty, Some(val)))
+    }
+
+    /// runs `f` one level of nesting deeper, failing at the next token
+    /// if that is too deep
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.nesting == MAX_NESTING {
+            return Err(Error::TooDeep(Box::new(self.peek().clone())));
+        }
+        self.nesting += 1;
+        let result = f(self);
+        self.nesting -= 1;
+        result
     }
 
-    fn unexpected(&mut self, msg: &str) -> anyhow::Error {
-        anyhow!("unexpected token '{:?}': {msg}", self.peek())
+    fn unexpected(&mut self, msg: &str) -> Error {
+        Error::Unexpected(Box::new(self.peek().clone()), msg.into())
     }
 
     fn peek(&mut self) -> &Token {
@@ -103,36 +304,51 @@ impl Parser {
             .expect("scanners last token should be EoF")
     }
 
-    fn ident(&mut self, tok: Option<Token>) -> anyhow::Result<Ident> {
-        let tok = tok.unwrap_or_else(|| {
-            self.tokens
-                .next()
-                .expect("last token from scanner should be EOF")
-        });
-        Ok(Ident::new(tok).map_err(Error::IdentifierExpected)?)
+    fn ident(&mut self, tok: Option<Token>) -> Result<Ident> {
+        let tok = match tok {
+            Some(tok) => tok,
+            // leave anything else in place, EoF in particular
+            None if !self.peek().token_type.is_identifier() => {
+                return Err(Error::IdentifierExpected(Box::new(self.peek().clone())))
+            }
+            None => self.next(),
+        };
+        Ident::new(tok).map_err(Error::IdentifierExpected)
     }
 
-    fn semicolon(&mut self) -> anyhow::Result<Token> {
-        Ok(self
-            .consume(&[TokenType::Semicolon])
-            .ok_or_else(|| Error::SemicolonExpected(self.next()))?)
+    fn semicolon(&mut self) -> Result<Token> {
+        self.consume(&[TokenType::Semicolon])
+            .ok_or_else(|| Error::SemicolonExpected(Box::new(self.peek().clone())))
     }
 
-    fn statement(&mut self) -> anyhow::Result<Statement> {
+    fn statement(&mut self) -> Result<Statement> {
         if self.consume(&[TokenType::Print]).is_some() {
             let expr = *self.expression()?;
             self.semicolon()?;
             return Ok(Statement::Print(expr));
         }
 
+        if let Some(keyword) = self.consume(&[TokenType::Return]) {
+            if self.consume(&[TokenType::Semicolon]).is_some() {
+                return Ok(Statement::Return(keyword, None));
+            }
+            let expr = *self.expression()?;
+            self.semicolon()?;
+            return Ok(Statement::Return(keyword, Some(expr)));
+        }
+
         if let Some(block) = self.block()? {
-            return Ok(block);
+            return Ok(Statement::Block(block));
         }
 
         if let Some(while_loop) = self.while_loop()? {
             return Ok(while_loop);
         }
 
+        if let Some(for_loop) = self.for_loop()? {
+            return Ok(for_loop);
+        }
+
         if let Some(cond) = self.conditional()? {
             return Ok(cond);
         }
@@ -146,63 +362,161 @@ impl Parser {
         Ok(Statement::Expr(*expr))
     }
 
-    fn while_loop(&mut self) -> anyhow::Result<Option<Statement>> {
+    fn while_loop(&mut self) -> Result<Option<Statement>> {
         if self.consume(&[TokenType::While]).is_none() {
             return Ok(None);
         }
 
         let condition = self.expression()?;
 
-        let body = self
-            .block()?
-            .ok_or_else(|| self.unexpected("expected '{'"))?;
+        let body = self.body()?;
 
         Ok(Some(Statement::While(*condition, Box::new(body))))
     }
 
-    fn block(&mut self) -> anyhow::Result<Option<Statement>> {
-        if self.consume(&[TokenType::LeftBrace]).is_none() {
+    /// `for (init; cond; incr) body`, lowered to a while loop as
+    /// described in [`ForClauses`], or `for (var x in items) body`
+    fn for_loop(&mut self) -> Result<Option<Statement>> {
+        let Some(keyword) = self.consume(&[TokenType::For]) else {
+            return Ok(None);
+        };
+
+        self.consume(&[TokenType::LeftParen])
+            .ok_or_else(|| self.unexpected("expected '(' after 'for'"))?;
+
+        let initializer = if self.consume(&[TokenType::Semicolon]).is_some() {
+            None
+        } else if self.consume(&[TokenType::Var]).is_some() {
+            let ident = self.ident(None)?;
+            if let Some(keyword) = self.consume(&[TokenType::In]) {
+                return self.for_in(ident, keyword).map(Some);
+            }
+            Some(self.var_initializer(ident)?)
+        } else {
+            let expr = *self.expression()?;
+            self.semicolon()?;
+            Some(Statement::Expr(expr))
+        };
+
+        let condition = if self.peek().token_type == TokenType::Semicolon {
+            None
+        } else {
+            Some(*self.expression()?)
+        };
+        let semicolon = self.semicolon()?;
+
+        let increment = if self.peek().token_type == TokenType::RightParen {
+            None
+        } else {
+            Some(*self.expression()?)
+        };
+        self.consume(&[TokenType::RightParen])
+            .ok_or_else(|| self.unexpected("expected ')' after for clauses"))?;
+
+        let body = self.body()?;
+
+        let clauses = ForClauses {
+            init: initializer.is_some(),
+            cond: condition.is_some(),
+            incr: increment.is_some(),
+        };
+        let Statement::Block(body) = body else {
+            unreachable!("loop bodies are blocks")
+        };
+        let (open, close) = (body.open.clone(), body.close.clone());
+        let mut body = Statement::Block(body);
+        if let Some(increment) = increment {
+            body = Statement::Block(Block {
+                open,
+                stmts: vec![body, Statement::Expr(increment)],
+                close: close.clone(),
+                for_loop: None,
+            });
+        }
+        let condition = condition.unwrap_or_else(|| {
+            Expr::Literal(Token {
+                token_type: TokenType::True,
+                lexeme: "true".into(),
+                ..semicolon
+            })
+        });
+        let mut stmts: Vec<_> = initializer.into_iter().collect();
+        stmts.push(Statement::While(condition, Box::new(body)));
+        Ok(Some(Statement::Block(Block {
+            open: keyword,
+            stmts,
+            close,
+            for_loop: Some(clauses),
+        })))
+    }
+
+    /// parses the rest of `for (var x in items) body` after `in`
+    fn for_in(&mut self, ident: Ident, keyword: Token) -> Result<Statement> {
+        let items = *self.expression()?;
+        self.consume(&[TokenType::RightParen])
+            .ok_or_else(|| self.unexpected("expected ')' after loop collection"))?;
+        let body = self.body()?;
+        Ok(Statement::ForIn(ident, keyword, items, Box::new(body)))
+    }
+
+    /// the block of an `if`, `else` or a loop
+    fn body(&mut self) -> Result<Statement> {
+        self.block()?
+            .map(Statement::Block)
+            .ok_or_else(|| self.unexpected("expected '{'"))
+    }
+
+    fn block(&mut self) -> Result<Option<Block>> {
+        if self.peek().token_type != TokenType::LeftBrace {
             return Ok(None);
         }
+        self.nested(|this| this.block_body().map(Some))
+    }
+
+    /// parses a block from its opening brace on
+    fn block_body(&mut self) -> Result<Block> {
+        let open = self.next();
         let mut stmts = vec![];
-        while self
-            .tokens
-            .peek()
-            .is_some_and(|x| !x.token_type.is_right_brace())
-        {
-            let stmt = self
-                .decleration()?
-                .ok_or(anyhow!("unexpected EoF. block not closed"))?;
-            stmts.push(stmt);
+        self.depth += 1;
+        while !matches!(
+            self.peek().token_type,
+            TokenType::RightBrace | TokenType::Eof
+        ) {
+            if let Some(stmt) = self.recovering_decleration() {
+                stmts.push(stmt);
+            }
         }
+        self.depth -= 1;
 
-        self.consume(&[TokenType::RightBrace])
+        let close = self
+            .consume(&[TokenType::RightBrace])
             .ok_or_else(|| self.unexpected("expected '}' to close block"))?;
-        Ok(Some(Statement::Block(stmts)))
+        Ok(Block {
+            open,
+            stmts,
+            close,
+            for_loop: None,
+        })
     }
 
-    fn expression(&mut self) -> anyhow::Result<Box<Expr>> {
-        self.assignment()
+    fn expression(&mut self) -> Result<Box<Expr>> {
+        self.nested(Parser::assignment)
     }
 
-    fn conditional(&mut self) -> anyhow::Result<Option<Statement>> {
+    fn conditional(&mut self) -> Result<Option<Statement>> {
         if self.consume(&[TokenType::If]).is_none() {
             return Ok(None);
         }
 
         let condition = self.expression()?;
 
-        let when_true = self
-            .block()?
-            .ok_or_else(|| self.unexpected("expected '{'"))?;
+        let when_true = self.body()?;
 
         if self.consume(&[TokenType::Else]).is_none() {
             return Ok(Some(Statement::If(*condition, Box::new(when_true), None)));
         }
 
-        let when_false = self
-            .block()?
-            .ok_or_else(|| self.unexpected("expected '{'"))?;
+        let when_false = self.body()?;
 
         Ok(Some(Statement::If(
             *condition,
@@ -211,42 +525,40 @@ impl Parser {
         )))
     }
 
-    fn assignment(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn assignment(&mut self) -> Result<Box<Expr>> {
         let expr = self.logic_or()?;
 
         if let Some(eq) = self.consume(&[TokenType::Equal]) {
             let rhs = self.expression()?;
-            if let Expr::Literal(token) = *expr {
-                if token.token_type.is_identifier() {
-                    return Ok(Box::new(Expr::Assignment(
-                        self.ident(Some(token))?,
-                        eq,
-                        rhs,
-                    )));
+            match *expr {
+                Expr::Variable(ident) => return Ok(Box::new(Expr::Assignment(ident, eq, rhs))),
+                Expr::Get(object, name) => return Ok(Box::new(Expr::Set(object, name, rhs))),
+                Expr::Index(object, bracket, index) => {
+                    return Ok(Box::new(Expr::SetIndex(object, bracket, index, rhs)))
                 }
+                _ => return Err(Error::InvalidAssignmentTarget(Box::new(eq))),
             }
-            panic!("invalid assignment target");
         }
 
         Ok(expr)
     }
 
-    fn logic_or(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn logic_or(&mut self) -> Result<Box<Expr>> {
         self.match_binary(&[TokenType::Or], Parser::logic_and)
     }
 
-    fn logic_and(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn logic_and(&mut self) -> Result<Box<Expr>> {
         self.match_binary(&[TokenType::And], Parser::equality)
     }
 
-    fn equality(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn equality(&mut self) -> Result<Box<Expr>> {
         self.match_binary(
             &[TokenType::BangEqual, TokenType::EqualEqual],
             Parser::comparison,
         )
     }
 
-    fn comparison(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn comparison(&mut self) -> Result<Box<Expr>> {
         self.match_binary(
             &[
                 TokenType::Greater,
@@ -258,36 +570,118 @@ impl Parser {
         )
     }
 
-    fn term(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn term(&mut self) -> Result<Box<Expr>> {
         self.match_binary(&[TokenType::Minus, TokenType::Plus], Parser::factor)
     }
 
-    fn factor(&mut self) -> anyhow::Result<Box<Expr>> {
-        self.match_binary(&[TokenType::Slash, TokenType::Star], Parser::unary)
+    fn factor(&mut self) -> Result<Box<Expr>> {
+        self.match_binary(
+            &[TokenType::Slash, TokenType::Star, TokenType::Percent],
+            Parser::unary,
+        )
     }
 
-    fn unary(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn unary(&mut self) -> Result<Box<Expr>> {
         if let Some(operator) = self.consume(&[TokenType::Bang, TokenType::Minus]) {
-            let right = self.unary()?;
+            let right = self.nested(Parser::unary)?;
             Ok(Box::new(Expr::Unary(operator, right)))
         } else {
-            self.primary()
+            self.call()
         }
     }
 
-    fn primary(&mut self) -> anyhow::Result<Box<Expr>> {
+    fn call(&mut self) -> Result<Box<Expr>> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.consume(&[TokenType::Dot]).is_some() {
+                let name = self.ident(None)?;
+                expr = Box::new(Expr::Get(expr, name));
+                continue;
+            }
+            if self.consume(&[TokenType::LeftBracket]).is_some() {
+                let index = self.expression()?;
+                let bracket = self
+                    .consume(&[TokenType::RightBracket])
+                    .ok_or_else(|| self.unexpected("expected ']' after index"))?;
+                expr = Box::new(Expr::Index(expr, bracket, index));
+                continue;
+            }
+            if self.consume(&[TokenType::LeftParen]).is_none() {
+                break;
+            }
+            let mut args = vec![];
+            if self.peek().token_type != TokenType::RightParen {
+                loop {
+                    args.push(*self.expression()?);
+                    if self.consume(&[TokenType::Comma]).is_none() {
+                        break;
+                    }
+                }
+            }
+            let paren = self
+                .consume(&[TokenType::RightParen])
+                .ok_or_else(|| self.unexpected("expected ')' after arguments"))?;
+            expr = Box::new(Expr::Call(expr, paren, args));
+        }
+
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Box<Expr>> {
         if let Some(operator) = self.consume(&[TokenType::False, TokenType::True, TokenType::Nil]) {
             return Ok(Box::new(Expr::Literal(operator)));
         }
 
+        if let Some(keyword) = self.consume(&[TokenType::This]) {
+            return Ok(Box::new(Expr::This(Ident::keyword(keyword))));
+        }
+
+        if let Some(keyword) = self.consume(&[TokenType::Super]) {
+            self.consume(&[TokenType::Dot])
+                .ok_or_else(|| self.unexpected("expected '.' after 'super'"))?;
+            let method = self.ident(None)?;
+            return Ok(Box::new(Expr::Super(Ident::keyword(keyword), method)));
+        }
+
+        // the rest of the string after an interpolated expression isn't
+        // a string literal, the expression just ended too early
+        if let Some(brace) = self.peek().closing_brace() {
+            return Err(Error::Unexpected(
+                Box::new(brace),
+                "expected an expression".into(),
+            ));
+        }
+
         match self.peek().token_type {
-            TokenType::Integer(_)
-            | TokenType::String(_)
-            | TokenType::Decimal(_)
-            | TokenType::Identifier(_) => return Ok(Box::new(Expr::Literal(self.next()))),
+            TokenType::Integer(_) | TokenType::String(_) | TokenType::Decimal(_) => {
+                return Ok(Box::new(Expr::Literal(self.next())))
+            }
+            TokenType::Identifier(_) => return Ok(Box::new(Expr::Variable(self.ident(None)?))),
+            TokenType::Interpolation(_) => return self.interpolation(),
             _ => (),
         };
 
+        if let Some(bracket) = self.consume(&[TokenType::LeftBracket]) {
+            let elements =
+                self.comma_separated(TokenType::RightBracket, |this| Ok(*this.expression()?))?;
+            self.consume(&[TokenType::RightBracket])
+                .ok_or_else(|| self.unexpected("expected ']' after list elements"))?;
+            return Ok(Box::new(Expr::List(bracket, elements)));
+        }
+
+        if let Some(brace) = self.consume(&[TokenType::LeftBrace]) {
+            let entries = self.comma_separated(TokenType::RightBrace, |this| {
+                let key = *this.expression()?;
+                this.consume(&[TokenType::Colon])
+                    .ok_or_else(|| this.unexpected("expected ':' after map key"))?;
+                Ok((key, *this.expression()?))
+            })?;
+            self.consume(&[TokenType::RightBrace])
+                .ok_or_else(|| self.unexpected("expected '}' after map entries"))?;
+            return Ok(Box::new(Expr::Map(brace, entries)));
+        }
+
         if self.consume(&[TokenType::LeftParen]).is_some() {
             let expr = self.expression()?;
             self.consume(&[TokenType::RightParen])
@@ -295,14 +689,49 @@ impl Parser {
             return Ok(Box::new(Expr::Grouping(expr)));
         }
 
-        Err(self.unexpected("idk what you did"))
+        Err(self.unexpected("expected an expression"))
+    }
+
+    /// the scanner splits an interpolated string into pieces around the
+    /// tokens of the expressions inside it
+    fn interpolation(&mut self) -> Result<Box<Expr>> {
+        let mut parts = vec![];
+        let mut piece = self.next();
+        loop {
+            let expr = *self.expression()?;
+            parts.push((piece, expr));
+            match self.peek().token_type {
+                TokenType::Interpolation(_) => piece = self.next(),
+                TokenType::String(_) => {
+                    return Ok(Box::new(Expr::Interpolation(parts, self.next())))
+                }
+                _ => return Err(self.unexpected("expected '}' after interpolated expression")),
+            }
+        }
+    }
+
+    /// parses items separated by commas up to, but not including,
+    /// `close`. a trailing comma is allowed
+    fn comma_separated<T>(
+        &mut self,
+        close: TokenType,
+        mut item: impl FnMut(&mut Parser) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut items = vec![];
+        while self.peek().token_type != close {
+            items.push(item(self)?);
+            if self.consume(&[TokenType::Comma]).is_none() {
+                break;
+            }
+        }
+        Ok(items)
     }
 
     fn match_binary(
         &mut self,
         tokens: &[TokenType],
-        f: fn(&mut Parser) -> anyhow::Result<Box<Expr>>,
-    ) -> anyhow::Result<Box<Expr>> {
+        f: fn(&mut Parser) -> Result<Box<Expr>>,
+    ) -> Result<Box<Expr>> {
         let mut expr = f(self)?;
 
         while let Some(operator) = self.consume(tokens) {
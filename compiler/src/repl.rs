@@ -0,0 +1,329 @@
+//! an interactive session running scripts as they are typed
+
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    ast::{Expr, Statement},
+    parse,
+    scanner::{self, ScanErrorKind, TokenType},
+    value::RValue,
+    BackendKind, Engine, RunError,
+};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+const HELP: &str = "\
+:env           list global variables, except built-in functions
+:ast <code>    print the syntax tree of code
+:tokens <code> print the tokens of code
+:load <file>   run a file in this session
+:reset         forget all variables
+:help          show this message
+:quit          exit the repl";
+
+/// where a session reads its lines from
+pub trait Input {
+    /// the next line, without its newline. `ReadlineError::Interrupted`
+    /// throws away what was typed so far and `ReadlineError::Eof` ends
+    /// the session
+    fn readline(&mut self, prompt: &str) -> rustyline::Result<String>;
+
+    /// called with each complete input before it runs
+    fn add_history_entry(&mut self, _input: &str) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    /// called when the session ends
+    fn finish(&mut self) -> rustyline::Result<()> {
+        Ok(())
+    }
+}
+
+/// lines typed into the terminal, with the history of earlier sessions
+/// kept in `~/.lox_history`
+pub struct Terminal {
+    editor: DefaultEditor,
+    history: Option<PathBuf>,
+}
+
+impl Terminal {
+    pub fn new() -> rustyline::Result<Self> {
+        let mut editor = DefaultEditor::new()?;
+        let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".lox_history"));
+        if let Some(history) = &history {
+            // there is no history before the first session
+            let _ = editor.load_history(history);
+        }
+        Ok(Self { editor, history })
+    }
+
+    /// lines typed into the terminal, only remembered until it is
+    /// dropped
+    pub fn without_history() -> rustyline::Result<Self> {
+        Ok(Self {
+            editor: DefaultEditor::new()?,
+            history: None,
+        })
+    }
+}
+
+impl Input for Terminal {
+    fn readline(&mut self, prompt: &str) -> rustyline::Result<String> {
+        self.editor.readline(prompt)
+    }
+
+    fn add_history_entry(&mut self, input: &str) -> rustyline::Result<()> {
+        self.editor.add_history_entry(input).map(drop)
+    }
+
+    fn finish(&mut self) -> rustyline::Result<()> {
+        match &self.history {
+            Some(history) => self.editor.save_history(history),
+            None => Ok(()),
+        }
+    }
+}
+
+/// lines read without prompting or editing, such as from a file
+impl<R: BufRead> Input for R {
+    fn readline(&mut self, _prompt: &str) -> rustyline::Result<String> {
+        let mut line = String::new();
+        if self.read_line(&mut line)? == 0 {
+            return Err(ReadlineError::Eof);
+        }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(line)
+    }
+}
+
+/// an interactive session. a lone expression has its value printed and
+/// lines starting with ':' are commands, see [`HELP`]. what the scripts
+/// and commands print goes to one writer and errors to another
+pub struct Repl {
+    engine: Engine,
+    kind: BackendKind,
+    input: Box<dyn Input>,
+    out: Shared,
+    err: Box<dyn Write>,
+}
+
+impl Repl {
+    /// a session on the terminal, printing to stdout and stderr
+    pub fn new(kind: BackendKind) -> rustyline::Result<Self> {
+        Ok(Self::with_io(
+            kind,
+            Terminal::new()?,
+            io::stdout(),
+            io::stderr(),
+        ))
+    }
+
+    pub fn with_io(
+        kind: BackendKind,
+        input: impl Input + 'static,
+        out: impl Write + 'static,
+        err: impl Write + 'static,
+    ) -> Self {
+        let out = Shared(Rc::new(RefCell::new(Box::new(out))));
+        Repl {
+            engine: Self::engine(kind, &out),
+            kind,
+            input: Box::new(input),
+            out,
+            err: Box::new(err),
+        }
+    }
+
+    /// an engine printing to the output of the session
+    fn engine(kind: BackendKind, out: &Shared) -> Engine {
+        let mut engine = Engine::with_backend(kind);
+        engine.set_output(out.clone());
+        engine
+    }
+
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        while let Some(input) = self.read_input()? {
+            if input.trim().is_empty() {
+                continue;
+            }
+            self.input.add_history_entry(input.trim_end())?;
+
+            match input.trim().strip_prefix(':') {
+                Some(command) => {
+                    if !self.command(command)? {
+                        break;
+                    }
+                }
+                None => self.eval(&input)?,
+            }
+        }
+        self.input.finish()?;
+        Ok(())
+    }
+
+    /// reads lines until they form complete input. ctrl-c throws away
+    /// what was typed so far, returns None at the end of input
+    fn read_input(&mut self) -> rustyline::Result<Option<String>> {
+        let mut input = String::new();
+        loop {
+            let prompt = match input.is_empty() {
+                true => PROMPT,
+                false => CONTINUATION_PROMPT,
+            };
+            match self.input.readline(prompt) {
+                Ok(line) => {
+                    input.push_str(&line);
+                    input.push('\n');
+                }
+                Err(ReadlineError::Interrupted) => input.clear(),
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            if input.trim_start().starts_with(':') || !is_incomplete(&input) {
+                return Ok(Some(input));
+            }
+        }
+    }
+
+    fn eval(&mut self, input: &str) -> io::Result<()> {
+        let source = terminate(input);
+        let result = parse(&source).and_then(|mut stmts| {
+            if stmts.len() == 1 {
+                stmts = stmts.into_iter().map(echo).collect();
+            }
+            self.engine.execute(stmts)
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => self.report(&e, "<repl>", &source),
+        }
+    }
+
+    /// writes the diagnostics of an error
+    fn report(&mut self, e: &RunError, file: &str, source: &str) -> io::Result<()> {
+        for diagnostic in e.diagnostics() {
+            writeln!(self.err, "{}", diagnostic.render(file, source))?;
+        }
+        Ok(())
+    }
+
+    /// runs a meta-command, returns false if the repl should exit
+    fn command(&mut self, line: &str) -> io::Result<bool> {
+        let (name, arg) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, arg)| (name, arg.trim()));
+
+        match name {
+            "env" => {
+                // the standard library would drown out the user's variables
+                let globals = self.engine.globals();
+                for (name, val) in globals
+                    .iter()
+                    .filter(|(_, val)| !matches!(val, RValue::Native(_)))
+                {
+                    writeln!(self.out, "{name} = {val}")?;
+                }
+            }
+            "ast" => {
+                let source = terminate(arg);
+                match parse(&source) {
+                    Ok(stmts) => {
+                        for stmt in stmts {
+                            writeln!(self.out, "{stmt:#?}")?;
+                        }
+                    }
+                    Err(e) => self.report(&e, "<repl>", &source)?,
+                }
+            }
+            "tokens" => match scanner::scan(arg.to_string()) {
+                Ok(tokens) => {
+                    for tok in tokens {
+                        let start = tok.location_start.to_string();
+                        writeln!(self.out, "{start:<8}{:?}", tok.token_type)?;
+                    }
+                }
+                Err(e) => self.report(&RunError::Scan(e), "<repl>", arg)?,
+            },
+            "load" if arg.is_empty() => writeln!(self.err, "error: usage is ':load <file>'")?,
+            "load" => match std::fs::read_to_string(arg) {
+                Ok(script) => {
+                    if let Err(e) = self.engine.eval(&script) {
+                        self.report(&e, arg, &script)?;
+                    }
+                }
+                Err(e) => writeln!(self.err, "error: unable to read '{arg}': {e}")?,
+            },
+            "reset" => self.engine = Self::engine(self.kind, &self.out),
+            "help" => writeln!(self.out, "{HELP}")?,
+            "quit" | "q" => return Ok(false),
+            _ => writeln!(self.err, "error: unknown command ':{name}'. see ':help'")?,
+        }
+        Ok(true)
+    }
+}
+
+/// the output of a session, shared with the engine running its scripts
+#[derive(Clone)]
+struct Shared(Rc<RefCell<Box<dyn Write>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// adds the semicolon a lone expression may be missing. it goes on its
+/// own line so that it isn't swallowed by a trailing comment
+fn terminate(input: &str) -> String {
+    let mut source = input.trim_end().to_string();
+    if !source.ends_with([';', '}']) {
+        source.push_str("\n;");
+    }
+    source
+}
+
+/// turns an expression statement into a print of its value. assignments
+/// are left alone, their value is what was just typed
+fn echo(stmt: Statement) -> Statement {
+    match stmt {
+        Statement::Expr(expr) if !matches!(expr, Expr::Assignment(..) | Expr::Set(..)) => {
+            Statement::Print(expr)
+        }
+        stmt => stmt,
+    }
+}
+
+/// whether the input ends inside a string or comment, or with unclosed
+/// brackets, in which case more lines are read before running it
+fn is_incomplete(input: &str) -> bool {
+    match scanner::scan(input.to_string()) {
+        Err(e) => matches!(
+            e.kind(),
+            ScanErrorKind::UnterminatedString | ScanErrorKind::UnterminatedComment
+        ),
+        Ok(tokens) => {
+            let depth = tokens.fold(0, |depth, tok| match tok.token_type {
+                TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => depth + 1,
+                TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket => {
+                    depth - 1
+                }
+                _ => depth,
+            });
+            depth > 0
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::Display;
+
+use crate::{diagnostic::Diagnostic, scanner::Token};
+
+/// a mistake in how variables, `this`, `super` or `return` are used
+/// that can be found without running the script
+#[derive(Debug)]
+pub enum Error {
+    /// a second declaration of a variable in the same scope
+    AlreadyDeclared(Box<Token>),
+    /// reading a local in its own initializer
+    OwnInitializer(Box<Token>),
+    /// a use of a variable which is declared later in the scope the
+    /// use is in. holds the use and the declaration
+    UsedBeforeDeclaration(Box<Token>, Box<Token>),
+    TopLevelReturn(Box<Token>),
+    /// `return value;` inside `init`. holds the `return` keyword
+    InitializerReturn(Box<Token>),
+    ThisOutsideClass(Box<Token>),
+    SuperOutsideSubclass(Box<Token>),
+    /// holds the superclass
+    InheritsItself(Box<Token>),
+}
+
+impl Error {
+    pub fn token(&self) -> &Token {
+        match self {
+            Error::AlreadyDeclared(tok)
+            | Error::OwnInitializer(tok)
+            | Error::UsedBeforeDeclaration(tok, _)
+            | Error::TopLevelReturn(tok)
+            | Error::InitializerReturn(tok)
+            | Error::ThisOutsideClass(tok)
+            | Error::SuperOutsideSubclass(tok)
+            | Error::InheritsItself(tok) => tok,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let tok = self.token();
+        let diagnostic = Diagnostic::error(self.to_string(), tok.location_start, tok.location_end);
+        match self {
+            Error::UsedBeforeDeclaration(_, decl) => diagnostic.with_help(format!(
+                "'{}' is declared at {}, move the declaration before this use",
+                decl.lexeme, decl.location_start
+            )),
+            Error::InitializerReturn(_) => {
+                diagnostic.with_help("initializers always return 'this'")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::AlreadyDeclared(tok) => write!(
+                f,
+                "variable '{}' already exists in this scope. you cannot assign values with var",
+                tok.lexeme
+            ),
+            Error::OwnInitializer(tok) => write!(
+                f,
+                "can't read local variable '{}' in its own initializer",
+                tok.lexeme
+            ),
+            Error::UsedBeforeDeclaration(tok, _) => {
+                write!(
+                    f,
+                    "variable '{}' is used before its declaration",
+                    tok.lexeme
+                )
+            }
+            Error::TopLevelReturn(_) => write!(f, "can't return from top-level code"),
+            Error::InitializerReturn(_) => write!(f, "can't return a value from an initializer"),
+            Error::ThisOutsideClass(_) => write!(f, "can't use 'this' outside of a class"),
+            Error::SuperOutsideSubclass(_) => {
+                write!(f, "can't use 'super' outside of a class with a superclass")
+            }
+            Error::InheritsItself(_) => write!(f, "a class can't inherit from itself"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
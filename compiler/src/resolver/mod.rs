@@ -0,0 +1,323 @@
+mod error;
+
+use std::collections::{HashMap, HashSet};
+
+pub use error::Error;
+
+use crate::{
+    ast::{self, Binding, Expr, Ident, Statement},
+    scanner::Token,
+    stack,
+};
+
+/// binds every use of a local variable to the scope and slot it lives
+/// in. runs over the whole script and reports all errors at once
+pub fn resolve(stmts: &[Statement]) -> Result<(), Vec<Error>> {
+    let mut resolver = Resolver::default();
+    for stmt in stmts {
+        stack::ensure(|| resolver.statement(stmt));
+    }
+
+    if !resolver.errors.is_empty() {
+        return Err(resolver.errors);
+    }
+    Ok(())
+}
+
+/// binds an expression evaluated inside of running code, such as in the
+/// debugger. `scopes` holds the names of the variables in each local
+/// scope around it, outermost first
+pub fn resolve_in(expr: &Expr, scopes: &[Vec<String>]) -> Result<(), Vec<Error>> {
+    let mut resolver = Resolver::default();
+    for names in scopes {
+        resolver.scopes.push(Scope::default());
+        for name in names {
+            resolver.builtin(name);
+            match name.as_str() {
+                "super" => resolver.class = ClassKind::Subclass,
+                "this" if resolver.class == ClassKind::None => resolver.class = ClassKind::Class,
+                _ => (),
+            }
+        }
+    }
+    stack::ensure(|| resolver.expr(expr));
+
+    if !resolver.errors.is_empty() {
+        return Err(resolver.errors);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum FunctionKind {
+    #[default]
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ClassKind {
+    #[default]
+    None,
+    Class,
+    Subclass,
+}
+
+/// a scope as it will be laid out at runtime
+#[derive(Default)]
+struct Scope {
+    /// names of the variables in slot order, and whether their
+    /// initializer has finished
+    vars: Vec<(String, bool)>,
+    /// names that were looked up past this scope, with their first use
+    passed_through: HashMap<String, Token>,
+}
+
+#[derive(Default)]
+struct Resolver {
+    /// local scopes, innermost last. empty at the top level, whose
+    /// variables are looked up by name
+    scopes: Vec<Scope>,
+    /// names declared at the top level
+    globals: HashSet<String>,
+    function: FunctionKind,
+    class: ClassKind,
+    errors: Vec<Error>,
+}
+
+impl Resolver {
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(block) => {
+                self.scopes.push(Scope::default());
+                for stmt in &block.stmts {
+                    self.statement(stmt);
+                }
+                self.scopes.pop();
+            }
+            Statement::Function(decl) => {
+                // defined right away so the function can recurse
+                self.declare(&decl.name);
+                self.define();
+                self.function(decl, FunctionKind::Function);
+            }
+            Statement::Class(decl) => self.class_decl(decl),
+            Statement::Return(keyword, expr) => {
+                match self.function {
+                    FunctionKind::None => self
+                        .errors
+                        .push(Error::TopLevelReturn(Box::new(keyword.clone()))),
+                    FunctionKind::Initializer if expr.is_some() => self
+                        .errors
+                        .push(Error::InitializerReturn(Box::new(keyword.clone()))),
+                    _ => (),
+                }
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            Statement::If(cond, when_true, when_false) => {
+                self.expr(cond);
+                self.statement(when_true);
+                if let Some(when_false) = when_false {
+                    self.statement(when_false);
+                }
+            }
+            Statement::While(cond, body) => {
+                self.expr(cond);
+                self.statement(body);
+            }
+            Statement::ForIn(ident, _, items, body) => {
+                // every iteration gets a scope holding just the variable
+                self.expr(items);
+                self.scopes.push(Scope::default());
+                self.declare(ident);
+                self.define();
+                self.statement(body);
+                self.scopes.pop();
+            }
+            Statement::Var(ident, _, init) => {
+                self.declare(ident);
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                self.define();
+            }
+            Statement::Print(expr) | Statement::Expr(expr) => self.expr(expr),
+            Statement::Empty => (),
+        }
+    }
+
+    fn class_decl(&mut self, decl: &ast::Class) {
+        let enclosing = std::mem::replace(&mut self.class, ClassKind::Class);
+        self.declare(&decl.name);
+        self.define();
+
+        // methods are closures over a scope holding `super`, which in
+        // turn is the parent of the scope holding `this`
+        if let Some(superclass) = &decl.superclass {
+            if superclass.name() == decl.name.name() {
+                self.errors
+                    .push(Error::InheritsItself(Box::new(superclass.token().clone())));
+            }
+            self.variable(superclass);
+            self.class = ClassKind::Subclass;
+            self.scopes.push(Scope::default());
+            self.builtin("super");
+        }
+        self.scopes.push(Scope::default());
+        self.builtin("this");
+
+        for method in &decl.methods {
+            let kind = match method.name.name().as_str() {
+                "init" => FunctionKind::Initializer,
+                _ => FunctionKind::Method,
+            };
+            self.function(method, kind);
+        }
+
+        self.scopes.pop();
+        if decl.superclass.is_some() {
+            self.scopes.pop();
+        }
+        self.class = enclosing;
+    }
+
+    /// parameters and body share a single scope, like they do when the
+    /// function is called
+    fn function(&mut self, decl: &ast::Function, kind: FunctionKind) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        self.scopes.push(Scope::default());
+        for param in &decl.params {
+            self.declare(param);
+            self.define();
+        }
+        for stmt in &decl.body.stmts {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+        self.function = enclosing;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        stack::ensure(|| match expr {
+            Expr::Unary(_, expr) | Expr::Grouping(expr) | Expr::Get(expr, _) => self.expr(expr),
+            Expr::Binary(l, _, r) | Expr::Set(l, _, r) | Expr::Index(l, _, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            Expr::SetIndex(object, _, index, val) => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(val);
+            }
+            Expr::List(_, elements) => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, val) in entries {
+                    self.expr(key);
+                    self.expr(val);
+                }
+            }
+            Expr::Interpolation(parts, _) => {
+                for (_, expr) in parts {
+                    self.expr(expr);
+                }
+            }
+            Expr::Literal(_) => (),
+            Expr::Variable(ident) => self.variable(ident),
+            Expr::Assignment(ident, _, val) => {
+                self.expr(val);
+                self.variable(ident);
+            }
+            Expr::Call(callee, _, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::This(keyword) => match self.class {
+                ClassKind::None => self
+                    .errors
+                    .push(Error::ThisOutsideClass(Box::new(keyword.token().clone()))),
+                _ => self.variable(keyword),
+            },
+            Expr::Super(keyword, _) => match self.class {
+                ClassKind::Subclass => self.variable(keyword),
+                _ => self.errors.push(Error::SuperOutsideSubclass(Box::new(
+                    keyword.token().clone(),
+                ))),
+            },
+        })
+    }
+
+    /// adds a variable to the innermost scope, it can't be read until
+    /// it's defined. globals are only checked for being declared twice
+    fn declare(&mut self, ident: &Ident) {
+        let Some(scope) = self.scopes.last_mut() else {
+            if !self.globals.insert(ident.name().clone()) {
+                self.errors
+                    .push(Error::AlreadyDeclared(Box::new(ident.token().clone())));
+            }
+            return;
+        };
+        if scope.vars.iter().any(|(name, _)| name == ident.name()) {
+            self.errors
+                .push(Error::AlreadyDeclared(Box::new(ident.token().clone())));
+        }
+        if let Some(used) = scope.passed_through.get(ident.name()) {
+            self.errors.push(Error::UsedBeforeDeclaration(
+                Box::new(used.clone()),
+                Box::new(ident.token().clone()),
+            ));
+        }
+        ident.set_binding(Binding::Local {
+            depth: 0,
+            slot: scope.vars.len(),
+        });
+        scope.vars.push((ident.name().clone(), false));
+    }
+
+    /// marks the most recently declared variable as initialized
+    fn define(&mut self) {
+        if let Some((_, defined)) = self.scopes.last_mut().and_then(|s| s.vars.last_mut()) {
+            *defined = true;
+        }
+    }
+
+    /// a variable the interpreter introduces itself, or one that is
+    /// already defined when resolving in a running scope
+    fn builtin(&mut self, name: &str) {
+        let scope = self.scopes.last_mut().expect("builtins are always local");
+        scope.vars.push((name.into(), true));
+    }
+
+    /// binds a use of a variable to the innermost declaration of its
+    /// name, or leaves it global if there is none
+    fn variable(&mut self, ident: &Ident) {
+        for (depth, scope) in self.scopes.iter_mut().rev().enumerate() {
+            let Some(slot) = scope
+                .vars
+                .iter()
+                .rposition(|(name, _)| name == ident.name())
+            else {
+                scope
+                    .passed_through
+                    .entry(ident.name().clone())
+                    .or_insert_with(|| ident.token().clone());
+                continue;
+            };
+            if !scope.vars[slot].1 {
+                self.errors
+                    .push(Error::OwnInitializer(Box::new(ident.token().clone())));
+            }
+            ident.set_binding(Binding::Local { depth, slot });
+            return;
+        }
+    }
+}
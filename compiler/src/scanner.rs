@@ -3,39 +3,133 @@ use std::{
     error, fmt,
     iter::Peekable,
     num::{ParseFloatError, ParseIntError},
-    ops,
     str::Chars,
     vec,
 };
 
-pub fn scan(script: String) -> anyhow::Result<Tokens> {
-    let tokens = scan_tokens(&script)?.into_iter().peekable();
-    Ok(Tokens { tokens })
+use crate::diagnostic::Diagnostic;
+
+pub fn scan(script: String) -> Result<Tokens, ScanError> {
+    let (tokens, comments) = scan_tokens(&script)?;
+    Ok(Tokens {
+        tokens: tokens.into_iter().peekable(),
+        comments,
+    })
+}
+
+/// a `//` or `/* */` comment. the parser never sees them, they are kept
+/// for tools that reproduce the source. `///` doc comments are also
+/// attached to the token after them, see [`Token::doc`]
+#[derive(Debug, Clone)]
+pub struct Comment {
+    /// the comment including its delimiters, without the newline after
+    /// a line comment
+    pub text: String,
+    pub location: Location,
 }
 
 #[derive(Debug)]
-pub enum ScanError {
+pub struct ScanError {
+    kind: ScanErrorKind,
+    start: Location,
+    end: Location,
+}
+
+#[derive(Debug)]
+pub enum ScanErrorKind {
     UnexpectedToken(char),
     UnterminatedString,
+    UnterminatedComment,
+    InvalidEscape(char),
+    InvalidUnicodeEscape,
     ParseInt(ParseIntError),
     ParseFloat(ParseFloatError),
+    /// a character that can't follow the digits of a number literal in
+    /// the radix, like the `2` in `0b102`
+    InvalidDigit(char, u32),
+    /// no digits where a number literal needs some, after the given part
+    MissingDigits(&'static str),
+    /// a `_` that isn't between two digits
+    MisplacedSeparator,
+    /// a decimal literal too large to be represented
+    DecimalOutOfRange,
+}
+
+impl ScanError {
+    fn new(kind: ScanErrorKind, start: Location, end: Location) -> Self {
+        Self { kind, start, end }
+    }
+
+    pub fn kind(&self) -> &ScanErrorKind {
+        &self.kind
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string(), self.start, self.end);
+        match self.kind {
+            ScanErrorKind::UnterminatedString => {
+                diagnostic.with_help("strings need a closing '\"'")
+            }
+            ScanErrorKind::UnterminatedComment => {
+                diagnostic.with_help("block comments need a closing '*/', one for every '/*'")
+            }
+            ScanErrorKind::InvalidEscape(_) => diagnostic
+                .with_help("the escapes are \\n, \\t, \\r, \\0, \\\", \\\\, \\$ and \\u{...}"),
+            ScanErrorKind::InvalidUnicodeEscape => {
+                diagnostic.with_help("write the code point as 1 to 6 hex digits, like \\u{1F600}")
+            }
+            ScanErrorKind::InvalidDigit(_, radix) => diagnostic.with_help(match radix {
+                2 => "binary literals only have the digits 0 and 1",
+                8 => "octal literals only have the digits 0 to 7",
+                16 => "hexadecimal literals only have the digits 0 to 9 and a to f",
+                _ => "decimal literals only have the digits 0 to 9",
+            }),
+            ScanErrorKind::MisplacedSeparator => {
+                diagnostic.with_help("'_' can only separate two digits, like in 1_000")
+            }
+            _ => diagnostic,
+        }
+    }
 }
 
 impl fmt::Display for ScanError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Self::UnexpectedToken(c) => write!(f, "encountered an unexpected token {c}"),
-            Self::UnterminatedString => write!(f, "encountered an unterminated string"),
-            ref x => x.fmt(f),
+        match self.kind {
+            ScanErrorKind::UnexpectedToken(c) => {
+                write!(f, "encountered an unexpected character '{c}'")
+            }
+            ScanErrorKind::UnterminatedString => write!(f, "encountered an unterminated string"),
+            ScanErrorKind::UnterminatedComment => write!(f, "encountered an unterminated comment"),
+            ScanErrorKind::InvalidEscape(c) => write!(f, "unknown escape sequence '\\{c}'"),
+            ScanErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+            ScanErrorKind::ParseInt(ref e) => write!(f, "invalid integer literal: {e}"),
+            ScanErrorKind::ParseFloat(ref e) => write!(f, "invalid decimal literal: {e}"),
+            ScanErrorKind::InvalidDigit(c, radix) => {
+                let name = match radix {
+                    2 => "a binary",
+                    8 => "an octal",
+                    16 => "a hexadecimal",
+                    _ => "a decimal",
+                };
+                write!(f, "invalid digit '{c}' in {name} literal")
+            }
+            ScanErrorKind::MissingDigits(after) => write!(f, "expected digits {after}"),
+            ScanErrorKind::MisplacedSeparator => write!(f, "misplaced '_' in a number literal"),
+            ScanErrorKind::DecimalOutOfRange => {
+                write!(
+                    f,
+                    "invalid decimal literal: number too large to be represented"
+                )
+            }
         }
     }
 }
 
 impl error::Error for ScanError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match *self {
-            Self::ParseInt(ref e) => Some(e),
-            Self::ParseFloat(ref e) => Some(e),
+        match self.kind {
+            ScanErrorKind::ParseInt(ref e) => Some(e),
+            ScanErrorKind::ParseFloat(ref e) => Some(e),
             _ => None,
         }
     }
@@ -79,34 +173,237 @@ impl<'a> Scanner<'a> {
     /// advance the scanner by one character
     /// returns false when it reaches eof
     fn advance(&mut self) -> Option<char> {
-        let newline = self.curr_char == '\n';
         if let Some(c) = self.next_char {
             self.next_char = self.chars.next();
             if self.started {
-                self.location.advance(newline);
+                self.location.advance(self.curr_char);
             } else {
                 self.started = true;
             }
+            self.curr_char = c;
             Some(c)
         } else {
             None
         }
     }
 
+    /// the character `n` characters after the next one
+    fn lookahead(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
+    }
+
+    /// advances over the digits of `radix` that come next, which `_`s
+    /// can separate, returns the digits. fails on a `_` that doesn't
+    /// follow the digit scanned last or isn't followed by one
+    fn digits(&mut self, radix: u32, start: Location) -> Result<String, ScanError> {
+        let mut digits = String::new();
+        while let Some(c) = self.next_char.filter(|c| c.is_digit(radix) || *c == '_') {
+            if c == '_' {
+                let between = self.curr_char.is_digit(radix)
+                    && self.lookahead(0).is_some_and(|c| c.is_digit(radix));
+                if !between {
+                    self.advance();
+                    let kind = ScanErrorKind::MisplacedSeparator;
+                    return Err(ScanError::new(kind, start, self.location));
+                }
+            } else {
+                digits.push(c);
+            }
+            self.advance();
+        }
+        Ok(digits)
+    }
+
+    /// the source from the character at `start` up to and including the
+    /// one at `end`
     fn substring(&self, start: Location, end: Location) -> String {
-        self.source[start.char as usize..(end.char + 1) as usize].to_string()
+        let end = end.byte as usize;
+        let len = self.source[end..].chars().next().map_or(0, char::len_utf8);
+        self.source[start.byte as usize..end + len].to_string()
+    }
+}
+
+/// scans the rest of a block comment after its `/*`. block comments
+/// nest, so code containing one can be commented out
+fn block_comment(scanner: &mut Scanner, start: Location) -> Result<(), ScanError> {
+    scanner.advance();
+    let mut depth = 1;
+    while depth > 0 {
+        let c = scanner
+            .advance()
+            .ok_or_else(|| ScanError::new(ScanErrorKind::UnterminatedComment, start, start))?;
+        match (c, scanner.next_char) {
+            ('/', Some('*')) => depth += 1,
+            ('*', Some('/')) => depth -= 1,
+            _ => continue,
+        }
+        scanner.advance();
+    }
+    Ok(())
+}
+
+/// scans the rest of a number literal after its first digit. `0x`, `0o`
+/// and `0b` start hexadecimal, octal and binary ints. decimals have
+/// digits after their dot, an exponent or both. letters and digits of
+/// another radix can't follow a literal
+fn number(scanner: &mut Scanner, first: char, start: Location) -> Result<TokenType, ScanError> {
+    let radix = match (first, scanner.next_char) {
+        ('0', Some('x' | 'X')) => 16,
+        ('0', Some('o' | 'O')) => 8,
+        ('0', Some('b' | 'B')) => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        scanner.advance();
+        let digits = scanner.digits(radix, start)?;
+        end_of_number(scanner, radix, start)?;
+        if digits.is_empty() {
+            let kind = ScanErrorKind::MissingDigits("after the radix prefix");
+            return Err(ScanError::new(kind, start, scanner.location));
+        }
+        return i64::from_str_radix(&digits, radix)
+            .map(TokenType::Integer)
+            .map_err(|e| ScanError::new(ScanErrorKind::ParseInt(e), start, scanner.location));
+    }
+
+    let mut num = first.to_string();
+    num.push_str(&scanner.digits(10, start)?);
+    let mut decimal = false;
+    if scanner.next_char == Some('.') {
+        scanner.advance();
+        num.push('.');
+        let digits = scanner.digits(10, start)?;
+        if digits.is_empty() {
+            let kind = ScanErrorKind::MissingDigits("after the decimal point");
+            return Err(ScanError::new(kind, start, scanner.location));
+        }
+        num.push_str(&digits);
+        decimal = true;
+    }
+    if let Some(e @ ('e' | 'E')) = scanner.next_char {
+        scanner.advance();
+        num.push(e);
+        if let Some(sign @ ('+' | '-')) = scanner.next_char {
+            scanner.advance();
+            num.push(sign);
+        }
+        let digits = scanner.digits(10, start)?;
+        if digits.is_empty() {
+            let kind = ScanErrorKind::MissingDigits("in the exponent");
+            return Err(ScanError::new(kind, start, scanner.location));
+        }
+        num.push_str(&digits);
+        decimal = true;
+    }
+    end_of_number(scanner, 10, start)?;
+
+    let error = |kind| ScanError::new(kind, start, scanner.location);
+    if decimal {
+        match num.parse::<f64>() {
+            Ok(num) if num.is_finite() => Ok(TokenType::Decimal(num)),
+            Ok(_) => Err(error(ScanErrorKind::DecimalOutOfRange)),
+            Err(e) => Err(error(ScanErrorKind::ParseFloat(e))),
+        }
+    } else {
+        num.parse()
+            .map(TokenType::Integer)
+            .map_err(|e| error(ScanErrorKind::ParseInt(e)))
+    }
+}
+
+/// fails if a letter or digit follows the number literal scanned last
+fn end_of_number(scanner: &mut Scanner, radix: u32, start: Location) -> Result<(), ScanError> {
+    match scanner.next_char {
+        Some(c) if c.is_alphanumeric() => {
+            scanner.advance();
+            let kind = ScanErrorKind::InvalidDigit(c, radix);
+            Err(ScanError::new(kind, start, scanner.location))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// scans the rest of a string literal after its opening quote, or after
+/// the `}` closing an interpolated expression. the string either ends,
+/// or goes on with another interpolation after `${`
+fn string(scanner: &mut Scanner, start: Location) -> Result<TokenType, ScanError> {
+    let unterminated = || ScanError::new(ScanErrorKind::UnterminatedString, start, start);
+    let mut text = String::new();
+    loop {
+        match scanner.advance().ok_or_else(unterminated)? {
+            '"' => return Ok(TokenType::String(text)),
+            '$' if scanner.next_char == Some('{') => {
+                scanner.advance();
+                return Ok(TokenType::Interpolation(text));
+            }
+            '\\' => {
+                let escape_start = scanner.location;
+                let c = scanner.advance().ok_or_else(unterminated)?;
+                text.push(escape(scanner, c, escape_start)?);
+            }
+            c => text.push(c),
+        }
+    }
+}
+
+/// the character an escape sequence stands for, `c` being the one after
+/// the backslash at `start`
+fn escape(scanner: &mut Scanner, c: char, start: Location) -> Result<char, ScanError> {
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '"' | '\\' | '$' => Ok(c),
+        'u' => {
+            let invalid = |scanner: &Scanner| {
+                ScanError::new(ScanErrorKind::InvalidUnicodeEscape, start, scanner.location)
+            };
+            if scanner.next_char != Some('{') {
+                return Err(invalid(scanner));
+            }
+            scanner.advance();
+            let mut digits = String::new();
+            while let Some(c) = scanner.next_char.filter(char::is_ascii_hexdigit) {
+                digits.push(c);
+                scanner.advance();
+            }
+            if scanner.next_char != Some('}') || digits.is_empty() || digits.len() > 6 {
+                return Err(invalid(scanner));
+            }
+            scanner.advance();
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| invalid(scanner))
+        }
+        c => Err(ScanError::new(
+            ScanErrorKind::InvalidEscape(c),
+            start,
+            scanner.location,
+        )),
     }
 }
 
 #[derive(Debug)]
 pub struct Tokens {
     tokens: Peekable<vec::IntoIter<Token>>,
+    comments: Vec<Comment>,
 }
 
 impl Tokens {
     pub fn peek(&mut self) -> Option<&Token> {
         self.tokens.peek()
     }
+
+    pub fn peek_mut(&mut self) -> Option<&mut Token> {
+        self.tokens.peek_mut()
+    }
+
+    /// the comments of the script in source order
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
 }
 
 impl Iterator for Tokens {
@@ -117,7 +414,7 @@ impl Iterator for Tokens {
     }
 }
 
-fn scan_tokens(script: &String) -> Result<Vec<Token>, ScanError> {
+fn scan_tokens(script: &String) -> Result<(Vec<Token>, Vec<Comment>), ScanError> {
     let keywords: HashMap<&str, TokenType> = HashMap::from([
         ("and", TokenType::And),
         ("class", TokenType::Class),
@@ -126,6 +423,7 @@ fn scan_tokens(script: &String) -> Result<Vec<Token>, ScanError> {
         ("for", TokenType::For),
         ("fun", TokenType::Fun),
         ("if", TokenType::If),
+        ("in", TokenType::In),
         ("nil", TokenType::Nil),
         ("or", TokenType::Or),
         ("print", TokenType::Print),
@@ -139,6 +437,12 @@ fn scan_tokens(script: &String) -> Result<Vec<Token>, ScanError> {
 
     let mut scanner = Scanner::new(script);
     let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    // the `///` comments waiting for the token they document
+    let mut doc: Option<String> = None;
+    // the strings whose interpolated expression is being scanned, with
+    // the number of braces opened inside that expression
+    let mut interpolations: Vec<(Location, usize)> = Vec::new();
 
     while let Some(c) = scanner.advance() {
         let location_start = scanner.location;
@@ -155,72 +459,89 @@ fn scan_tokens(script: &String) -> Result<Vec<Token>, ScanError> {
         let token_type = match c {
             '(' => TokenType::LeftParen,
             ')' => TokenType::RightParen,
-            '{' => TokenType::LeftBrace,
-            '}' => TokenType::RightBrace,
+            '{' => {
+                if let Some((_, depth)) = interpolations.last_mut() {
+                    *depth += 1;
+                }
+                TokenType::LeftBrace
+            }
+            '}' => match interpolations.last_mut() {
+                Some((start, 0)) => {
+                    let start = *start;
+                    interpolations.pop();
+                    let piece = string(&mut scanner, start)?;
+                    if piece.is_interpolation() {
+                        interpolations.push((start, 0));
+                    }
+                    piece
+                }
+                Some((_, depth)) => {
+                    *depth -= 1;
+                    TokenType::RightBrace
+                }
+                None => TokenType::RightBrace,
+            },
+            '[' => TokenType::LeftBracket,
+            ']' => TokenType::RightBracket,
+            ':' => TokenType::Colon,
             ',' => TokenType::Comma,
             '.' => TokenType::Dot,
             '-' => TokenType::Minus,
             '+' => TokenType::Plus,
             ';' => TokenType::Semicolon,
             '*' => TokenType::Star,
+            '%' => TokenType::Percent,
 
             '!' => if_next('=', TokenType::BangEqual, TokenType::Bang),
             '=' => if_next('=', TokenType::EqualEqual, TokenType::Equal),
             '<' => if_next('=', TokenType::LessEqual, TokenType::Less),
             '>' => if_next('=', TokenType::GreaterEqual, TokenType::Greater),
-            '/' => {
-                if scanner.next_char == Some('/') {
-                    while let Some(c) = scanner.advance() {
-                        if c == '\n' {
-                            break;
+            '/' => match scanner.next_char {
+                Some('/') => {
+                    while scanner.next_char.is_some_and(|c| c != '\n') {
+                        scanner.advance();
+                    }
+                    let text = scanner.substring(location_start, scanner.location);
+                    let text = text.trim_end();
+                    // four or more slashes are a plain comment, often a ruler
+                    if let Some(line) = text.strip_prefix("///").filter(|l| !l.starts_with('/')) {
+                        let line = line.strip_prefix(' ').unwrap_or(line);
+                        match &mut doc {
+                            Some(doc) => {
+                                doc.push('\n');
+                                doc.push_str(line);
+                            }
+                            None => doc = Some(line.to_string()),
                         }
                     }
+                    comments.push(Comment {
+                        text: text.to_string(),
+                        location: location_start,
+                    });
                     continue;
-                } else {
-                    TokenType::Slash
                 }
-            }
+                Some('*') => {
+                    block_comment(&mut scanner, location_start)?;
+                    comments.push(Comment {
+                        text: scanner.substring(location_start, scanner.location),
+                        location: location_start,
+                    });
+                    continue;
+                }
+                _ => TokenType::Slash,
+            },
 
             ' ' | '\t' | '\n' | '\r' => continue,
 
             '"' => {
-                loop {
-                    match scanner.advance() {
-                        Some(c) => {
-                            if c == '"' {
-                                break;
-                            }
-                        }
-                        None => return Err(ScanError::UnterminatedString),
-                    }
+                let piece = string(&mut scanner, location_start)?;
+                if piece.is_interpolation() {
+                    interpolations.push((location_start, 0));
                 }
-                TokenType::String(scanner.substring(location_start + 1, scanner.location - 1))
+                piece
             }
 
-            '0'..='9' => {
-                let mut saw_dot = false;
-                while let Some(c) = scanner.next_char {
-                    if !c.is_numeric() && (c != '.' || saw_dot) {
-                        break;
-                    }
-                    if c == '.' {
-                        saw_dot = true;
-                    }
-                    scanner.advance();
-                }
-                let num = scanner.substring(location_start, scanner.location);
-                if saw_dot {
-                    match num.parse::<f64>() {
-                        Ok(val) => TokenType::Decimal(val),
-                        Err(err) => return Err(ScanError::ParseFloat(err)),
-                    }
-                } else {
-                    match num.parse::<i64>() {
-                        Ok(val) => TokenType::Integer(val),
-                        Err(err) => return Err(ScanError::ParseInt(err)),
-                    }
-                }
-            }
+            '0'..='9' => number(&mut scanner, c, location_start)?,
 
             c if c.is_alphabetic() => {
                 while let Some(c) = scanner.next_char {
@@ -237,7 +558,11 @@ fn scan_tokens(script: &String) -> Result<Vec<Token>, ScanError> {
             }
 
             _ => {
-                return Err(ScanError::UnexpectedToken(c));
+                return Err(ScanError::new(
+                    ScanErrorKind::UnexpectedToken(c),
+                    location_start,
+                    location_start,
+                ));
             }
         };
         let location_end = scanner.location;
@@ -247,22 +572,35 @@ fn scan_tokens(script: &String) -> Result<Vec<Token>, ScanError> {
             location_start,
             location_end,
             lexeme,
+            doc: doc.take(),
         });
     }
+    if let Some((start, _)) = interpolations.pop() {
+        return Err(ScanError::new(
+            ScanErrorKind::UnterminatedString,
+            start,
+            start,
+        ));
+    }
     tokens.push(Token {
         token_type: TokenType::Eof,
         location_start: scanner.location,
         location_end: scanner.location,
         lexeme: "".into(),
+        doc,
     });
-    Ok(tokens)
+    Ok((tokens, comments))
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+/// ordered by position in the source
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Location {
-    /// source[char] == current character
+    /// number of characters before the current one
     char: u64,
 
+    /// source[byte..] starts with the current character
+    byte: u64,
+
     /// number of newline characters up to now
     line: u64,
 
@@ -271,37 +609,36 @@ pub struct Location {
 }
 
 impl Location {
-    fn advance(&mut self, newline: bool) {
+    /// zero based line number
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// zero based column number, counted in characters
+    pub fn column(&self) -> u64 {
+        self.column
+    }
+
+    /// offset into the source in bytes
+    pub fn byte(&self) -> usize {
+        self.byte as usize
+    }
+
+    /// moves past `c`, the current character
+    fn advance(&mut self, c: char) {
         self.char += 1;
+        self.byte += c.len_utf8() as u64;
         self.column += 1;
-        if newline {
+        if c == '\n' {
             self.column = 0;
             self.line += 1;
         }
     }
 }
 
-impl ops::Add<u64> for Location {
-    type Output = Location;
-
-    fn add(self, rhs: u64) -> Self::Output {
-        Location {
-            char: self.char + rhs,
-            column: self.column + rhs,
-            ..self
-        }
-    }
-}
-
-impl ops::Sub<u64> for Location {
-    type Output = Location;
-
-    fn sub(self, rhs: u64) -> Self::Output {
-        Location {
-            char: self.char - rhs,
-            column: self.column - rhs,
-            ..self
-        }
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
     }
 }
 
@@ -311,6 +648,27 @@ pub struct Token {
     pub location_start: Location,
     pub location_end: Location,
     pub lexeme: String,
+    /// the `///` comments right before the token, without their slashes.
+    /// the parser moves them to the name of the declaration they document
+    pub doc: Option<String>,
+}
+
+impl Token {
+    /// the `}` a string piece starts with when it goes on after an
+    /// interpolated expression, as a token of its own
+    pub fn closing_brace(&self) -> Option<Token> {
+        let piece = matches!(
+            self.token_type,
+            TokenType::String(_) | TokenType::Interpolation(_)
+        );
+        (piece && self.lexeme.starts_with('}')).then(|| Token {
+            token_type: TokenType::RightBrace,
+            location_start: self.location_start,
+            location_end: self.location_start,
+            lexeme: "}".into(),
+            doc: None,
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -320,6 +678,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -327,6 +688,7 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     // One or two character tokens.
     Bang,
@@ -343,6 +705,10 @@ pub enum TokenType {
     String(String),
     Integer(i64),
     Decimal(f64),
+    /// the text of a string literal up to a `${` starting an
+    /// interpolated expression. the literal goes on after the `}`
+    /// closing the expression, with another interpolation or a string
+    Interpolation(String),
 
     // Keywords.
     And,
@@ -352,6 +718,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -374,6 +741,22 @@ impl TokenType {
         matches!(self, Self::RightBrace)
     }
 
+    /// the text of a string literal or a piece of one
+    pub fn as_string_piece(&self) -> &str {
+        match self {
+            Self::String(s) | Self::Interpolation(s) => s,
+            _ => unreachable!("{self:?} is not part of a string"),
+        }
+    }
+
+    /// Returns `true` if the token type is [`Interpolation`].
+    ///
+    /// [`Interpolation`]: TokenType::Interpolation
+    #[must_use]
+    pub fn is_interpolation(&self) -> bool {
+        matches!(self, Self::Interpolation(..))
+    }
+
     /// Returns `true` if the token type is [`Identifier`].
     ///
     /// [`Identifier`]: TokenType::Identifier
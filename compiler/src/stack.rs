@@ -0,0 +1,22 @@
+//! room on the stack for the passes that recurse through a script, so
+//! deeply nested or recursive scripts can't overflow the host's stack,
+//! and how deep the calls of a script can go on every backend
+
+/// calls nested deeper than this are reported as a stack overflow. the
+/// top level of the script counts as a call
+pub(crate) const MAX_CALL_DEPTH: usize = 1024;
+
+/// how much stack has to be left for a pass to run on the current one.
+/// blocks and expressions nested [`MAX_NESTING`] deep take up to about
+/// 13 MiB in a debug build
+///
+/// [`MAX_NESTING`]: crate::parser::MAX_NESTING
+const RED_ZONE: usize = 32 * 1024 * 1024;
+
+/// size of the pieces of stack allocated when there isn't enough left
+const SEGMENT: usize = 64 * 1024 * 1024;
+
+/// runs `f`, on a new piece of stack if the current one is running low
+pub(crate) fn ensure<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT, f)
+}
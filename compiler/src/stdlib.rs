@@ -0,0 +1,251 @@
+use std::{
+    cell::RefCell,
+    io::{BufRead, Write},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::value::{List, Map, Native, RValue};
+
+/// where a script's output goes, shared between the backend running it
+/// and the natives that write
+pub type Output = Rc<RefCell<Box<dyn Write>>>;
+
+/// output going to stdout
+pub fn stdout() -> Output {
+    Rc::new(RefCell::new(Box::new(std::io::stdout())))
+}
+
+/// where `input` reads lines from, shared between the backend running a
+/// script and the native. `None` reads stdin, without buffering more
+/// than the line read so that others can keep reading it
+pub type Input = Rc<RefCell<Option<Box<dyn BufRead>>>>;
+
+/// input coming from stdin
+pub fn stdin() -> Input {
+    Rc::new(RefCell::new(None))
+}
+
+/// the native functions every script can call, created once for each
+/// interpreter or vm. `random` and `seed` share one random number
+/// generator, seeded from the system until `seed` is called. `input`
+/// writes its prompt to `out` and reads from `input`
+pub fn natives(out: &Output, input: &Input) -> Vec<Native> {
+    let out = out.clone();
+    let input = input.clone();
+    let rng = Rc::new(RefCell::new(fastrand::Rng::new()));
+    let seed_rng = rng.clone();
+
+    vec![
+        Native::new("clock", 0..=0, |_| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?;
+            Ok(RValue::Decimal(now.as_secs_f64()))
+        }),
+        Native::new("len", 1..=1, |args| {
+            let len = match &args[0] {
+                RValue::String(s) => s.chars().count(),
+                RValue::List(list) => list.borrow().len(),
+                RValue::Map(map) => map.borrow().len(),
+                val => return Err(expected("len", "a string, list or map", val)),
+            };
+            Ok(RValue::Int(len as i64))
+        }),
+        Native::new("str", 1..=1, |args| Ok(RValue::String(args[0].to_string()))),
+        Native::new("int", 1..=1, |args| match &args[0] {
+            RValue::Int(i) => Ok(RValue::Int(*i)),
+            RValue::Decimal(d) => float_to_int(d.trunc()),
+            RValue::String(s) => s
+                .trim()
+                .parse()
+                .map(RValue::Int)
+                .map_err(|_| format!("can't convert '{s}' to int")),
+            val => Err(format!("can't convert {} to int", val.type_name())),
+        }),
+        Native::new("float", 1..=1, |args| match &args[0] {
+            RValue::Int(i) => Ok(RValue::Decimal(*i as f64)),
+            RValue::Decimal(d) => Ok(RValue::Decimal(*d)),
+            RValue::String(s) => s
+                .trim()
+                .parse()
+                .map(RValue::Decimal)
+                .map_err(|_| format!("can't convert '{s}' to decimal")),
+            val => Err(format!("can't convert {} to decimal", val.type_name())),
+        }),
+        Native::new("input", 0..=1, move |args| {
+            if !args.is_empty() {
+                let mut out = out.borrow_mut();
+                write!(out, "{}", args[0])
+                    .and_then(|()| out.flush())
+                    .map_err(|e| format!("unable to print: {e}"))?;
+            }
+            let mut line = String::new();
+            let read = match &mut *input.borrow_mut() {
+                Some(input) => input.read_line(&mut line),
+                None => std::io::stdin().read_line(&mut line),
+            };
+            match read {
+                Ok(0) => Ok(RValue::Null),
+                Ok(_) => {
+                    let len = line.trim_end_matches(['\n', '\r']).len();
+                    line.truncate(len);
+                    Ok(RValue::String(line))
+                }
+                Err(e) => Err(format!("unable to read input: {e}")),
+            }
+        }),
+        Native::new("read_file", 1..=1, |args| {
+            let path = string("read_file", args, 0)?;
+            std::fs::read_to_string(path)
+                .map(RValue::String)
+                .map_err(|e| format!("unable to read '{path}': {e}"))
+        }),
+        Native::new("write_file", 2..=2, |args| {
+            let path = string("write_file", args, 0)?;
+            let contents = string("write_file", args, 1)?;
+            std::fs::write(path, contents)
+                .map(|()| RValue::Null)
+                .map_err(|e| format!("unable to write '{path}': {e}"))
+        }),
+        Native::new("substr", 3..=3, |args| {
+            let s = string("substr", args, 0)?;
+            let start = index("substr", args, 1)?;
+            let len = index("substr", args, 2)?;
+            let count = s.chars().count();
+            if start + len > count {
+                return Err(format!(
+                    "substring {start}..{} is out of bounds for a string of length {count}",
+                    start + len
+                ));
+            }
+            Ok(RValue::String(s.chars().skip(start).take(len).collect()))
+        }),
+        Native::new("split", 2..=2, |args| {
+            let s = string("split", args, 0)?;
+            let separator = string("split", args, 1)?;
+            if separator.is_empty() {
+                return Err("split needs a non-empty separator".to_string());
+            }
+            let parts = s
+                .split(separator)
+                .map(|part| RValue::String(part.to_string()))
+                .collect();
+            Ok(RValue::List(Rc::new(RefCell::new(parts))))
+        }),
+        Native::new("push", 2..=2, |args| {
+            list("push", args, 0)?.borrow_mut().push(args[1].clone());
+            Ok(RValue::Null)
+        }),
+        Native::new("pop", 1..=1, |args| {
+            list("pop", args, 0)?
+                .borrow_mut()
+                .pop()
+                .ok_or_else(|| "can't pop from an empty list".to_string())
+        }),
+        Native::new("keys", 1..=1, |args| {
+            let keys = map("keys", args, 0)?
+                .borrow()
+                .keys()
+                .cloned()
+                .map(RValue::from)
+                .collect();
+            Ok(RValue::List(Rc::new(RefCell::new(keys))))
+        }),
+        Native::new("values", 1..=1, |args| {
+            let values = map("values", args, 0)?.borrow().values().cloned().collect();
+            Ok(RValue::List(Rc::new(RefCell::new(values))))
+        }),
+        Native::new("abs", 1..=1, |args| match number("abs", args, 0)? {
+            RValue::Int(i) => i
+                .checked_abs()
+                .map(RValue::Int)
+                .ok_or_else(|| format!("absolute value of {i} overflows")),
+            RValue::Decimal(d) => Ok(RValue::Decimal(d.abs())),
+            _ => unreachable!("number only returns ints and decimals"),
+        }),
+        Native::new("sqrt", 1..=1, |args| {
+            Ok(RValue::Decimal(decimal("sqrt", args, 0)?.sqrt()))
+        }),
+        Native::new("floor", 1..=1, |args| match number("floor", args, 0)? {
+            RValue::Decimal(d) => float_to_int(d.floor()),
+            int => Ok(int.clone()),
+        }),
+        Native::new("random", 0..=1, move |args| {
+            let mut rng = rng.borrow_mut();
+            match args {
+                [] => Ok(RValue::Decimal(rng.f64())),
+                _ => match index("random", args, 0)? {
+                    0 => Err("random needs an upper bound above 0".to_string()),
+                    max => Ok(RValue::Int(rng.i64(0..max as i64))),
+                },
+            }
+        }),
+        Native::new("seed", 1..=1, move |args| {
+            let RValue::Int(seed) = args[0] else {
+                return Err(expected("seed", "an int", &args[0]));
+            };
+            seed_rng.borrow_mut().seed(seed as u64);
+            Ok(RValue::Null)
+        }),
+    ]
+}
+
+fn expected(fun: &str, ty: &str, val: &RValue) -> String {
+    format!("{fun} expects {ty}, not {}", val.type_name())
+}
+
+fn string<'a>(fun: &str, args: &'a [RValue], i: usize) -> Result<&'a str, String> {
+    match &args[i] {
+        RValue::String(s) => Ok(s),
+        val => Err(expected(fun, "a string", val)),
+    }
+}
+
+fn list<'a>(fun: &str, args: &'a [RValue], i: usize) -> Result<&'a List, String> {
+    match &args[i] {
+        RValue::List(list) => Ok(list),
+        val => Err(expected(fun, "a list", val)),
+    }
+}
+
+fn map<'a>(fun: &str, args: &'a [RValue], i: usize) -> Result<&'a Map, String> {
+    match &args[i] {
+        RValue::Map(map) => Ok(map),
+        val => Err(expected(fun, "a map", val)),
+    }
+}
+
+fn number<'a>(fun: &str, args: &'a [RValue], i: usize) -> Result<&'a RValue, String> {
+    match &args[i] {
+        val @ (RValue::Int(_) | RValue::Decimal(_)) => Ok(val),
+        val => Err(expected(fun, "a number", val)),
+    }
+}
+
+fn decimal(fun: &str, args: &[RValue], i: usize) -> Result<f64, String> {
+    match number(fun, args, i)? {
+        RValue::Int(i) => Ok(*i as f64),
+        RValue::Decimal(d) => Ok(*d),
+        _ => unreachable!("number only returns ints and decimals"),
+    }
+}
+
+/// a non-negative int, used for positions and lengths
+fn index(fun: &str, args: &[RValue], i: usize) -> Result<usize, String> {
+    match &args[i] {
+        RValue::Int(i) => {
+            usize::try_from(*i).map_err(|_| format!("{fun} expects a non-negative int, not {i}"))
+        }
+        val => Err(expected(fun, "an int", val)),
+    }
+}
+
+/// converts a whole decimal, failing if it doesn't fit
+fn float_to_int(d: f64) -> Result<RValue, String> {
+    if d.is_finite() && d >= i64::MIN as f64 && d < i64::MAX as f64 {
+        Ok(RValue::Int(d as i64))
+    } else {
+        Err(format!("{d} doesn't fit in an int"))
+    }
+}
@@ -0,0 +1,671 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::{Deref, DerefMut, RangeInclusive},
+    rc::Rc,
+};
+
+use crate::{interpreter, vm};
+
+/// a runtime value. lists and maps are shared, assigning one to a new
+/// variable doesn't copy it
+///
+/// arithmetic and comparisons between an `Int` and a `Decimal` promote
+/// the `Int` to a `Decimal` first. operations on two `Int`s stay `Int`s
+#[derive(Debug, Clone)]
+pub enum RValue {
+    Boolean(bool),
+    String(String),
+    Int(i64),
+    Decimal(f64),
+    /// function of the tree-walking interpreter
+    Function(interpreter::Function),
+    /// function compiled to bytecode
+    Closure(Rc<vm::Closure>),
+    /// method of the bytecode vm together with its receiver
+    BoundMethod(Rc<vm::BoundMethod>),
+    /// function implemented in rust, callable from either backend
+    Native(Rc<Native>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    List(List),
+    Map(Map),
+    Null,
+}
+
+pub type List = Rc<RefCell<Items>>;
+
+pub type Map = Rc<RefCell<Entries>>;
+
+/// how deep lists and maps nested in each other are written out. the
+/// ones nested deeper are written as `[…]` or `{…}`
+const MAX_SHOWN: usize = 100;
+
+/// the elements of a list
+#[derive(Debug, Default)]
+pub struct Items(Vec<RValue>);
+
+/// the entries of a map, ordered by key so that iterating it is
+/// deterministic
+#[derive(Debug, Default)]
+pub struct Entries(BTreeMap<Key, RValue>);
+
+impl From<Vec<RValue>> for Items {
+    fn from(items: Vec<RValue>) -> Self {
+        Self(items)
+    }
+}
+
+impl From<BTreeMap<Key, RValue>> for Entries {
+    fn from(entries: BTreeMap<Key, RValue>) -> Self {
+        Self(entries)
+    }
+}
+
+impl FromIterator<RValue> for Items {
+    fn from_iter<I: IntoIterator<Item = RValue>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl FromIterator<(Key, RValue)> for Entries {
+    fn from_iter<I: IntoIterator<Item = (Key, RValue)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Deref for Items {
+    type Target = Vec<RValue>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Items {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Deref for Entries {
+    type Target = BTreeMap<Key, RValue>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Entries {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for Items {
+    fn drop(&mut self) {
+        drop_all(std::mem::take(&mut self.0));
+    }
+}
+
+impl Drop for Entries {
+    fn drop(&mut self) {
+        drop_all(std::mem::take(&mut self.0).into_values().collect());
+    }
+}
+
+/// drops values one after another, moving the contents of the lists,
+/// maps, instances and functions that are dropped with them onto
+/// `pending`. dropping them recursively would overflow the stack on
+/// deeply nested ones
+pub(crate) fn drop_all(mut pending: Vec<RValue>) {
+    while let Some(val) = pending.pop() {
+        match val {
+            RValue::List(list) => {
+                if let Ok(items) = Rc::try_unwrap(list) {
+                    pending.append(&mut items.into_inner().0);
+                }
+            }
+            RValue::Map(map) => {
+                if let Ok(entries) = Rc::try_unwrap(map) {
+                    let entries = std::mem::take(&mut entries.into_inner().0);
+                    pending.extend(entries.into_values());
+                }
+            }
+            RValue::Instance(instance) => {
+                if let Ok(instance) = Rc::try_unwrap(instance) {
+                    let fields = std::mem::take(&mut instance.into_inner().fields);
+                    pending.extend(fields.into_values());
+                }
+            }
+            RValue::Function(mut function) => {
+                pending.append(&mut function.take_captured());
+            }
+            RValue::Closure(closure) => {
+                if let Ok(mut closure) = Rc::try_unwrap(closure) {
+                    pending.append(&mut closure.take_captured());
+                }
+            }
+            RValue::BoundMethod(bound) => {
+                if let Ok(vm::BoundMethod { receiver, method }) = Rc::try_unwrap(bound) {
+                    pending.push(receiver);
+                    pending.push(RValue::Closure(method));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// a value that can be used as a map key. decimals are left out as NaN
+/// isn't equal to itself
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Null,
+    Boolean(bool),
+    Int(i64),
+    String(String),
+}
+
+impl TryFrom<&RValue> for Key {
+    type Error = String;
+
+    fn try_from(val: &RValue) -> Result<Self, Self::Error> {
+        match val {
+            RValue::Null => Ok(Key::Null),
+            RValue::Boolean(b) => Ok(Key::Boolean(*b)),
+            RValue::Int(i) => Ok(Key::Int(*i)),
+            RValue::String(s) => Ok(Key::String(s.clone())),
+            _ => Err(format!("can't use {} as a map key", val.type_name())),
+        }
+    }
+}
+
+impl From<Key> for RValue {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Null => RValue::Null,
+            Key::Boolean(b) => RValue::Boolean(b),
+            Key::Int(i) => RValue::Int(i),
+            Key::String(s) => RValue::String(s),
+        }
+    }
+}
+
+pub struct Class {
+    name: String,
+    superclass: Option<Rc<Class>>,
+    /// functions of whichever backend created the class
+    methods: HashMap<String, RValue>,
+}
+
+impl Class {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<String, RValue>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    /// looks up a method on the class, then on its superclasses
+    pub fn find_method(&self, name: &str) -> Option<&RValue> {
+        self.methods.get(name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+pub struct Instance {
+    class: Rc<Class>,
+    fields: HashMap<String, RValue>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn class(&self) -> &Rc<Class> {
+        &self.class
+    }
+
+    pub fn field(&self, name: &str) -> Option<&RValue> {
+        self.fields.get(name)
+    }
+
+    pub fn set_field(&mut self, name: String, val: RValue) {
+        self.fields.insert(name, val);
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        drop_all(std::mem::take(&mut self.fields).into_values().collect());
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
+
+pub type NativeFn = dyn Fn(&[RValue]) -> Result<RValue, String>;
+
+pub struct Native {
+    name: String,
+    /// how many arguments the function accepts
+    arity: RangeInclusive<usize>,
+    fun: Box<NativeFn>,
+}
+
+impl Native {
+    pub fn new(
+        name: impl Into<String>,
+        arity: RangeInclusive<usize>,
+        fun: impl Fn(&[RValue]) -> Result<RValue, String> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            arity,
+            fun: Box::new(fun),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// checks the argument count before calling the function
+    pub fn call(&self, args: &[RValue]) -> Result<RValue, String> {
+        if !self.arity.contains(&args.len()) {
+            let expected = match (self.arity.start(), self.arity.end()) {
+                (min, max) if min == max => min.to_string(),
+                (min, max) => format!("{min} to {max}"),
+            };
+            return Err(format!(
+                "expected {expected} arguments but got {}",
+                args.len()
+            ));
+        }
+        (self.fun)(args)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl RValue {
+    pub fn is_truthy(&self) -> Result<bool, String> {
+        match *self {
+            RValue::Boolean(b) => Ok(b),
+            RValue::Int(i) => Ok(i != 0),
+            RValue::Null => Ok(false),
+            _ => Err(format!(
+                "can't establish truthyness for {}",
+                self.type_name()
+            )),
+        }
+    }
+
+    /// orders numbers and strings. `None` if either side is NaN
+    pub fn compare(&self, rhs: &RValue) -> Result<Option<Ordering>, String> {
+        match (self, rhs) {
+            (RValue::Int(x), RValue::Int(y)) => Ok(Some(x.cmp(y))),
+            (RValue::Int(i), RValue::Decimal(d)) => Ok((*i as f64).partial_cmp(d)),
+            (RValue::Decimal(d), RValue::Int(i)) => Ok(d.partial_cmp(&(*i as f64))),
+            (RValue::Decimal(x), RValue::Decimal(y)) => Ok(x.partial_cmp(y)),
+            (RValue::String(a), RValue::String(b)) => Ok(Some(a.cmp(b))),
+            _ => Err(format!(
+                "can't compare {} with {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+
+    /// reads `self[index]` from a list, map or string
+    pub fn index(&self, index: &RValue) -> Result<RValue, String> {
+        match (self, index) {
+            (RValue::List(list), RValue::Int(i)) => {
+                let list = list.borrow();
+                position(*i, list.len(), "list").map(|i| list[i].clone())
+            }
+            (RValue::String(s), RValue::Int(i)) => {
+                let i = position(*i, s.chars().count(), "string")?;
+                Ok(RValue::String(s.chars().nth(i).into_iter().collect()))
+            }
+            (RValue::Map(map), key) => map
+                .borrow()
+                .get(&Key::try_from(key)?)
+                .cloned()
+                .ok_or_else(|| format!("key {} is not in the map", Repr(key))),
+            (RValue::List(_) | RValue::String(_), _) => Err(format!(
+                "{} indices must be ints, not {}",
+                self.type_name(),
+                index.type_name()
+            )),
+            _ => Err(format!("can't index into {}", self.type_name())),
+        }
+    }
+
+    /// `self[index] = val` on a list or map
+    pub fn set_index(&self, index: &RValue, val: RValue) -> Result<(), String> {
+        match (self, index) {
+            (RValue::List(list), RValue::Int(i)) => {
+                let mut list = list.borrow_mut();
+                let i = position(*i, list.len(), "list")?;
+                list[i] = val;
+                Ok(())
+            }
+            (RValue::Map(map), key) => {
+                map.borrow_mut().insert(Key::try_from(key)?, val);
+                Ok(())
+            }
+            (RValue::List(_), _) => Err(format!(
+                "list indices must be ints, not {}",
+                index.type_name()
+            )),
+            _ => Err(format!("can't assign to an index of {}", self.type_name())),
+        }
+    }
+
+    /// what a `for in` loop visits: the elements of a list, the keys of
+    /// a map or the characters of a string. a list is visited as it is
+    /// changed by the loop, the others are copied first
+    pub fn iterable(&self) -> Result<List, String> {
+        match self {
+            RValue::List(list) => Ok(list.clone()),
+            RValue::Map(map) => {
+                let keys = map.borrow().keys().cloned().map(RValue::from).collect();
+                Ok(Rc::new(RefCell::new(Items(keys))))
+            }
+            RValue::String(s) => {
+                let chars = s.chars().map(|c| RValue::String(c.into())).collect();
+                Ok(Rc::new(RefCell::new(Items(chars))))
+            }
+            _ => Err(format!("can't iterate over {}", self.type_name())),
+        }
+    }
+
+    /// the value as stored into a variable declared as a decimal: ints
+    /// are converted, anything else the checker let through is kept
+    pub fn promote(self) -> RValue {
+        match self {
+            RValue::Int(i) => RValue::Decimal(i as f64),
+            val => val,
+        }
+    }
+
+    /// name of the value's type as shown in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RValue::Boolean(_) => "bool",
+            RValue::String(_) => "string",
+            RValue::Int(_) => "int",
+            RValue::Decimal(_) => "decimal",
+            RValue::Function(_)
+            | RValue::Closure(_)
+            | RValue::BoundMethod(_)
+            | RValue::Native(_) => "function",
+            RValue::Class(_) => "class",
+            RValue::Instance(_) => "instance",
+            RValue::List(_) => "list",
+            RValue::Map(_) => "map",
+            RValue::Null => "nil",
+        }
+    }
+}
+
+/// values of different types are never equal, except for numbers which
+/// are compared after promotion. functions, classes, instances, lists
+/// and maps are equal only to themselves
+impl PartialEq for RValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RValue::Boolean(a), RValue::Boolean(b)) => a == b,
+            (RValue::String(a), RValue::String(b)) => a == b,
+            (RValue::Int(x), RValue::Int(y)) => x == y,
+            (RValue::Int(i), RValue::Decimal(d)) | (RValue::Decimal(d), RValue::Int(i)) => {
+                *i as f64 == *d
+            }
+            (RValue::Decimal(x), RValue::Decimal(y)) => x == y,
+            (RValue::Function(a), RValue::Function(b)) => a == b,
+            (RValue::Closure(a), RValue::Closure(b)) => Rc::ptr_eq(a, b),
+            (RValue::BoundMethod(a), RValue::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            (RValue::Native(a), RValue::Native(b)) => Rc::ptr_eq(a, b),
+            (RValue::Class(a), RValue::Class(b)) => Rc::ptr_eq(a, b),
+            (RValue::Instance(a), RValue::Instance(b)) => Rc::ptr_eq(a, b),
+            (RValue::List(a), RValue::List(b)) => Rc::ptr_eq(a, b),
+            (RValue::Map(a), RValue::Map(b)) => Rc::ptr_eq(a, b),
+            (RValue::Null, RValue::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false, &mut vec![])
+    }
+}
+
+impl RValue {
+    /// writes the value, quoting strings if `quoted`. `shown` holds the
+    /// collections being written, so that one containing itself writes
+    /// that as `[...]` or `{...}` instead of never finishing, and one
+    /// nested deeper than [`MAX_SHOWN`] as `[…]` or `{…}`
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        quoted: bool,
+        shown: &mut Vec<*const ()>,
+    ) -> fmt::Result {
+        match self {
+            RValue::Boolean(b) => write!(f, "{b}"),
+            RValue::String(s) if quoted => write!(f, "{s:?}"),
+            RValue::String(s) => write!(f, "{s}"),
+            RValue::Int(i) => write!(f, "{i}"),
+            RValue::Decimal(d) => write!(f, "{d}"),
+            RValue::Function(fun) => write!(f, "{fun:?}"),
+            RValue::Closure(closure) => write!(f, "{closure:?}"),
+            RValue::BoundMethod(method) => write!(f, "{method:?}"),
+            RValue::Native(native) => write!(f, "{native:?}"),
+            RValue::Class(class) => write!(f, "{class:?}"),
+            RValue::Instance(instance) => write!(f, "{:?}", instance.borrow()),
+            RValue::List(list) => {
+                let ptr = Rc::as_ptr(list).cast();
+                if shown.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                if shown.len() == MAX_SHOWN {
+                    return write!(f, "[…]");
+                }
+                shown.push(ptr);
+                write!(f, "[")?;
+                for (i, val) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    val.write(f, true, shown)?;
+                }
+                shown.pop();
+                write!(f, "]")
+            }
+            RValue::Map(map) => {
+                let ptr = Rc::as_ptr(map).cast();
+                if shown.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                if shown.len() == MAX_SHOWN {
+                    return write!(f, "{{…}}");
+                }
+                shown.push(ptr);
+                write!(f, "{{")?;
+                for (i, (key, val)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    RValue::from(key.clone()).write(f, true, shown)?;
+                    write!(f, ": ")?;
+                    val.write(f, true, shown)?;
+                }
+                shown.pop();
+                write!(f, "}}")
+            }
+            RValue::Null => write!(f, "nil"),
+        }
+    }
+}
+
+/// displays strings quoted, as they are inside lists and maps
+struct Repr<'a>(&'a RValue);
+
+impl fmt::Display for Repr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.write(f, true, &mut vec![])
+    }
+}
+
+/// the result of integer arithmetic, or an error naming the operation
+/// that overflowed. decimals follow IEEE 754 and never fail
+fn checked(result: Option<i64>, operation: impl FnOnce() -> String) -> Result<RValue, String> {
+    result
+        .map(RValue::Int)
+        .ok_or_else(|| format!("integer overflow in {}", operation()))
+}
+
+/// checks that `index` is within a collection of `len` items
+fn position(index: i64, len: usize, collection: &str) -> Result<usize, String> {
+    usize::try_from(index)
+        .ok()
+        .filter(|i| *i < len)
+        .ok_or_else(|| format!("index {index} is out of bounds for a {collection} of length {len}"))
+}
+
+impl<'r> std::ops::Add<&'r RValue> for &RValue {
+    type Output = Result<RValue, String>;
+
+    fn add(self, rhs: &'r RValue) -> Self::Output {
+        match (self, rhs) {
+            (RValue::Int(x), RValue::Int(y)) => checked(x.checked_add(*y), || format!("{x} + {y}")),
+            (RValue::Int(i), RValue::Decimal(d)) => Ok(RValue::Decimal(*i as f64 + d)),
+            (RValue::Decimal(d), RValue::Int(i)) => Ok(RValue::Decimal(d + *i as f64)),
+            (RValue::Decimal(x), RValue::Decimal(y)) => Ok(RValue::Decimal(x + y)),
+            (RValue::String(a), RValue::String(b)) => Ok(RValue::String(a.clone() + b)),
+            _ => Err(format!(
+                "invalid types for addition: {} + {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl<'r> std::ops::Sub<&'r RValue> for &RValue {
+    type Output = Result<RValue, String>;
+
+    fn sub(self, rhs: &'r RValue) -> Self::Output {
+        match (self, rhs) {
+            (RValue::Int(x), RValue::Int(y)) => checked(x.checked_sub(*y), || format!("{x} - {y}")),
+            (RValue::Int(i), RValue::Decimal(d)) => Ok(RValue::Decimal(*i as f64 - d)),
+            (RValue::Decimal(d), RValue::Int(i)) => Ok(RValue::Decimal(d - *i as f64)),
+            (RValue::Decimal(x), RValue::Decimal(y)) => Ok(RValue::Decimal(x - y)),
+            _ => Err(format!(
+                "invalid types for subtraction: {} - {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl<'r> std::ops::Mul<&'r RValue> for &RValue {
+    type Output = Result<RValue, String>;
+
+    fn mul(self, rhs: &'r RValue) -> Self::Output {
+        match (self, rhs) {
+            (RValue::Int(x), RValue::Int(y)) => checked(x.checked_mul(*y), || format!("{x} * {y}")),
+            (RValue::Int(i), RValue::Decimal(d)) => Ok(RValue::Decimal(*i as f64 * d)),
+            (RValue::Decimal(d), RValue::Int(i)) => Ok(RValue::Decimal(d * *i as f64)),
+            (RValue::Decimal(x), RValue::Decimal(y)) => Ok(RValue::Decimal(x * y)),
+            _ => Err(format!(
+                "invalid types for multiplication: {} * {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl<'r> std::ops::Div<&'r RValue> for &RValue {
+    type Output = Result<RValue, String>;
+
+    fn div(self, rhs: &'r RValue) -> Self::Output {
+        match (self, rhs) {
+            (RValue::Int(_), RValue::Int(0)) => Err("division by zero".to_string()),
+            (RValue::Int(x), RValue::Int(y)) => checked(x.checked_div(*y), || format!("{x} / {y}")),
+            (RValue::Int(i), RValue::Decimal(d)) => Ok(RValue::Decimal(*i as f64 / d)),
+            (RValue::Decimal(d), RValue::Int(i)) => Ok(RValue::Decimal(d / *i as f64)),
+            (RValue::Decimal(x), RValue::Decimal(y)) => Ok(RValue::Decimal(x / y)),
+            _ => Err(format!(
+                "invalid types for division: {} / {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+/// the remainder has the sign of the dividend, like in rust
+impl<'r> std::ops::Rem<&'r RValue> for &RValue {
+    type Output = Result<RValue, String>;
+
+    fn rem(self, rhs: &'r RValue) -> Self::Output {
+        match (self, rhs) {
+            (RValue::Int(_), RValue::Int(0)) => Err("division by zero".to_string()),
+            (RValue::Int(x), RValue::Int(y)) => checked(x.checked_rem(*y), || format!("{x} % {y}")),
+            (RValue::Int(i), RValue::Decimal(d)) => Ok(RValue::Decimal(*i as f64 % d)),
+            (RValue::Decimal(d), RValue::Int(i)) => Ok(RValue::Decimal(d % *i as f64)),
+            (RValue::Decimal(x), RValue::Decimal(y)) => Ok(RValue::Decimal(x % y)),
+            _ => Err(format!(
+                "invalid types for modulo: {} % {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl std::ops::Neg for &RValue {
+    type Output = Result<RValue, String>;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            RValue::Int(i) => checked(i.checked_neg(), || format!("-({i})")),
+            RValue::Decimal(d) => Ok(RValue::Decimal(-d)),
+            _ => Err(format!("can't negate {}", self.type_name())),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::rc::Rc;
+
+use crate::{scanner::Token, value::RValue};
+
+use super::value::Function;
+
+/// a single vm instruction. operands follow the opcode in the byte
+/// stream, their layout is listed next to each instruction. `u16`
+/// operands are big endian
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// constant: u16
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// slot: u8
+    GetLocal,
+    /// slot: u8
+    SetLocal,
+    /// name: u16
+    GetGlobal,
+    /// name: u16
+    DefineGlobal,
+    /// name: u16
+    SetGlobal,
+    /// index: u8
+    GetUpvalue,
+    /// index: u8
+    SetUpvalue,
+    /// name: u16
+    GetProperty,
+    /// name: u16
+    SetProperty,
+    /// name: u16. pops the superclass and the receiver
+    GetSuper,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Not,
+    Negate,
+    /// converts an int on top of the stack to a decimal, for storing it
+    /// into a variable declared as one
+    Promote,
+    Print,
+    /// forward offset: u16
+    Jump,
+    /// forward offset: u16. pops the condition
+    JumpIfFalse,
+    /// backward offset: u16
+    Loop,
+    /// argument count: u8
+    Call,
+    /// function: u16, then an `is_local: u8, index: u8` pair per upvalue
+    Closure,
+    CloseUpvalue,
+    Return,
+    /// checks that the value on top of the stack can be inherited from
+    Inherit,
+    /// name: u16, method count: u8, has superclass: u8. pops the
+    /// methods, the superclass stays below them as the `super` local
+    Class,
+    /// element count: u16
+    List,
+    /// entry count: u16. pops a key and a value per entry
+    Map,
+    /// pops the index and the collection
+    GetIndex,
+    /// pops the value, the index and the collection, pushes the value
+    SetIndex,
+    /// replaces the value on top of the stack with the list a `for in`
+    /// loop visits
+    Iter,
+    /// slot: u8, forward offset: u16. the slot holds the list from
+    /// `Iter` and the one after it the position in it. pushes the next
+    /// element or jumps when there is none left
+    ForIter,
+    /// value count: u16. pops the values and pushes them joined into a
+    /// string
+    Interpolate,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 45] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Modulo,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Promote,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Inherit,
+        OpCode::Class,
+        OpCode::List,
+        OpCode::Map,
+        OpCode::GetIndex,
+        OpCode::SetIndex,
+        OpCode::Iter,
+        OpCode::ForIter,
+        OpCode::Interpolate,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+/// an entry of the constant pool
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Value(RValue),
+    /// name of a variable, property or class
+    Name(Rc<str>),
+    Function(Rc<Function>),
+}
+
+/// compiled bytecode of a single function
+#[derive(Debug, Default)]
+pub struct Chunk {
+    code: Vec<u8>,
+    constants: Vec<Constant>,
+    /// line table. every entry holds the offset of the first byte that
+    /// was compiled from the token, runs of bytes from the same token
+    /// share an entry
+    lines: Vec<(usize, Token)>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, token: &Token) {
+        let same_token = self
+            .lines
+            .last()
+            .is_some_and(|(_, last)| last.location_start == token.location_start);
+        if !same_token {
+            self.lines.push((self.code.len(), token.clone()));
+        }
+        self.code.push(byte);
+    }
+
+    /// appends a byte compiled from the same token as the one before it
+    pub fn push(&mut self, byte: u8) {
+        self.code.push(byte);
+    }
+
+    /// overwrites the u16 operand at `offset`
+    pub fn patch(&mut self, offset: usize, val: u16) {
+        self.code[offset..offset + 2].copy_from_slice(&val.to_be_bytes());
+    }
+
+    /// adds a constant to the pool, reusing the slot of an equal name
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        if let Constant::Name(name) = &constant {
+            let existing = self
+                .constants
+                .iter()
+                .position(|c| matches!(c, Constant::Name(n) if n == name));
+            if let Some(index) = existing {
+                return index;
+            }
+        }
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn constant(&self, index: usize) -> &Constant {
+        &self.constants[index]
+    }
+
+    /// the token the byte at `offset` was compiled from
+    pub fn token(&self, offset: usize) -> &Token {
+        let entry = self.lines.partition_point(|(start, _)| *start <= offset);
+        &self.lines[entry - 1].1
+    }
+}
@@ -0,0 +1,742 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{self, Expr, Ident, Statement},
+    scanner::{Location, Token, TokenType},
+    stack,
+    value::RValue,
+};
+
+use super::{
+    chunk::{Chunk, Constant, OpCode},
+    error::CompileError,
+    value::Function,
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// compiles a script into the function the vm runs it as
+pub fn compile(stmts: &[Statement]) -> Result<Rc<Function>> {
+    let mut compiler = Compiler::default();
+    compiler
+        .functions
+        .push(FunctionState::new(String::new(), FunctionKind::Script));
+    for stmt in stmts {
+        stack::ensure(|| compiler.statement(stmt))?;
+    }
+
+    // the implicit return can't fail, so it doesn't need a real token
+    let end = Token {
+        token_type: TokenType::Eof,
+        location_start: Location::default(),
+        location_end: Location::default(),
+        lexeme: String::new(),
+        doc: None,
+    };
+    compiler.emit_return(&end);
+    let script = compiler.functions.pop().expect("script is compiled last");
+    Ok(Rc::new(script.function))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    /// `init` methods always return `this`
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    /// scope depth the local was declared at. `None` while its
+    /// initializer is being compiled
+    depth: Option<usize>,
+    /// closed over by a closure, so it has to be moved off the stack
+    /// when its scope ends
+    is_captured: bool,
+}
+
+/// where a closure finds one of its upvalues when it's created
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct UpvalueRef {
+    /// a local of the enclosing function, not one of its upvalues
+    is_local: bool,
+    index: u8,
+}
+
+/// a function whose body is being compiled
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    /// mirrors the vm stack slots of the function's frame
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    /// the function is declared to return a decimal
+    returns_decimal: bool,
+}
+
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind) -> Self {
+        // slot 0 holds the callee, or the receiver for methods
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+        Self {
+            function: Function {
+                name,
+                ..Function::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver.into(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            returns_decimal: false,
+        }
+    }
+}
+
+/// how a variable is accessed
+#[derive(Clone, Copy)]
+enum Var {
+    Local(u8),
+    Upvalue(u8),
+    Global(u16),
+}
+
+#[derive(Default)]
+struct Compiler {
+    /// the function being compiled and the ones enclosing it, innermost last
+    functions: Vec<FunctionState>,
+}
+
+impl Compiler {
+    fn statement(&mut self, stmt: &Statement) -> Result<()> {
+        match stmt {
+            Statement::Print(expr) => {
+                self.expr(expr)?;
+                self.emit(OpCode::Print, expr.first_token());
+            }
+            Statement::Expr(expr) => {
+                self.expr(expr)?;
+                self.emit(OpCode::Pop, expr.first_token());
+            }
+            Statement::Var(ident, _, init) => {
+                if self.current().scope_depth > 0 {
+                    self.declare_local(ident)?;
+                }
+                match init {
+                    Some(expr) => self.expr(expr)?,
+                    None => self.emit(OpCode::Nil, ident.token()),
+                }
+                if ident.is_decimal() {
+                    self.emit(OpCode::Promote, ident.token());
+                }
+                self.define_variable(ident)?;
+            }
+            Statement::Block(block) => {
+                self.current().scope_depth += 1;
+                for stmt in &block.stmts {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+            }
+            Statement::Function(decl) => {
+                if self.current().scope_depth > 0 {
+                    // initialized right away so the function can recurse
+                    self.declare_local(&decl.name)?;
+                    self.mark_initialized();
+                }
+                self.function(decl, FunctionKind::Function)?;
+                self.define_variable(&decl.name)?;
+            }
+            Statement::Class(decl) => self.class_decl(decl)?,
+            Statement::Return(tok, expr) => match self.current().kind {
+                FunctionKind::Initializer => self.emit_return(tok),
+                _ => {
+                    match expr {
+                        Some(expr) => self.expr(expr)?,
+                        None => self.emit(OpCode::Nil, tok),
+                    }
+                    if self.current().returns_decimal {
+                        self.emit(OpCode::Promote, tok);
+                    }
+                    self.emit(OpCode::Return, tok);
+                }
+            },
+            Statement::If(cond, when_true, when_false) => {
+                self.expr(cond)?;
+                let skip_true = self.emit_jump(OpCode::JumpIfFalse, cond.first_token());
+                self.statement(when_true)?;
+                let skip_false = self.emit_jump(OpCode::Jump, cond.first_token());
+                self.patch_jump(skip_true, cond.first_token())?;
+                if let Some(when_false) = when_false {
+                    self.statement(when_false)?;
+                }
+                self.patch_jump(skip_false, cond.first_token())?;
+            }
+            Statement::While(cond, body) => {
+                let start = self.chunk().code().len();
+                self.expr(cond)?;
+                let exit = self.emit_jump(OpCode::JumpIfFalse, cond.first_token());
+                self.statement(body)?;
+                self.emit_loop(start, cond.first_token())?;
+                self.patch_jump(exit, cond.first_token())?;
+            }
+            Statement::ForIn(ident, keyword, items, body) => {
+                // the list and the position in it are kept in locals
+                // that can't be named from the script
+                self.current().scope_depth += 1;
+                self.expr(items)?;
+                self.emit(OpCode::Iter, keyword);
+                self.add_local(String::new(), keyword)?;
+                self.mark_initialized();
+                self.emit_constant(OpCode::Constant, Constant::Value(RValue::Int(0)), keyword)?;
+                self.add_local(String::new(), keyword)?;
+                self.mark_initialized();
+                let slot = (self.current().locals.len() - 2) as u8;
+
+                let start = self.chunk().code().len();
+                self.emit(OpCode::ForIter, keyword);
+                self.emit_byte(slot, keyword);
+                self.emit_u16(u16::MAX, keyword);
+                let exit = self.chunk().code().len() - 2;
+
+                // a fresh scope per iteration, so closures capture the
+                // element they were created for
+                self.current().scope_depth += 1;
+                self.declare_local(ident)?;
+                self.mark_initialized();
+                self.statement(body)?;
+                self.end_scope();
+                self.emit_loop(start, keyword)?;
+                self.patch_jump(exit, keyword)?;
+                self.end_scope();
+            }
+            Statement::Empty => (),
+        }
+        Ok(())
+    }
+
+    fn class_decl(&mut self, decl: &ast::Class) -> Result<()> {
+        let name = decl.name.token();
+
+        // declared up front so methods can refer to their own class
+        if self.current().scope_depth > 0 {
+            self.declare_local(&decl.name)?;
+        }
+        self.emit(OpCode::Nil, name);
+        self.define_variable(&decl.name)?;
+
+        // the superclass lives in a scope around the methods, which
+        // capture it as `super`
+        if let Some(superclass) = &decl.superclass {
+            self.current().scope_depth += 1;
+            let var = self.resolve(superclass.name(), superclass.token())?;
+            self.get_var(var, superclass.token());
+            self.emit(OpCode::Inherit, superclass.token());
+            self.add_local("super".into(), superclass.token())?;
+            self.mark_initialized();
+        }
+
+        for method in &decl.methods {
+            let kind = match method.name.name().as_str() {
+                "init" => FunctionKind::Initializer,
+                _ => FunctionKind::Method,
+            };
+            self.function(method, kind)?;
+        }
+
+        let methods = u8::try_from(decl.methods.len())
+            .map_err(|_| CompileError::new(name, "too many methods in one class"))?;
+        self.emit_constant(
+            OpCode::Class,
+            Constant::Name(decl.name.name().as_str().into()),
+            name,
+        )?;
+        self.emit_byte(methods, name);
+        self.emit_byte(decl.superclass.is_some().into(), name);
+
+        let var = self.resolve(decl.name.name(), name)?;
+        self.set_var(var, name);
+        self.emit(OpCode::Pop, name);
+
+        if decl.superclass.is_some() {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    /// compiles the function's body into its own chunk and emits the
+    /// code creating a closure of it
+    fn function(&mut self, decl: &ast::Function, kind: FunctionKind) -> Result<()> {
+        let name = decl.name.token();
+        let mut state = FunctionState::new(decl.name.name().clone(), kind);
+        state.function.arity = decl.params.len();
+        state.returns_decimal = decl.returns_decimal();
+        self.functions.push(state);
+
+        if let Some(param) = decl.params.get(u8::MAX as usize) {
+            return Err(CompileError::new(
+                param.token(),
+                "can't have more than 255 parameters",
+            ));
+        }
+        self.current().scope_depth += 1;
+        for param in &decl.params {
+            self.declare_local(param)?;
+            self.mark_initialized();
+            if param.is_decimal() {
+                let slot = Var::Local(self.current().locals.len() as u8 - 1);
+                self.get_var(slot, param.token());
+                self.emit(OpCode::Promote, param.token());
+                self.set_var(slot, param.token());
+                self.emit(OpCode::Pop, param.token());
+            }
+        }
+        for stmt in &decl.body.stmts {
+            self.statement(stmt)?;
+        }
+        self.emit_return(name);
+
+        let state = self.functions.pop().expect("pushed above");
+        let function = Rc::new(state.function);
+        self.emit_constant(OpCode::Closure, Constant::Function(function), name)?;
+        for upvalue in state.upvalues {
+            self.emit_byte(upvalue.is_local.into(), name);
+            self.emit_byte(upvalue.index, name);
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<()> {
+        stack::ensure(|| {
+            match expr {
+                Expr::Unary(tok, operand) => {
+                    self.expr(operand)?;
+                    match tok.token_type {
+                        TokenType::Bang => self.emit(OpCode::Not, operand.first_token()),
+                        TokenType::Minus => self.emit(OpCode::Negate, tok),
+                        _ => return Err(CompileError::new(tok, "unsupported unary operator")),
+                    }
+                }
+                Expr::Binary(l, tok, r) => match tok.token_type {
+                    TokenType::Or => {
+                        self.expr(l)?;
+                        let rhs = self.emit_jump(OpCode::JumpIfFalse, l.first_token());
+                        self.emit(OpCode::True, tok);
+                        let end = self.emit_jump(OpCode::Jump, tok);
+                        self.patch_jump(rhs, tok)?;
+                        self.boolean(r)?;
+                        self.patch_jump(end, tok)?;
+                    }
+                    TokenType::And => {
+                        self.expr(l)?;
+                        let short_circuit = self.emit_jump(OpCode::JumpIfFalse, l.first_token());
+                        self.boolean(r)?;
+                        let end = self.emit_jump(OpCode::Jump, tok);
+                        self.patch_jump(short_circuit, tok)?;
+                        self.emit(OpCode::False, tok);
+                        self.patch_jump(end, tok)?;
+                    }
+                    _ => {
+                        self.expr(l)?;
+                        self.expr(r)?;
+                        let op = match tok.token_type {
+                            TokenType::Plus => OpCode::Add,
+                            TokenType::Minus => OpCode::Subtract,
+                            TokenType::Star => OpCode::Multiply,
+                            TokenType::Slash => OpCode::Divide,
+                            TokenType::Percent => OpCode::Modulo,
+                            TokenType::EqualEqual | TokenType::BangEqual => OpCode::Equal,
+                            TokenType::Greater => OpCode::Greater,
+                            TokenType::GreaterEqual => OpCode::GreaterEqual,
+                            TokenType::Less => OpCode::Less,
+                            TokenType::LessEqual => OpCode::LessEqual,
+                            _ => return Err(CompileError::new(tok, "unsupported binary operator")),
+                        };
+                        self.emit(op, tok);
+                        if tok.token_type == TokenType::BangEqual {
+                            self.emit(OpCode::Not, tok);
+                        }
+                    }
+                },
+                Expr::Grouping(expr) => self.expr(expr)?,
+                Expr::Literal(tok) => match &tok.token_type {
+                    TokenType::True => self.emit(OpCode::True, tok),
+                    TokenType::False => self.emit(OpCode::False, tok),
+                    TokenType::Nil => self.emit(OpCode::Nil, tok),
+                    TokenType::String(s) => {
+                        let val = Constant::Value(RValue::String(s.clone()));
+                        self.emit_constant(OpCode::Constant, val, tok)?;
+                    }
+                    TokenType::Integer(i) => {
+                        let val = Constant::Value(RValue::Int(*i));
+                        self.emit_constant(OpCode::Constant, val, tok)?;
+                    }
+                    TokenType::Decimal(d) => {
+                        let val = Constant::Value(RValue::Decimal(*d));
+                        self.emit_constant(OpCode::Constant, val, tok)?;
+                    }
+                    _ => unreachable!("parser only builds literals from literal tokens: {tok:?}"),
+                },
+                Expr::Interpolation(parts, end) => {
+                    for (piece, expr) in parts {
+                        let val = Constant::Value(RValue::String(
+                            piece.token_type.as_string_piece().into(),
+                        ));
+                        self.emit_constant(OpCode::Constant, val, piece)?;
+                        self.expr(expr)?;
+                    }
+                    let val =
+                        Constant::Value(RValue::String(end.token_type.as_string_piece().into()));
+                    self.emit_constant(OpCode::Constant, val, end)?;
+                    let count = u16::try_from(2 * parts.len() + 1).map_err(|_| {
+                        CompileError::new(end, "too many interpolations in one string")
+                    })?;
+                    self.emit(OpCode::Interpolate, end);
+                    self.emit_u16(count, end);
+                }
+                Expr::Variable(ident) | Expr::This(ident) => {
+                    let var = self.resolve(ident.name(), ident.token())?;
+                    self.get_var(var, ident.token());
+                }
+                Expr::Assignment(ident, _, val) => {
+                    self.expr(val)?;
+                    if ident.is_decimal() {
+                        self.emit(OpCode::Promote, ident.token());
+                    }
+                    let var = self.resolve(ident.name(), ident.token())?;
+                    self.set_var(var, ident.token());
+                }
+                Expr::Call(callee, paren, args) => {
+                    self.expr(callee)?;
+                    for arg in args {
+                        self.expr(arg)?;
+                    }
+                    let count = u8::try_from(args.len()).map_err(|_| {
+                        CompileError::new(paren, "can't have more than 255 arguments")
+                    })?;
+                    self.emit(OpCode::Call, paren);
+                    self.emit_byte(count, paren);
+                }
+                Expr::Get(object, name) => {
+                    self.expr(object)?;
+                    let constant = Constant::Name(name.name().as_str().into());
+                    self.emit_constant(OpCode::GetProperty, constant, name.token())?;
+                }
+                Expr::Set(object, name, val) => {
+                    self.expr(object)?;
+                    self.expr(val)?;
+                    let constant = Constant::Name(name.name().as_str().into());
+                    self.emit_constant(OpCode::SetProperty, constant, name.token())?;
+                }
+                Expr::List(bracket, elements) => {
+                    for element in elements {
+                        self.expr(element)?;
+                    }
+                    let count = u16::try_from(elements.len()).map_err(|_| {
+                        CompileError::new(bracket, "too many elements in one list literal")
+                    })?;
+                    self.emit(OpCode::List, bracket);
+                    self.emit_u16(count, bracket);
+                }
+                Expr::Map(brace, entries) => {
+                    for (key, val) in entries {
+                        self.expr(key)?;
+                        self.expr(val)?;
+                    }
+                    let count = u16::try_from(entries.len()).map_err(|_| {
+                        CompileError::new(brace, "too many entries in one map literal")
+                    })?;
+                    self.emit(OpCode::Map, brace);
+                    self.emit_u16(count, brace);
+                }
+                Expr::Index(object, bracket, index) => {
+                    self.expr(object)?;
+                    self.expr(index)?;
+                    self.emit(OpCode::GetIndex, bracket);
+                }
+                Expr::SetIndex(object, bracket, index, val) => {
+                    self.expr(object)?;
+                    self.expr(index)?;
+                    self.expr(val)?;
+                    self.emit(OpCode::SetIndex, bracket);
+                }
+                Expr::Super(keyword, method) => {
+                    let keyword = keyword.token();
+                    let this = self.resolve("this", keyword)?;
+                    self.get_var(this, keyword);
+                    let superclass = self.resolve("super", keyword)?;
+                    self.get_var(superclass, keyword);
+                    let constant = Constant::Name(method.name().as_str().into());
+                    self.emit_constant(OpCode::GetSuper, constant, method.token())?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// compiles `expr` and turns its value into a boolean, failing the
+    /// same way a condition does
+    fn boolean(&mut self, expr: &Expr) -> Result<()> {
+        self.expr(expr)?;
+        let tok = expr.first_token();
+        let falsy = self.emit_jump(OpCode::JumpIfFalse, tok);
+        self.emit(OpCode::True, tok);
+        let end = self.emit_jump(OpCode::Jump, tok);
+        self.patch_jump(falsy, tok)?;
+        self.emit(OpCode::False, tok);
+        self.patch_jump(end, tok)
+    }
+}
+
+/// variables
+impl Compiler {
+    /// the resolver already rejected redeclarations, so this only has
+    /// to keep track of the slot
+    fn declare_local(&mut self, ident: &Ident) -> Result<()> {
+        self.add_local(ident.name().clone(), ident.token())
+    }
+
+    fn add_local(&mut self, name: String, tok: &Token) -> Result<()> {
+        let state = self.current();
+        if state.locals.len() > u8::MAX as usize {
+            return Err(CompileError::new(
+                tok,
+                "too many local variables in one function",
+            ));
+        }
+        state.locals.push(Local {
+            name,
+            depth: None,
+            is_captured: false,
+        });
+        Ok(())
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.current();
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    /// makes the value on top of the stack the variable's value
+    fn define_variable(&mut self, ident: &Ident) -> Result<()> {
+        if self.current().scope_depth > 0 {
+            // the value already sits in the local's slot
+            self.mark_initialized();
+            return Ok(());
+        }
+        let name = Constant::Name(ident.name().as_str().into());
+        self.emit_constant(OpCode::DefineGlobal, name, ident.token())
+    }
+
+    /// pops the locals of the innermost scope
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self
+            .current()
+            .locals
+            .pop_if(|local| local.depth.is_some_and(|d| d > depth))
+        {
+            let op = match local.is_captured {
+                true => OpCode::CloseUpvalue,
+                false => OpCode::Pop,
+            };
+            self.chunk().push(op as u8);
+        }
+    }
+
+    fn resolve(&mut self, name: &str, tok: &Token) -> Result<Var> {
+        let innermost = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(innermost, name) {
+            return Ok(Var::Local(slot));
+        }
+        if let Some(index) = self.resolve_upvalue(innermost, name, tok)? {
+            return Ok(Var::Upvalue(index));
+        }
+        let constant = self.make_constant(Constant::Name(name.into()), tok)?;
+        Ok(Var::Global(constant))
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u8> {
+        let locals = &self.functions[function].locals;
+        let slot = locals.iter().rposition(|local| local.name == name)?;
+        Some(slot as u8)
+    }
+
+    /// finds `name` in the functions enclosing `function`, threading it
+    /// through the upvalues of every function in between
+    fn resolve_upvalue(&mut self, function: usize, name: &str, tok: &Token) -> Result<Option<u8>> {
+        if function == 0 {
+            return Ok(None);
+        }
+        let enclosing = function - 1;
+        if let Some(slot) = self.resolve_local(enclosing, name) {
+            self.functions[enclosing].locals[slot as usize].is_captured = true;
+            return self
+                .add_upvalue(
+                    function,
+                    UpvalueRef {
+                        is_local: true,
+                        index: slot,
+                    },
+                    tok,
+                )
+                .map(Some);
+        }
+        match self.resolve_upvalue(enclosing, name, tok)? {
+            Some(index) => self
+                .add_upvalue(
+                    function,
+                    UpvalueRef {
+                        is_local: false,
+                        index,
+                    },
+                    tok,
+                )
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&mut self, function: usize, upvalue: UpvalueRef, tok: &Token) -> Result<u8> {
+        let state = &mut self.functions[function];
+        if let Some(index) = state.upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(index as u8);
+        }
+        if state.upvalues.len() > u8::MAX as usize {
+            return Err(CompileError::new(
+                tok,
+                "too many captured variables in one function",
+            ));
+        }
+        state.upvalues.push(upvalue);
+        state.function.upvalue_count = state.upvalues.len();
+        Ok((state.upvalues.len() - 1) as u8)
+    }
+
+    fn get_var(&mut self, var: Var, tok: &Token) {
+        match var {
+            Var::Local(slot) => {
+                self.emit(OpCode::GetLocal, tok);
+                self.emit_byte(slot, tok);
+            }
+            Var::Upvalue(index) => {
+                self.emit(OpCode::GetUpvalue, tok);
+                self.emit_byte(index, tok);
+            }
+            Var::Global(name) => {
+                self.emit(OpCode::GetGlobal, tok);
+                self.emit_u16(name, tok);
+            }
+        }
+    }
+
+    /// assigns the value on top of the stack, leaving it there
+    fn set_var(&mut self, var: Var, tok: &Token) {
+        match var {
+            Var::Local(slot) => {
+                self.emit(OpCode::SetLocal, tok);
+                self.emit_byte(slot, tok);
+            }
+            Var::Upvalue(index) => {
+                self.emit(OpCode::SetUpvalue, tok);
+                self.emit_byte(index, tok);
+            }
+            Var::Global(name) => {
+                self.emit(OpCode::SetGlobal, tok);
+                self.emit_u16(name, tok);
+            }
+        }
+    }
+}
+
+/// bytecode emission
+impl Compiler {
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("always compiling a function")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+
+    fn emit(&mut self, op: OpCode, tok: &Token) {
+        self.chunk().write(op as u8, tok);
+    }
+
+    fn emit_byte(&mut self, byte: u8, tok: &Token) {
+        self.chunk().write(byte, tok);
+    }
+
+    fn emit_u16(&mut self, val: u16, tok: &Token) {
+        for byte in val.to_be_bytes() {
+            self.chunk().write(byte, tok);
+        }
+    }
+
+    fn make_constant(&mut self, constant: Constant, tok: &Token) -> Result<u16> {
+        let index = self.chunk().add_constant(constant);
+        u16::try_from(index)
+            .map_err(|_| CompileError::new(tok, "too many constants in one function"))
+    }
+
+    /// emits an instruction taking a constant as its operand
+    fn emit_constant(&mut self, op: OpCode, constant: Constant, tok: &Token) -> Result<()> {
+        let index = self.make_constant(constant, tok)?;
+        self.emit(op, tok);
+        self.emit_u16(index, tok);
+        Ok(())
+    }
+
+    fn emit_return(&mut self, tok: &Token) {
+        match self.current().kind {
+            FunctionKind::Initializer => {
+                self.emit(OpCode::GetLocal, tok);
+                self.emit_byte(0, tok);
+            }
+            _ => self.emit(OpCode::Nil, tok),
+        }
+        self.emit(OpCode::Return, tok);
+    }
+
+    /// emits a jump with a placeholder offset. returns the position of
+    /// the offset for [`Compiler::patch_jump`]
+    fn emit_jump(&mut self, op: OpCode, tok: &Token) -> usize {
+        self.emit(op, tok);
+        self.emit_u16(u16::MAX, tok);
+        self.chunk().code().len() - 2
+    }
+
+    /// makes the jump at `offset` land on the next instruction
+    fn patch_jump(&mut self, offset: usize, tok: &Token) -> Result<()> {
+        let distance = self.chunk().code().len() - offset - 2;
+        let distance = u16::try_from(distance)
+            .map_err(|_| CompileError::new(tok, "too much code to jump over"))?;
+        self.chunk().patch(offset, distance);
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize, tok: &Token) -> Result<()> {
+        self.emit(OpCode::Loop, tok);
+        let distance = self.chunk().code().len() - start + 2;
+        let distance = u16::try_from(distance)
+            .map_err(|_| CompileError::new(tok, "loop body is too large"))?;
+        self.emit_u16(distance, tok);
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use crate::{diagnostic::Diagnostic, scanner::Token};
+
+/// a script that parsed but can't be turned into bytecode
+#[derive(Debug)]
+pub struct CompileError {
+    token: Box<Token>,
+    message: String,
+    help: Option<String>,
+}
+
+impl CompileError {
+    pub fn new(token: &Token, message: impl Into<String>) -> Self {
+        Self {
+            token: Box::new(token.clone()),
+            message: message.into(),
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::at(&self.token, self.message.clone());
+        match &self.help {
+            Some(help) => diagnostic.with_help(help.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "compile error at {} near '{}': {}",
+            self.token.location_start, self.token.lexeme, self.message
+        )
+    }
+}
+
+impl std::error::Error for CompileError {}
@@ -0,0 +1,582 @@
+mod chunk;
+mod compiler;
+mod error;
+mod value;
+
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use chunk::{Constant, OpCode};
+pub use compiler::compile;
+pub use error::CompileError;
+use value::Upvalue;
+pub use value::{BoundMethod, Closure, Function};
+
+use crate::{
+    interpreter::RuntimeError,
+    stack, stdlib,
+    value::{Class, Instance, Key, Native, RValue},
+};
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    /// offset of the next byte to execute
+    ip: usize,
+    /// stack slot of the callee, its arguments and locals follow it
+    base: usize,
+}
+
+/// executes compiled scripts on a value stack
+pub struct Vm {
+    stack: Vec<RValue>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, RValue>,
+    /// upvalues still pointing into the stack
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    /// where `print` writes to
+    out: stdlib::Output,
+    /// where `input` reads from
+    input: stdlib::Input,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            out: stdlib::stdout(),
+            input: stdlib::stdin(),
+        }
+    }
+}
+
+impl Vm {
+    /// a vm with the standard library defined
+    pub fn new() -> Self {
+        let mut vm = Self::default();
+        for native in stdlib::natives(&vm.out, &vm.input) {
+            vm.define_native(native);
+        }
+        vm
+    }
+
+    /// makes a rust function callable from scripts as a global
+    pub fn define_native(&mut self, native: Native) {
+        let name = native.name().into();
+        self.globals.insert(name, RValue::Native(Rc::new(native)));
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &RValue)> {
+        self.globals.iter().map(|(name, val)| (&**name, val))
+    }
+
+    pub fn global(&self, name: &str) -> Option<RValue> {
+        self.globals.get(name).cloned()
+    }
+
+    /// defines a global, replacing any previous one of the same name
+    pub fn define_global(&mut self, name: &str, val: RValue) {
+        self.globals.insert(name.into(), val);
+    }
+
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        *self.out.borrow_mut() = out;
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        *self.input.borrow_mut() = Some(input);
+    }
+
+    /// runs a compiled script. on error the stack is unwound so the vm
+    /// can keep being used with the globals defined so far
+    pub fn execute(&mut self, script: Rc<Function>) -> Result<()> {
+        let closure = Rc::new(Closure {
+            function: script,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(RValue::Closure(closure.clone()));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+
+        let result = self.run();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+}
+
+impl Vm {
+    fn run(&mut self) -> Result<()> {
+        loop {
+            let op = OpCode::from_byte(self.read_byte()).expect("compiler only emits opcodes");
+            match op {
+                OpCode::Constant => match self.read_constant() {
+                    Constant::Value(val) => self.stack.push(val),
+                    constant => unreachable!("not a value constant: {constant:?}"),
+                },
+                OpCode::Nil => self.stack.push(RValue::Null),
+                OpCode::True => self.stack.push(RValue::Boolean(true)),
+                OpCode::False => self.stack.push(RValue::Boolean(false)),
+                OpCode::Pop => drop(self.pop()),
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    match self.globals.get(&name) {
+                        Some(val) => self.stack.push(val.clone()),
+                        None => {
+                            return Err(self
+                                .error(format!("variable '{name}' does not exist"))
+                                .with_help(format!("declare it with 'var {name}' first")))
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    if self.globals.contains_key(&name) {
+                        return Err(self.error(format!(
+                            "variable '{name}' already exists in this scope. you cannot assign values with var"
+                        )));
+                    }
+                    let val = self.pop();
+                    self.globals.insert(name, val);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    let val = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(var) => *var = val,
+                        None => {
+                            return Err(self
+                                .error(format!(
+                                    "unable to assign '{name}'. variable does not exist"
+                                ))
+                                .with_help(format!("declare it with 'var {name}' first")))
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let val = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(val) => val.clone(),
+                    };
+                    self.stack.push(val);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let val = self.peek(0).clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = val,
+                        Upvalue::Closed(closed) => *closed = val,
+                    };
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let instance = match self.pop() {
+                        RValue::Instance(instance) => instance,
+                        val => {
+                            return Err(self.error(format!(
+                                "only instances have properties, not {}",
+                                val.type_name()
+                            )))
+                        }
+                    };
+                    let field = instance.borrow().field(&name).cloned();
+                    let val = match field {
+                        Some(val) => val,
+                        None => {
+                            let class = instance.borrow().class().clone();
+                            self.bind_method(&class, RValue::Instance(instance), &name)?
+                        }
+                    };
+                    self.stack.push(val);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let val = self.pop();
+                    let RValue::Instance(instance) = self.pop() else {
+                        return Err(self.error("only instances have fields"));
+                    };
+                    instance
+                        .borrow_mut()
+                        .set_field(name.to_string(), val.clone());
+                    self.stack.push(val);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    let RValue::Class(superclass) = self.pop() else {
+                        unreachable!("`super` is checked by OpCode::Inherit")
+                    };
+                    let receiver = self.pop();
+                    let method = self.bind_method(&superclass, receiver, &name)?;
+                    self.stack.push(method);
+                }
+                OpCode::Equal => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(RValue::Boolean(lhs == rhs));
+                }
+                OpCode::Greater => self.comparison(Ordering::is_gt)?,
+                OpCode::GreaterEqual => self.comparison(Ordering::is_ge)?,
+                OpCode::Less => self.comparison(Ordering::is_lt)?,
+                OpCode::LessEqual => self.comparison(Ordering::is_le)?,
+                OpCode::Add => self.arithmetic(|lhs, rhs| lhs + rhs)?,
+                OpCode::Subtract => self.arithmetic(|lhs, rhs| lhs - rhs)?,
+                OpCode::Multiply => self.arithmetic(|lhs, rhs| lhs * rhs)?,
+                OpCode::Divide => self.arithmetic(|lhs, rhs| lhs / rhs)?,
+                OpCode::Modulo => self.arithmetic(|lhs, rhs| lhs % rhs)?,
+                OpCode::Not => {
+                    let truthy = self.truthy()?;
+                    self.stack.push(RValue::Boolean(!truthy));
+                }
+                OpCode::Negate => {
+                    let val = -&self.pop();
+                    let val = val.map_err(|msg| self.error(msg))?;
+                    self.stack.push(val);
+                }
+                OpCode::Promote => {
+                    let val = self.pop().promote();
+                    self.stack.push(val);
+                }
+                OpCode::Print => {
+                    let val = self.pop();
+                    writeln!(self.out.borrow_mut(), "{val}")
+                        .map_err(|e| self.error(format!("unable to print: {e}")))?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.truthy()? {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let args = self.read_byte() as usize;
+                    self.call_value(args)?;
+                }
+                OpCode::Closure => {
+                    let Constant::Function(function) = self.read_constant() else {
+                        unreachable!("closures are made from function constants")
+                    };
+                    let upvalues = (0..function.upvalue_count)
+                        .map(|_| {
+                            let is_local = self.read_byte() == 1;
+                            let index = self.read_byte() as usize;
+                            if is_local {
+                                self.capture_upvalue(self.frame().base + index)
+                            } else {
+                                self.frame().closure.upvalues[index].clone()
+                            }
+                        })
+                        .collect();
+                    let closure = Closure { function, upvalues };
+                    self.stack.push(RValue::Closure(Rc::new(closure)));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("returning from a frame");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Inherit => {
+                    if !matches!(self.peek(0), RValue::Class(_)) {
+                        return Err(self.error(format!(
+                            "superclass must be a class, not {}",
+                            self.peek(0).type_name()
+                        )));
+                    }
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let count = self.read_byte() as usize;
+                    let has_superclass = self.read_byte() == 1;
+                    let methods = self
+                        .stack
+                        .split_off(self.stack.len() - count)
+                        .into_iter()
+                        .map(|method| match &method {
+                            RValue::Closure(closure) => (closure.function.name.clone(), method),
+                            _ => unreachable!("methods are compiled to closures"),
+                        })
+                        .collect();
+                    let superclass = match (has_superclass, self.peek(0)) {
+                        (true, RValue::Class(superclass)) => Some(superclass.clone()),
+                        _ => None,
+                    };
+                    let class = Class::new(name.to_string(), superclass, methods);
+                    self.stack.push(RValue::Class(Rc::new(class)));
+                }
+                OpCode::List => {
+                    let count = self.read_u16() as usize;
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.stack
+                        .push(RValue::List(Rc::new(RefCell::new(elements.into()))));
+                }
+                OpCode::Interpolate => {
+                    let count = self.read_u16() as usize;
+                    let text = self
+                        .stack
+                        .split_off(self.stack.len() - count)
+                        .iter()
+                        .map(RValue::to_string)
+                        .collect();
+                    self.stack.push(RValue::String(text));
+                }
+                OpCode::Map => {
+                    let count = self.read_u16() as usize;
+                    let entries = self.stack.split_off(self.stack.len() - 2 * count);
+                    let mut map = BTreeMap::new();
+                    for entry in entries.chunks_exact(2) {
+                        let key = Key::try_from(&entry[0]).map_err(|msg| self.error(msg))?;
+                        map.insert(key, entry[1].clone());
+                    }
+                    self.stack
+                        .push(RValue::Map(Rc::new(RefCell::new(map.into()))));
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let val = object.index(&index).map_err(|msg| self.error(msg))?;
+                    self.stack.push(val);
+                }
+                OpCode::SetIndex => {
+                    let val = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    object
+                        .set_index(&index, val.clone())
+                        .map_err(|msg| self.error(msg))?;
+                    self.stack.push(val);
+                }
+                OpCode::Iter => {
+                    let list = self.pop().iterable().map_err(|msg| self.error(msg))?;
+                    self.stack.push(RValue::List(list));
+                }
+                OpCode::ForIter => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    let offset = self.read_u16() as usize;
+                    let (RValue::List(list), RValue::Int(i)) =
+                        (&self.stack[slot], &self.stack[slot + 1])
+                    else {
+                        unreachable!("the compiler sets up the loop's hidden locals")
+                    };
+                    let next = list.borrow().get(*i as usize).cloned();
+                    match next {
+                        Some(item) => {
+                            self.stack[slot + 1] = RValue::Int(i + 1);
+                            self.stack.push(item);
+                        }
+                        None => self.frame_mut().ip += offset,
+                    }
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, args: usize) -> Result<()> {
+        let callee_slot = self.stack.len() - args - 1;
+        match self.stack[callee_slot].clone() {
+            RValue::Closure(closure) => self.call(closure, args),
+            RValue::BoundMethod(bound) => {
+                self.stack[callee_slot] = bound.receiver.clone();
+                self.call(bound.method.clone(), args)
+            }
+            RValue::Native(native) => {
+                let result = native
+                    .call(&self.stack[callee_slot + 1..])
+                    .map_err(|msg| self.error(msg))?;
+                self.stack.truncate(callee_slot);
+                self.stack.push(result);
+                Ok(())
+            }
+            RValue::Class(class) => {
+                let instance = Instance::new(class.clone());
+                self.stack[callee_slot] = RValue::Instance(Rc::new(RefCell::new(instance)));
+                match class.find_method("init") {
+                    Some(RValue::Closure(init)) => self.call(init.clone(), args),
+                    _ if args != 0 => {
+                        Err(self.error(format!("expected 0 arguments but got {args}")))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            callee => Err(self.error(format!(
+                "can only call functions and classes, not {}",
+                callee.type_name()
+            ))),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, args: usize) -> Result<()> {
+        if args != closure.function.arity {
+            return Err(self.error(format!(
+                "expected {} arguments but got {args}",
+                closure.function.arity
+            )));
+        }
+        if self.frames.len() == stack::MAX_CALL_DEPTH {
+            return Err(self.error("stack overflow"));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - args - 1,
+        });
+        Ok(())
+    }
+
+    fn bind_method(&self, class: &Class, receiver: RValue, name: &str) -> Result<RValue> {
+        match class.find_method(name) {
+            Some(RValue::Closure(method)) => Ok(RValue::BoundMethod(Rc::new(BoundMethod {
+                receiver,
+                method: method.clone(),
+            }))),
+            _ => Err(self.error(format!("undefined property '{name}'"))),
+        }
+    }
+
+    /// the open upvalue for the stack slot, shared by every closure
+    /// capturing it
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    /// moves the variables from `from` upwards off the stack into the
+    /// upvalues capturing them
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn comparison(&mut self, test: fn(Ordering) -> bool) -> Result<()> {
+        let rhs = self.pop();
+        let lhs = self.pop();
+        let ord = lhs.compare(&rhs).map_err(|msg| self.error(msg))?;
+        self.stack.push(RValue::Boolean(ord.is_some_and(test)));
+        Ok(())
+    }
+
+    fn arithmetic(
+        &mut self,
+        op: fn(&RValue, &RValue) -> std::result::Result<RValue, String>,
+    ) -> Result<()> {
+        let rhs = self.pop();
+        let lhs = self.pop();
+        let val = op(&lhs, &rhs).map_err(|msg| self.error(msg))?;
+        self.stack.push(val);
+        Ok(())
+    }
+
+    /// pops a value and checks whether it counts as true
+    fn truthy(&mut self) -> Result<bool> {
+        let val = self.pop();
+        val.is_truthy().map_err(|msg| self.error(msg))
+    }
+
+    /// an error pointing at the token the current instruction was
+    /// compiled from
+    fn error(&self, message: impl Into<String>) -> RuntimeError {
+        let frame = self.frame();
+        let token = frame.closure.function.chunk.token(frame.ip - 1);
+        RuntimeError::new(token, message)
+    }
+}
+
+/// reading the bytecode of the current frame
+impl Vm {
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("vm runs inside a frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("vm runs inside a frame")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.function.chunk.code()[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.read_byte(), self.read_byte()])
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().closure.function.chunk.constant(index).clone()
+    }
+
+    fn read_name(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::Name(name) => name,
+            constant => unreachable!("not a name constant: {constant:?}"),
+        }
+    }
+
+    fn pop(&mut self) -> RValue {
+        self.stack.pop().expect("compiler keeps the stack balanced")
+    }
+
+    fn peek(&self, distance: usize) -> &RValue {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+}
@@ -0,0 +1,78 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::value::{self, RValue};
+
+use super::chunk::Chunk;
+
+/// a function compiled to bytecode. shared by every closure created
+/// from its declaration
+#[derive(Default)]
+pub struct Function {
+    /// empty for the top level script
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name.as_str() {
+            "" => write!(f, "<script>"),
+            name => write!(f, "<fn {name}>"),
+        }
+    }
+}
+
+/// a variable captured by a closure. stays on the vm stack while the
+/// variable's scope is alive and moves into the upvalue when it ends
+#[derive(Debug)]
+pub enum Upvalue {
+    /// absolute stack slot of the variable
+    Open(usize),
+    Closed(RValue),
+}
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Closure {
+    /// takes the values of the closed upvalues no other closure shares
+    /// out of the closure
+    pub(crate) fn take_captured(&mut self) -> Vec<RValue> {
+        std::mem::take(&mut self.upvalues)
+            .into_iter()
+            .filter_map(|upvalue| Rc::try_unwrap(upvalue).ok())
+            .filter_map(|upvalue| match upvalue.into_inner() {
+                Upvalue::Closed(val) => Some(val),
+                Upvalue::Open(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl Drop for Closure {
+    fn drop(&mut self) {
+        value::drop_all(self.take_captured());
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.function.fmt(f)
+    }
+}
+
+pub struct BoundMethod {
+    /// the instance `this` refers to
+    pub receiver: RValue,
+    pub method: Rc<Closure>,
+}
+
+impl fmt::Debug for BoundMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.method.fmt(f)
+    }
+}
@@ -0,0 +1,197 @@
+//! drives the debugger with typed commands and checks where the script
+//! pauses and what the commands print
+
+mod common;
+
+use std::{cell::Cell, io, rc::Rc};
+
+use common::Capture;
+use compiler::{checker, debugger::Debugger, interpreter::Interpreter, parse, resolver, RunError};
+
+const SCRIPT: &str = "\
+fun add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+var x = add(1, 2);
+print x;
+print add(x, 4);
+";
+
+/// runs [`SCRIPT`] in the debugger with `commands` typed into it,
+/// returning what it printed, the errors it reported and whether the
+/// script was stopped
+fn debug(commands: &str) -> (String, String, bool) {
+    let (out, err) = (Capture::default(), Capture::default());
+    let quit = Rc::new(Cell::new(false));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(out.clone()));
+    interpreter.set_hook(Box::new(Debugger::with_io(
+        "test.lox",
+        SCRIPT,
+        quit.clone(),
+        io::Cursor::new(commands.to_string()),
+        out.clone(),
+        err.clone(),
+    )));
+    let result = parse(SCRIPT).and_then(|stmts| {
+        resolver::resolve(&stmts).map_err(RunError::Resolve)?;
+        checker::check(&stmts).map_err(RunError::Check)?;
+        for stmt in stmts {
+            interpreter.evaluate(stmt).map_err(RunError::Runtime)?;
+        }
+        Ok(())
+    });
+    assert_eq!(
+        result.is_err(),
+        quit.get(),
+        "only quitting stops the script"
+    );
+    (out.text(), err.text(), quit.get())
+}
+
+/// the lines the debugger paused on, in order
+fn pauses(out: &str) -> Vec<&str> {
+    out.lines()
+        .filter_map(|line| line.strip_prefix("test.lox:"))
+        .collect()
+}
+
+#[test]
+fn starts_paused_before_the_first_statement() {
+    let (out, _, quit) = debug("quit\n");
+    assert_eq!(out, "test.lox:1\n    1 | fun add(a, b) {\n");
+    assert!(quit);
+}
+
+#[test]
+fn the_end_of_the_commands_quits() {
+    let (out, _, quit) = debug("");
+    assert_eq!(pauses(&out), ["1"]);
+    assert!(quit);
+}
+
+#[test]
+fn step_goes_into_functions() {
+    let (out, _, _) = debug("step\ns\ns\ns\n");
+    assert_eq!(pauses(&out), ["1", "5", "2", "3", "6"]);
+}
+
+#[test]
+fn an_empty_line_repeats_the_command() {
+    let (out, _, _) = debug("s\n\n\n\n");
+    assert_eq!(pauses(&out), ["1", "5", "2", "3", "6"]);
+}
+
+#[test]
+fn next_steps_over_calls() {
+    let (out, _, quit) = debug("next\nn\nn\nn\n");
+    assert_eq!(pauses(&out), ["1", "5", "6", "7"]);
+    assert!(out.contains("6 | print x;\n3\n"));
+    assert!(out.ends_with("7\n"));
+    assert!(!quit);
+}
+
+#[test]
+fn finish_runs_until_the_function_returns() {
+    let (out, _, _) = debug("s\ns\nfinish\nc\n");
+    assert_eq!(pauses(&out), ["1", "5", "2", "6"]);
+}
+
+#[test]
+fn finish_outside_of_a_function_is_an_error() {
+    let (out, err, _) = debug("f\nq\n");
+    assert_eq!(pauses(&out), ["1"]);
+    assert_eq!(err, "error: not inside of a function\n");
+}
+
+#[test]
+fn continue_stops_at_breakpoints() {
+    let (out, _, quit) = debug("break 7\nb 3\nb\nc\nc\nc\nc\n");
+    assert_eq!(
+        out,
+        "\
+test.lox:1
+    1 | fun add(a, b) {
+test.lox:3
+test.lox:7
+test.lox:3
+*   3 |     return sum;
+3
+test.lox:7
+*   7 | print add(x, 4);
+test.lox:3
+*   3 |     return sum;
+7
+"
+    );
+    assert!(!quit);
+}
+
+#[test]
+fn deleted_breakpoints_dont_stop() {
+    let (out, err, _) = debug("b 3\nb 6\nd 3\nd 4\nc\nc\n");
+    assert_eq!(pauses(&out), ["1", "6"]);
+    assert_eq!(err, "error: there is no breakpoint on line '4'\n");
+}
+
+#[test]
+fn breakpoints_need_a_line_of_the_script() {
+    let (_, err, _) = debug("b 8\nb x\nq\n");
+    assert_eq!(
+        err,
+        "error: there is no line '8'\nerror: there is no line 'x'\n"
+    );
+}
+
+#[test]
+fn env_lists_the_scopes_and_globals() {
+    let (out, _, _) = debug("s\ns\ns\nenv\nq\n");
+    assert!(out.ends_with(
+        "\
+scope 0:
+  a = 1
+  b = 2
+  sum = 3
+globals:
+  add = <fn add>
+"
+    ));
+}
+
+#[test]
+fn print_evaluates_in_the_paused_scope() {
+    let (out, err, _) = debug("s\ns\np a * 10 + b\nprint add(a, 5)\nq\n");
+    assert!(out.ends_with("2 |     var sum = a + b;\n12\n6\n"));
+    assert_eq!(err, "");
+}
+
+#[test]
+fn print_reports_errors_in_the_expression() {
+    let (_, err, _) = debug("s\ns\np sum\np\nq\n");
+    assert!(err.starts_with("error: variable 'sum' does not exist\n --> <expr>:1:1\n"));
+    assert!(err.ends_with("error: usage is 'print <expr>'\n"));
+}
+
+#[test]
+fn list_shows_the_code_around_the_line() {
+    let (out, _, _) = debug("b 3\nlist\nq\n");
+    let listing: Vec<_> = out.lines().skip(2).collect();
+    assert_eq!(
+        listing,
+        [
+            "    1 | fun add(a, b) {",
+            "    2 |     var sum = a + b;",
+            "*   3 |     return sum;",
+            "    4 | }",
+            "    5 | var x = add(1, 2);",
+            "    6 | print x;",
+        ]
+    );
+}
+
+#[test]
+fn unknown_commands_are_errors() {
+    let (_, err, _) = debug("jump\nq\n");
+    assert_eq!(err, "error: unknown command 'jump'. see 'help'\n");
+}
@@ -0,0 +1,206 @@
+//! runs scripts through [`Engine`] as a host program would: exchanging
+//! values with them, and on threads with the default stack size
+
+mod common;
+
+use std::thread;
+
+use common::Capture;
+use compiler::{parser::MAX_NESTING, BackendKind, Engine, GetError, RunError};
+
+const BACKENDS: [BackendKind; 2] = [BackendKind::Tree, BackendKind::Vm];
+
+/// evaluates `source` on a new thread with each backend. errors come
+/// back as the stage that failed and the message, as they can't leave
+/// the thread
+fn eval_on_thread(source: String) -> Vec<Result<(), (&'static str, String)>> {
+    thread::spawn(move || {
+        BACKENDS
+            .iter()
+            .map(|&kind| {
+                Engine::with_backend(kind).eval(&source).map_err(|e| {
+                    let stage = match e {
+                        RunError::Parse(_) => "parse",
+                        RunError::Runtime(_) => "runtime",
+                        _ => "other",
+                    };
+                    (stage, e.to_string())
+                })
+            })
+            .collect()
+    })
+    .join()
+    .expect("the thread running the script panicked")
+}
+
+#[test]
+fn unbounded_recursion_is_a_runtime_error() {
+    let source = "fun f(n) { return f(n + 1); } f(0);".to_string();
+    for result in eval_on_thread(source) {
+        let (stage, message) = result.unwrap_err();
+        assert_eq!(stage, "runtime");
+        assert!(message.contains("stack overflow"), "{message}");
+    }
+}
+
+#[test]
+fn recursion_stops_at_the_same_depth_on_both_backends() {
+    let depth = "
+        fun depth(n) {
+            if n == 0 {
+                return 0;
+            }
+            return 1 + depth(n - 1);
+        }
+    ";
+    for result in eval_on_thread(format!("{depth} var deepest = depth(1022);")) {
+        result.unwrap();
+    }
+    for result in eval_on_thread(format!("{depth} var deepest = depth(1023);")) {
+        assert_eq!(result.unwrap_err().0, "runtime");
+    }
+}
+
+#[test]
+fn nesting_up_to_the_limit_runs() {
+    let n = MAX_NESTING - 1;
+    let sources = [
+        format!("var x = {}1{};", "(".repeat(n), ")".repeat(n)),
+        format!("var x = {}1;", "-".repeat(n)),
+        format!("{}var x = 1;{}", "if true {".repeat(n), "}".repeat(n)),
+    ];
+    for source in sources {
+        for result in eval_on_thread(source) {
+            result.unwrap();
+        }
+    }
+}
+
+#[test]
+fn nesting_past_the_limit_is_a_parse_error() {
+    let n = 20_000;
+    let sources = [
+        format!("var x = {}1{};", "(".repeat(n), ")".repeat(n)),
+        format!("var x = {}1;", "-".repeat(n)),
+        format!("{}var x = 1;{}", "{".repeat(n), "}".repeat(n)),
+    ];
+    for source in sources {
+        for result in eval_on_thread(source) {
+            let (stage, message) = result.unwrap_err();
+            assert_eq!(stage, "parse");
+            assert!(message.contains("nested too deeply"), "{message}");
+        }
+    }
+}
+
+#[test]
+fn chains_far_past_the_nesting_limit_run() {
+    let n = 5_000;
+    let sources = [
+        format!("var x = 1{};", " + 1".repeat(n)),
+        format!("var x = true{};", " and true".repeat(n)),
+        format!("var x = 1; var y = x{};", " * x".repeat(n)),
+        format!("fun f() {{ return f; }} var x = f{};", "()".repeat(n)),
+        format!("var x = [1]; x[0] = x; var y = x{};", "[0]".repeat(n)),
+        format!(
+            "class C {{ m() {{ return this; }} }} var x = C(){};",
+            ".m()".repeat(n)
+        ),
+    ];
+    for source in sources {
+        for result in eval_on_thread(source) {
+            result.unwrap();
+        }
+    }
+}
+
+#[test]
+fn registered_functions_reject_arguments_of_the_wrong_type() {
+    for kind in BACKENDS {
+        let mut engine = Engine::with_backend(kind);
+        engine.register("double", |x: i64| x * 2);
+        let e = engine.eval("double(\"four\");").unwrap_err();
+        let RunError::Runtime(e) = e else {
+            panic!("{e}");
+        };
+        assert_eq!(
+            e.diagnostic().message(),
+            "argument 1 of double: expected int, not string"
+        );
+    }
+}
+
+#[test]
+fn set_values_read_back_the_same() {
+    for kind in BACKENDS {
+        let mut engine = Engine::with_backend(kind);
+        engine.set("count", 3);
+        engine.set("ratio", 0.5);
+        engine.set("name", "lox");
+        engine.set("flag", true);
+        engine.set("items", vec![1, 2]);
+        engine.set("nothing", None::<i64>);
+        assert_eq!(engine.get::<i64>("count"), Ok(3));
+        assert_eq!(engine.get::<f64>("ratio"), Ok(0.5));
+        assert_eq!(engine.get::<String>("name"), Ok("lox".to_string()));
+        assert_eq!(engine.get::<bool>("flag"), Ok(true));
+        assert_eq!(engine.get::<Vec<i64>>("items"), Ok(vec![1, 2]));
+        assert_eq!(engine.get::<Option<i64>>("nothing"), Ok(None));
+    }
+}
+
+#[test]
+fn get_fails_on_missing_and_wrongly_typed_globals() {
+    for kind in BACKENDS {
+        let mut engine = Engine::with_backend(kind);
+        engine.eval("var name = \"lox\";").unwrap();
+        assert_eq!(
+            engine.get::<i64>("missing"),
+            Err(GetError::Undefined("missing".to_string()))
+        );
+        let e = engine.get::<i64>("name").unwrap_err();
+        assert_eq!(
+            e,
+            GetError::Mismatch("name".to_string(), "expected int, not string".to_string())
+        );
+        assert_eq!(e.to_string(), "variable 'name': expected int, not string");
+    }
+}
+
+#[test]
+fn globals_persist_between_scripts() {
+    for kind in BACKENDS {
+        let mut engine = Engine::with_backend(kind);
+        engine
+            .eval("var total = 1; fun add(n) { total = total + n; }")
+            .unwrap();
+        engine.eval("add(2);").unwrap();
+        engine.eval("add(total);").unwrap();
+        assert_eq!(engine.get::<i64>("total"), Ok(6));
+    }
+}
+
+#[test]
+fn output_goes_where_it_is_set_to() {
+    for kind in BACKENDS {
+        let output = Capture::default();
+        let mut engine = Engine::with_backend(kind);
+        engine.set_output(output.clone());
+        engine.eval("print 1 + 2; print \"done\";").unwrap();
+        assert_eq!(output.text(), "3\ndone\n");
+    }
+}
+
+#[test]
+fn input_reads_lines_from_where_it_is_set_to() {
+    for kind in BACKENDS {
+        let output = Capture::default();
+        let mut engine = Engine::with_backend(kind);
+        engine.set_output(output.clone());
+        engine.set_input(std::io::Cursor::new("ada\r\nbob"));
+        engine
+            .eval("print input(\"> \"); print input(); print input();")
+            .unwrap();
+        assert_eq!(output.text(), "> ada\nbob\nnil\n");
+    }
+}
@@ -0,0 +1,122 @@
+//! formats every script under `tests/fmt` and compares the result with
+//! the `.out` file next to it, then checks `fmt --check` and
+//! `--emit ast-opt` on the command line
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use compiler::formatter;
+
+fn dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fmt")
+}
+
+/// the script `name` formatted, and the layout it should have
+fn layout(name: &str) -> (String, String) {
+    let path = dir().join(name);
+    let source = std::fs::read_to_string(path.with_extension("lox")).unwrap();
+    let expected = std::fs::read_to_string(path.with_extension("out")).unwrap();
+    let formatted = formatter::format(&source).unwrap();
+    (formatted, expected)
+}
+
+#[test]
+fn comments_stay_where_they_were() {
+    let (formatted, expected) = layout("comments");
+    assert_eq!(formatted, expected);
+}
+
+#[test]
+fn single_blank_lines_survive() {
+    let (formatted, expected) = layout("blank_lines");
+    assert_eq!(formatted, expected);
+}
+
+#[test]
+fn parentheses_are_kept_where_precedence_needs_them() {
+    let (formatted, expected) = layout("parentheses");
+    assert_eq!(formatted, expected);
+}
+
+#[test]
+fn comments_inside_expressions_and_headers_are_refused() {
+    let sources = [
+        ("fun f(x /* param */, y) {}", 0),
+        ("var m = {\n    // one\n    \"a\": 1\n};", 1),
+        ("fun f(x, y) {\n    return x // why\n        + y;\n}", 1),
+        ("if true {\n} /* else */ else {\n}", 1),
+        ("for (var i = 0; i < 2; /* step */ i = i + 1) {}", 0),
+    ];
+    for (source, line) in sources {
+        let e = formatter::format(source).unwrap_err();
+        let diagnostics = e.diagnostics();
+        assert_eq!(diagnostics[0].message(), "can't keep this comment in place");
+        assert_eq!(diagnostics[0].start().line(), line, "{source}");
+    }
+}
+
+#[test]
+fn expected_layouts_are_formatted_already() {
+    for name in ["comments", "blank_lines", "parentheses"] {
+        let (_, expected) = layout(name);
+        assert_eq!(formatter::format(&expected).unwrap(), expected, "{name}");
+    }
+}
+
+/// runs `fmt` with `args` on a script written to the file `name`,
+/// returning whether it succeeded and what the file holds afterwards
+fn fmt(name: &str, args: &[&str], source: &str) -> (bool, String) {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, source).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg("fmt")
+        .args(args)
+        .arg(&path)
+        .status()
+        .unwrap();
+    (status.success(), std::fs::read_to_string(&path).unwrap())
+}
+
+#[test]
+fn check_fails_without_writing() {
+    let source = "print (1+2)*3;";
+    assert_eq!(
+        fmt("unformatted.lox", &["--check"], source),
+        (false, source.to_string())
+    );
+}
+
+#[test]
+fn check_passes_formatted_scripts() {
+    let source = "print (1 + 2) * 3;\n";
+    assert_eq!(
+        fmt("formatted.lox", &["--check"], source),
+        (true, source.to_string())
+    );
+}
+
+#[test]
+fn fmt_writes_the_layout() {
+    let (ok, written) = fmt("written.lox", &[], "print (1+2)*3;");
+    assert!(ok);
+    assert_eq!(written, "print (1 + 2) * 3;\n");
+}
+
+#[test]
+fn optimized_scripts_are_emitted_formatted() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("optimized.lox");
+    let source = "fun f() { return 1; print 2; }\nif false { print 3; }\nprint 1 + 2 * 3;";
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .args(["--emit", "ast-opt"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "fun f() {\n    return 1;\n}\nprint 7;\n"
+    );
+}
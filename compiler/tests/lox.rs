@@ -0,0 +1,251 @@
+//! runs every script under `tests/lox` on both backends and compares
+//! what it does with the expectations written in its comments:
+//!
+//! - `// expect: text` is a line the script prints
+//! - `// expect runtime error: message` is the error the script stops
+//!   with, raised on the line of the comment
+//! - `// expect error: message` is an error scanning, parsing,
+//!   resolving or type checking the script finds on the line of the
+//!   comment. there can be several
+//!
+//! on x86-64 linux, scripts under `native` are also compiled to native
+//! executables, which have to do the same, unless they have
+//! `// expect build error: message` comments. those are the errors
+//! compiling them to native code finds, on the line of the comment,
+//! and the interpreters ignore them. every script that parses also has
+//! to be laid out the same when it is formatted a second time
+//!
+//! scripts read no input, and can write files in the directory named by
+//! the global `temp_dir`. `cargo test --test lox -- name` only runs the
+//! scripts whose path contains `name`
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+use common::Capture;
+use compiler::{formatter, native, BackendKind, Engine, RunError};
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// expect error: ";
+const EXPECT_BUILD_ERROR: &str = "// expect build error: ";
+
+fn main() -> ExitCode {
+    let filters: Vec<_> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let mut scripts = vec![];
+    find_scripts(&root, &mut scripts);
+    scripts.sort();
+    let scripts: Vec<_> = scripts
+        .into_iter()
+        .map(|path| {
+            let name = path.strip_prefix(&root).unwrap_or(&path);
+            (name.display().to_string(), path)
+        })
+        .filter(|(name, _)| filters.is_empty() || filters.iter().any(|f| name.contains(f)))
+        .collect();
+
+    println!("\nrunning {} scripts", scripts.len());
+    let mut failures = vec![];
+    for (name, path) in &scripts {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                println!("script {name} ... FAILED");
+                failures.push((name, format!("unable to read the script: {e}")));
+                continue;
+            }
+        };
+        let problems = check(name, &source);
+        if problems.is_empty() {
+            println!("script {name} ... ok");
+        } else {
+            println!("script {name} ... FAILED");
+            failures.push((name, problems.join("\n")));
+        }
+    }
+
+    for (name, problems) in &failures {
+        println!("\n---- {name} ----\n{problems}");
+    }
+    let result = match failures.is_empty() {
+        true => "ok",
+        false => "FAILED",
+    };
+    println!(
+        "\nscript result: {result}. {} passed; {} failed\n",
+        scripts.len() - failures.len(),
+        failures.len()
+    );
+    match failures.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+fn find_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_scripts(&path, scripts);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            scripts.push(path);
+        }
+    }
+}
+
+/// runs a script and describes everything it did differently than
+/// expected
+fn check(name: &str, source: &str) -> Vec<String> {
+    let expected = expectations(source);
+    let mut problems = vec![];
+    for kind in [BackendKind::Tree, BackendKind::Vm] {
+        let actual = run(source, kind);
+        if actual != expected {
+            problems.push(format!("{kind:?} backend:\n{}", diff(&expected, &actual)));
+        }
+    }
+    if cfg!(all(target_arch = "x86_64", target_os = "linux"))
+        && Path::new(name).starts_with("native")
+    {
+        let build_errors = build_errors(source);
+        let expected = match build_errors.is_empty() {
+            true => expected,
+            false => build_errors,
+        };
+        match run_native(name, source) {
+            Ok(actual) if actual == expected => (),
+            Ok(actual) => problems.push(format!("native code:\n{}", diff(&expected, &actual))),
+            Err(e) => problems.push(format!("native code: {e}")),
+        }
+    }
+
+    if let Ok(formatted) = formatter::format(source) {
+        match formatter::format(&formatted) {
+            Ok(again) if again == formatted => (),
+            Ok(again) => problems.push(format!(
+                "formatting the formatted script changes it:\n{}",
+                diff(&lines(&formatted), &lines(&again))
+            )),
+            Err(e) => problems.push(format!("the formatted script doesn't parse: {e}")),
+        }
+    }
+    problems
+}
+
+/// what the script should do, in the order it happens: the lines it
+/// prints, then the errors it stops with
+fn expectations(source: &str) -> Vec<String> {
+    let mut output = vec![];
+    let mut errors = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        if let Some((_, text)) = line.split_once(EXPECT) {
+            output.push(text.to_string());
+        } else if let Some((_, message)) = line.split_once(EXPECT_RUNTIME_ERROR) {
+            errors.push(format!("runtime error on line {line_no}: {message}"));
+        } else if let Some((_, message)) = line.split_once(EXPECT_ERROR) {
+            errors.push(format!("error on line {line_no}: {message}"));
+        }
+    }
+    output.extend(errors);
+    output
+}
+
+/// the errors compiling the script to native code should stop with, in
+/// the form of [`expectations`]
+fn build_errors(source: &str) -> Vec<String> {
+    let mut errors = vec![];
+    for (i, line) in source.lines().enumerate() {
+        if let Some((_, message)) = line.split_once(EXPECT_BUILD_ERROR) {
+            errors.push(format!("error on line {}: {message}", i + 1));
+        }
+    }
+    errors
+}
+
+/// what the script did, in the form of [`expectations`]
+fn run(source: &str, kind: BackendKind) -> Vec<String> {
+    let output = Capture::default();
+    let mut engine = Engine::with_backend(kind);
+    engine.set_output(output.clone());
+    engine.set_input(std::io::empty());
+    engine.set("temp_dir", env!("CARGO_TARGET_TMPDIR"));
+    let result = engine.eval(source);
+
+    let mut actual = lines(&output.text());
+    if let Err(e) = result {
+        let what = match e {
+            RunError::Runtime(_) => "runtime error",
+            _ => "error",
+        };
+        for diagnostic in e.diagnostics() {
+            actual.push(format!(
+                "{what} on line {}: {}",
+                diagnostic.start().line() + 1,
+                diagnostic.message()
+            ));
+        }
+    }
+    actual
+}
+
+/// what the script did when compiled to an executable, which prints its
+/// runtime errors in the form of [`expectations`], or the errors
+/// compiling it
+fn run_native(name: &str, source: &str) -> Result<Vec<String>, String> {
+    let module = match native::compile(source) {
+        Ok(module) => module,
+        Err(e) => {
+            return Ok(e
+                .diagnostics()
+                .iter()
+                .map(|d| format!("error on line {}: {}", d.start().line() + 1, d.message()))
+                .collect())
+        }
+    };
+    let exe = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name.replace(['/', '.'], "_"));
+    native::link(&native::assemble(&module), &exe).map_err(|e| e.to_string())?;
+    let output = Command::new(&exe)
+        .output()
+        .map_err(|e| format!("unable to run the executable: {e}"))?;
+
+    let mut actual = lines(&String::from_utf8_lossy(&output.stdout));
+    actual.extend(lines(&String::from_utf8_lossy(&output.stderr)));
+    Ok(actual)
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+/// the expected and actual lines side by side, marking the lines that
+/// differ with `-` and `+`
+fn diff(expected: &[String], actual: &[String]) -> String {
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push_str(&format!("  {e}\n")),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push_str(&format!("- {e}\n"));
+                }
+                if let Some(a) = a {
+                    out.push_str(&format!("+ {a}\n"));
+                }
+            }
+        }
+    }
+    out
+}
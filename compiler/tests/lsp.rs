@@ -0,0 +1,216 @@
+//! drives the language server with framed messages and checks what it
+//! answers
+
+use std::io;
+
+use compiler::lsp::Server;
+use serde_json::{json, Value};
+
+const URI: &str = "file:///test.lox";
+
+const SOURCE: &str = "\
+/// adds one
+fun inc(x) {
+    return x + 1;
+}
+class Counter {
+    count() {
+        return inc(0);
+    }
+}
+var y = inc(2);
+print y;
+";
+
+fn frame(message: &Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn request(id: u64, method: &str, params: Value) -> String {
+    frame(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+}
+
+fn notification(method: &str, params: Value) -> String {
+    frame(&json!({"jsonrpc": "2.0", "method": method, "params": params}))
+}
+
+fn open(text: &str) -> String {
+    notification(
+        "textDocument/didOpen",
+        json!({"textDocument": {"uri": URI, "languageId": "lox", "version": 1, "text": text}}),
+    )
+}
+
+/// a request about the character at `line` and `character` of the test
+/// document
+fn at(id: u64, method: &str, line: u64, character: u64) -> String {
+    request(
+        id,
+        method,
+        json!({
+            "textDocument": {"uri": URI},
+            "position": {"line": line, "character": character},
+            "context": {"includeDeclaration": true},
+        }),
+    )
+}
+
+/// serves `input` to a new server, returns the messages it wrote
+fn serve(input: &str) -> Vec<Value> {
+    let mut output = vec![];
+    Server::new()
+        .serve(io::Cursor::new(input.to_string()), &mut output)
+        .unwrap();
+
+    let mut output = String::from_utf8(output).unwrap();
+    let mut messages = vec![];
+    while let Some((header, rest)) = output.split_once("\r\n\r\n") {
+        let length: usize = header
+            .strip_prefix("Content-Length: ")
+            .unwrap()
+            .parse()
+            .unwrap();
+        messages.push(serde_json::from_str(&rest[..length]).unwrap());
+        output = rest[length..].to_string();
+    }
+    assert!(output.is_empty(), "unframed output: {output}");
+    messages
+}
+
+/// the result of the request with `id`
+fn result(messages: &[Value], id: u64) -> &Value {
+    let response = messages
+        .iter()
+        .find(|message| message["id"] == id)
+        .unwrap_or_else(|| panic!("no response to {id} in {messages:?}"));
+    &response["result"]
+}
+
+fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+    json!({
+        "start": {"line": start.0, "character": start.1},
+        "end": {"line": end.0, "character": end.1},
+    })
+}
+
+#[test]
+fn opening_a_document_publishes_its_diagnostics() {
+    let messages = serve(&open("print nope;\nvar x = ;\n"));
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["method"], "textDocument/publishDiagnostics");
+    let diagnostics = messages[0]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"], range((1, 8), (1, 9)));
+    assert!(diagnostics[0]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("unexpected ';': expected an expression"));
+}
+
+#[test]
+fn a_valid_document_has_no_diagnostics() {
+    let messages = serve(&open(SOURCE));
+    assert_eq!(messages[0]["params"]["diagnostics"], json!([]));
+}
+
+#[test]
+fn definition_points_at_the_declaration() {
+    let messages = serve(&(open(SOURCE) + &at(1, "textDocument/definition", 9, 8)));
+    assert_eq!(
+        result(&messages, 1),
+        &json!({"uri": URI, "range": range((1, 4), (1, 7))})
+    );
+}
+
+#[test]
+fn references_include_every_use() {
+    let messages = serve(&(open(SOURCE) + &at(1, "textDocument/references", 1, 5)));
+    let lines: Vec<_> = result(&messages, 1)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|location| location["range"]["start"]["line"].clone())
+        .collect();
+    assert_eq!(lines, [json!(1), json!(6), json!(9)]);
+}
+
+#[test]
+fn hover_shows_the_signature_and_docs() {
+    let messages = serve(&(open(SOURCE) + &at(1, "textDocument/hover", 9, 9)));
+    let hover = result(&messages, 1);
+    assert_eq!(hover["range"], range((9, 8), (9, 11)));
+    assert_eq!(
+        hover["contents"]["value"],
+        "```lox\nfun inc(x)\n```\n\nadds one"
+    );
+}
+
+#[test]
+fn hovering_nothing_is_null() {
+    let messages = serve(&(open(SOURCE) + &at(1, "textDocument/hover", 10, 0)));
+    assert_eq!(result(&messages, 1), &Value::Null);
+}
+
+#[test]
+fn document_symbols_are_an_outline() {
+    let symbols = request(
+        1,
+        "textDocument/documentSymbol",
+        json!({"textDocument": {"uri": URI}}),
+    );
+    let messages = serve(&(open(SOURCE) + &symbols));
+    let outline = result(&messages, 1).as_array().unwrap();
+    let names: Vec<_> = outline.iter().map(|symbol| &symbol["name"]).collect();
+    assert_eq!(names, ["inc", "Counter", "y"]);
+    assert_eq!(outline[1]["children"][0]["name"], "count");
+    assert_eq!(outline[1]["children"][0]["kind"], 6);
+}
+
+#[test]
+fn completion_offers_keywords_natives_and_visible_names() {
+    let messages = serve(&(open(SOURCE) + &at(1, "textDocument/completion", 2, 11)));
+    let labels: Vec<_> = result(&messages, 1)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap().to_string())
+        .collect();
+    for label in ["while", "clock", "inc", "x", "Counter"] {
+        assert!(labels.iter().any(|l| l == label), "{label} in {labels:?}");
+    }
+}
+
+#[test]
+fn completion_after_a_dot_offers_methods() {
+    let source = "class A { count() {} }\nA().\n";
+    let messages = serve(&(open(source) + &at(1, "textDocument/completion", 1, 4)));
+    assert_eq!(
+        result(&messages, 1),
+        &json!([{"label": "count", "kind": 2, "detail": "A.count()"}])
+    );
+}
+
+#[test]
+fn a_message_without_a_length_is_skipped() {
+    let body = json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}).to_string();
+    let input = format!("Content-Type: x\r\n\r\n{body}") + &request(2, "shutdown", json!(null));
+    let messages = serve(&input);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["error"]["code"], -32700);
+    assert_eq!(messages[1]["id"], 2);
+}
+
+#[test]
+fn a_message_with_an_invalid_length_is_skipped() {
+    let body = json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}).to_string();
+    let input =
+        format!("content-length: many\r\n\r\n{body}") + &request(2, "shutdown", json!(null));
+    let messages = serve(&input);
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0]["error"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("invalid Content-Length 'many'"));
+    assert_eq!(messages[1]["id"], 2);
+}
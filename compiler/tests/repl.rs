@@ -0,0 +1,134 @@
+//! drives repl sessions with typed lines and checks what they print
+
+mod common;
+
+use std::{io, path::Path};
+
+use common::Capture;
+use compiler::{repl::Repl, BackendKind};
+
+/// runs a session on each backend with `input` typed into it, returning
+/// what it printed and the errors it reported
+fn session(input: &str) -> (String, String) {
+    let mut results = [BackendKind::Tree, BackendKind::Vm].map(|kind| {
+        let (out, err) = (Capture::default(), Capture::default());
+        let input = io::Cursor::new(input.to_string());
+        Repl::with_io(kind, input, out.clone(), err.clone())
+            .run()
+            .unwrap();
+        (out.text(), err.text())
+    });
+    assert_eq!(results[0], results[1], "the backends differ");
+    std::mem::take(&mut results[0])
+}
+
+#[test]
+fn bare_expressions_are_printed() {
+    let (out, err) = session("1 + 2\nvar a = 1;\na = 5\na\n\"a\" + \"b\";\n");
+    assert_eq!(out, "3\n5\nab\n");
+    assert_eq!(err, "");
+}
+
+#[test]
+fn only_lone_expressions_are_printed() {
+    let (out, _) = session("1; 2;\nprint 3;\n");
+    assert_eq!(out, "3\n");
+}
+
+#[test]
+fn unfinished_input_continues_on_the_next_line() {
+    let input = "\
+fun add(a, b) {
+    return a + b;
+}
+add(1,
+    2)
+[1,
+ 2]
+\"two
+lines\"
+1 /* a
+comment */ + 1
+";
+    let (out, err) = session(input);
+    assert_eq!(out, "3\n[1, 2]\ntwo\nlines\n2\n");
+    assert_eq!(err, "");
+}
+
+#[test]
+fn errors_are_reported_and_the_session_goes_on() {
+    let (out, err) = session("print nope;\n1 +\n;\nprint 1;\n");
+    assert_eq!(out, "1\n");
+    assert!(err.contains("variable 'nope' does not exist"), "{err}");
+    assert!(err.contains("expected an expression"), "{err}");
+}
+
+#[test]
+fn env_lists_the_variables_defined_in_the_session() {
+    let (out, _) = session("var b = [1];\nvar a = \"x\";\nfun f() {}\n:env\n");
+    assert_eq!(out, "a = x\nb = [1]\nf = <fn f>\n");
+}
+
+#[test]
+fn ast_prints_the_syntax_tree_without_running_it() {
+    let (out, _) = session(":ast print 1\n:env\n");
+    assert!(out.starts_with("Print(\n"), "{out}");
+    assert!(out.contains("Integer(\n"), "{out}");
+}
+
+#[test]
+fn ast_reports_errors_in_the_code_it_parsed() {
+    let (out, err) = session(":ast 1 +\n");
+    assert_eq!(out, "");
+    assert!(err.contains("2 | ;\n  | ^"), "{err}");
+}
+
+#[test]
+fn tokens_prints_each_token_with_its_location() {
+    let (out, _) = session(":tokens var x = 1;\n");
+    assert_eq!(
+        out,
+        "\
+1:1     Var
+1:5     Identifier(\"x\")
+1:7     Equal
+1:9     Integer(1)
+1:10    Semicolon
+1:10    Eof
+"
+    );
+}
+
+#[test]
+fn load_runs_a_file_in_the_session() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("repl_load.lox");
+    std::fs::write(&path, "var loaded = 2;\nprint \"loading\";\n").unwrap();
+    let (out, err) = session(&format!(":load {}\nloaded * 2\n:load\n", path.display()));
+    assert_eq!(out, "loading\n4\n");
+    assert_eq!(err, "error: usage is ':load <file>'\n");
+
+    let (_, err) = session(":load does/not/exist.lox\n");
+    assert!(
+        err.starts_with("error: unable to read 'does/not/exist.lox'"),
+        "{err}"
+    );
+}
+
+#[test]
+fn reset_forgets_all_variables() {
+    let (out, err) = session("var a = 1;\n:reset\n:env\na\n");
+    assert_eq!(out, "");
+    assert!(err.contains("variable 'a' does not exist"), "{err}");
+}
+
+#[test]
+fn quit_ends_the_session() {
+    let (out, _) = session("1\n:quit\n2\n");
+    assert_eq!(out, "1\n");
+}
+
+#[test]
+fn unknown_commands_are_errors() {
+    let (_, err) = session(":frobnicate\n");
+    assert_eq!(err, "error: unknown command ':frobnicate'. see ':help'\n");
+}